@@ -0,0 +1,48 @@
+//! Arch-neutral boot-time memory description.
+//!
+//! `_start` used to take a `&'static bootloader::bootinfo::BootInfo` and pass
+//! it straight through to x86's `memory::init`, which made the whole KSEG2
+//! `MemorySpaceManager` layer (`VirtualSpace`, `VirtualArea`, the buddy
+//! manager) depend on the x86 bootloader shape even though none of it
+//! actually cares how the memory map was obtained. Each arch now normalizes
+//! whatever boot record it gets handed - a `BootInfo` memory map on x86_64,
+//! a flattened device tree on riscv64-virt - into `BootParams` before
+//! calling the shared init path.
+
+use alloc::vec::Vec;
+
+/// One contiguous, page-granular region of physical memory, normalized away
+/// from whatever arch-specific shape it arrived in.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryRegion {
+    pub start_frame: usize,
+    pub end_frame: usize,
+    pub usable: bool,
+}
+
+/// Everything the shared memory-init path needs out of a boot record.
+#[derive(Debug, Clone)]
+pub struct BootParams {
+    pub memory_map: Vec<MemoryRegion>,
+    /// Offset added to a physical address to reach its kernel-space mapping.
+    pub physical_memory_offset: usize,
+    /// A cpio ("newc") initramfs image the bootloader placed in memory
+    /// alongside the kernel, already mapped and readable, if the boot
+    /// protocol supports handing one over (currently only Limine modules).
+    /// `fs::VIRTUAL_FS` mounts this as the root filesystem instead of
+    /// opening the SFS block device when present.
+    pub initramfs: Option<&'static [u8]>,
+}
+
+/// Implemented once per arch by whatever boot record that arch's firmware or
+/// loader hands the kernel entry point.
+pub trait ArchBoot {
+    fn boot_params(&self) -> BootParams;
+}
+
+/// Arch hook for bringing up another hart/CPU at `entry`: x86 just lets an
+/// already-parked AP past its spin-wait, riscv64-virt calls into SBI's HSM
+/// extension to actually start the hart.
+pub trait ApBringup {
+    fn start_other_cpu(hart_id: usize, entry: usize);
+}
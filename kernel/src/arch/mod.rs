@@ -0,0 +1,16 @@
+//! Per-arch kernel entry points, gathered behind one `cfg`-selected module so
+//! the rest of the kernel (and the KSEG2 `MemorySpaceManager` layer in
+//! particular) can go through [`boot::ArchBoot`]/[`boot::ApBringup`] instead
+//! of reaching into a specific arch's boot record.
+
+pub mod boot;
+
+#[cfg(target_arch = "x86_64")]
+pub mod x86_64;
+#[cfg(target_arch = "x86_64")]
+pub use self::x86_64::*;
+
+#[cfg(target_arch = "riscv64")]
+pub mod riscv64;
+#[cfg(target_arch = "riscv64")]
+pub use self::riscv64::*;
@@ -0,0 +1,134 @@
+use crate::arch::boot::{BootParams, MemoryRegion};
+use crate::consts::KSEG2_START;
+use crate::memory::{active_table, init_heap, FRAME_ALLOCATOR};
+use alloc::vec::Vec;
+use log::*;
+use rcore_memory::paging::*;
+use rcore_memory::PAGE_SIZE;
+
+pub fn init(boot_params: &BootParams) {
+    init_frame_allocator(boot_params);
+    init_kernel_kseg2_map();
+    init_heap();
+}
+
+/// Init FrameAllocator and insert all usable regions from the normalized
+/// boot-time memory map (mirrors `x86_64::memory::init_frame_allocator`).
+fn init_frame_allocator(boot_params: &BootParams) {
+    let mut ba = FRAME_ALLOCATOR.lock();
+    for region in boot_params.memory_map.iter() {
+        if region.usable {
+            ba.insert(region.start_frame..region.end_frame);
+        }
+    }
+}
+
+fn init_kernel_kseg2_map() {
+    // Same dirty hack as x86_64: touch one entry in KSEG2's range so the
+    // covering second-level page table gets allocated once, up front, and
+    // can then be shared by every page table the kernel creates afterwards.
+    let mut page_table = active_table();
+    page_table.map(KSEG2_START, 0x0).update();
+    page_table.unmap(KSEG2_START);
+}
+
+/// Minimal flattened-device-tree reader: just enough to pull the `reg`
+/// property (a list of big-endian `(base, size)` pairs, `#address-cells` =
+/// `#size-cells` = 2 on riscv64-virt) out of the top-level `memory` node.
+/// A real deployment would reach for a full `device_tree`/`fdt` crate to
+/// also honor `reserved-memory` and non-default cell sizes; this covers the
+/// one thing `BootParams` actually needs.
+pub fn parse_fdt_memory(fdt_addr: usize) -> BootParams {
+    const FDT_MAGIC: u32 = 0xd00d_feed;
+    const FDT_BEGIN_NODE: u32 = 1;
+    const FDT_END_NODE: u32 = 2;
+    const FDT_PROP: u32 = 3;
+    const FDT_NOP: u32 = 4;
+    const FDT_END: u32 = 9;
+
+    unsafe fn read_be32(addr: usize) -> u32 {
+        u32::from_be((addr as *const u32).read_unaligned())
+    }
+    unsafe fn read_be64(addr: usize) -> u64 {
+        u64::from_be((addr as *const u64).read_unaligned())
+    }
+    fn align4(x: usize) -> usize {
+        (x + 3) & !3
+    }
+
+    let mut regions = Vec::new();
+    unsafe {
+        if read_be32(fdt_addr) != FDT_MAGIC {
+            error!("[FDT] bad magic at {:#x}, no memory map available", fdt_addr);
+            return BootParams {
+                memory_map: regions,
+                physical_memory_offset: crate::consts::KERNEL_OFFSET,
+                // We bailed out before reaching the `chosen` node, so we
+                // never got a chance to look for an initrd reg even if one
+                // is present.
+                initramfs: None,
+            };
+        }
+        let off_dt_struct = read_be32(fdt_addr + 8) as usize;
+        let mut cursor = fdt_addr + off_dt_struct;
+        let mut in_memory_node = 0i32; // depth of the `memory@...` node we're inside, 0 = not inside one
+        let mut depth = 0i32;
+        loop {
+            let token = read_be32(cursor);
+            cursor += 4;
+            match token {
+                t if t == FDT_BEGIN_NODE => {
+                    let name_start = cursor;
+                    let mut len = 0;
+                    while *((name_start + len) as *const u8) != 0 {
+                        len += 1;
+                    }
+                    let name = core::slice::from_raw_parts(name_start as *const u8, len);
+                    depth += 1;
+                    if in_memory_node == 0 && name.starts_with(b"memory") {
+                        in_memory_node = depth;
+                    }
+                    cursor = align4(name_start + len + 1);
+                }
+                t if t == FDT_END_NODE => {
+                    if in_memory_node == depth {
+                        in_memory_node = 0;
+                    }
+                    depth -= 1;
+                }
+                t if t == FDT_PROP => {
+                    let prop_len = read_be32(cursor) as usize;
+                    let data = cursor + 8; // skip len + nameoff
+                    if in_memory_node != 0 {
+                        let mut off = 0;
+                        while off + 16 <= prop_len {
+                            let base = read_be64(data + off) as usize;
+                            let size = read_be64(data + off + 8) as usize;
+                            regions.push(MemoryRegion {
+                                start_frame: base / PAGE_SIZE,
+                                end_frame: (base + size) / PAGE_SIZE,
+                                usable: true,
+                            });
+                            off += 16;
+                        }
+                    }
+                    cursor = align4(data + prop_len);
+                }
+                t if t == FDT_NOP => {}
+                t if t == FDT_END => break,
+                other => {
+                    error!("[FDT] unexpected token {:#x}, stopping early", other);
+                    break;
+                }
+            }
+        }
+    }
+    BootParams {
+        memory_map: regions,
+        physical_memory_offset: crate::consts::KERNEL_OFFSET,
+        // TODO: read `/chosen`'s `linux,initrd-start`/`linux,initrd-end` to
+        // pick up an initramfs handed over via the FDT, same as `limine`
+        // does through its module request.
+        initramfs: None,
+    }
+}
@@ -0,0 +1,78 @@
+//! riscv64-virt: boots through SBI firmware (OpenSBI/rustsbi) rather than a
+//! PC-style bootloader. The only two things `_start` gets from firmware are
+//! the hart id in `a0` and a pointer to a flattened device tree in `a1`;
+//! everything else (memory map, console, timer, bringing up the other
+//! harts) goes through the SBI calls in the `sbi` crate instead of ACPI/APIC.
+
+use core::sync::atomic::*;
+use log::*;
+use sbi::*;
+
+use crate::arch::boot::{ApBringup, ArchBoot, BootParams};
+
+pub mod memory;
+
+static AP_CAN_INIT: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Boot record riscv64-virt actually has: the FDT blob SBI firmware left in
+/// `a1`. Implements both arch-neutral traits, `ArchBoot` and `ApBringup`.
+pub struct Riscv64Boot {
+    fdt: usize,
+}
+
+impl ArchBoot for Riscv64Boot {
+    fn boot_params(&self) -> BootParams {
+        memory::parse_fdt_memory(self.fdt)
+    }
+}
+
+impl ApBringup for Riscv64Boot {
+    fn start_other_cpu(hart_id: usize, entry: usize) {
+        // Unlike x86 (no bring-up primitive short of ACPI/APIC, so APs just
+        // spin-wait on a flag `_start` sets), SBI's Hart State Management
+        // extension can actually start a parked hart at an arbitrary entry
+        // point, so other harts don't need to be parked in firmware first.
+        match hart_state_management::hart_start(hart_id, entry, 0) {
+            Ok(()) => {}
+            Err(err) => error!("[SBI] failed to start hart {}: {:?}", hart_id, err),
+        }
+    }
+}
+
+/// The entry point of kernel, reached from the riscv64 boot assembly with
+/// `a0` = hart id, `a1` = FDT pointer, per the SBI boot convention.
+#[no_mangle]
+pub extern "C" fn _start(hartid: usize, fdt: usize) -> ! {
+    println!("Hello world! from hart {}!", hartid);
+
+    if hartid != 0 {
+        while !AP_CAN_INIT.load(Ordering::Relaxed) {}
+        other_start();
+    }
+
+    crate::logging::init();
+
+    let boot = Riscv64Boot { fdt };
+    let boot_params = boot.boot_params();
+    info!("{:#?}", boot_params);
+
+    memory::init(&boot_params);
+
+    crate::drivers::init();
+    crate::rcore_fs::init();
+    crate::process::init();
+
+    crate::lkm::manager::ModuleManager::init();
+    crate::lkm::cdev::CDevManager::init();
+
+    AP_CAN_INIT.store(true, Ordering::Relaxed);
+
+    crate::kmain();
+}
+
+/// The entry point for other harts: `_start` sends every hart but 0 here
+/// once hart 0 sets `AP_CAN_INIT`. Nothing on this path calls
+/// `Riscv64Boot::start_other_cpu`, so no hart is started through SBI HSM.
+fn other_start() -> ! {
+    crate::kmain();
+}
@@ -0,0 +1,51 @@
+//! Local APIC, read/written through `arch::io::Mmio` instead of the raw
+//! pointer casts the rest of this tree still uses for device registers.
+//!
+//! Only as much of the LAPIC as `arch::ipi` needs to broadcast a shootdown
+//! IPI lives here: enabling the APIC and sending "all excluding self" via
+//! the ICR. No LVT/timer/EOI handling yet.
+pub mod lapic {
+    use crate::arch::io::Mmio;
+
+    /// `init_device_vm_map` maps this range `KERNEL_OFFSET`-relative and
+    /// non-cacheable; `Mmio::from_phys` reaches it the same way `Dma` does.
+    const LAPIC_PHYS_BASE: usize = 0xfee0_0000;
+
+    const REG_ID: usize = 0x020;
+    const REG_SVR: usize = 0x0f0;
+    const REG_ICR_LOW: usize = 0x300;
+    const REG_ICR_HIGH: usize = 0x310;
+
+    /// Spurious-interrupt vector register's APIC-enable bit.
+    const SVR_APIC_ENABLE: u32 = 1 << 8;
+    /// ICR: set while a previously-written IPI hasn't been accepted yet.
+    const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+    /// ICR destination shorthand: every CPU but the sender.
+    const ICR_DEST_ALL_BUT_SELF: u32 = 0b11 << 18;
+
+    fn reg(offset: usize) -> &'static mut Mmio<u32> {
+        unsafe { Mmio::from_phys(LAPIC_PHYS_BASE + offset) }
+    }
+
+    /// Enable the LAPIC. Called once per CPU during `driver::init`.
+    pub fn init() {
+        let svr = reg(REG_SVR).read();
+        reg(REG_SVR).write(svr | SVR_APIC_ENABLE);
+    }
+
+    /// This CPU's APIC ID, as `arch::cpu::id()` is backed by.
+    pub fn id() -> u32 {
+        reg(REG_ID).read() >> 24
+    }
+
+    /// Send `vector` to every online CPU except the caller, via the ICR's
+    /// "all excluding self" destination shorthand. Used by
+    /// `arch::ipi::invoke_on_allcpu` to broadcast a TLB shootdown.
+    pub fn send_ipi_all_but_self(vector: u8) {
+        while reg(REG_ICR_LOW).readf(ICR_DELIVERY_PENDING) {
+            core::hint::spin_loop();
+        }
+        reg(REG_ICR_HIGH).write(0);
+        reg(REG_ICR_LOW).write(vector as u32 | ICR_DEST_ALL_BUT_SELF);
+    }
+}
@@ -0,0 +1,10 @@
+//! Arch-level device drivers, as opposed to the higher-level `crate::drivers`
+//! that sit behind `FileOperations`/block device traits.
+
+pub mod apic;
+
+/// Per-CPU arch driver bring-up, called once from `kernel_main` before
+/// `crate::drivers::init()`.
+pub fn init() {
+    apic::lapic::init();
+}
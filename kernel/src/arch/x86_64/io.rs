@@ -0,0 +1,239 @@
+//! Typed MMIO/PIO register access and DMA-coherent buffer allocation.
+//!
+//! `arch::x86_64::memory::init_device_vm_map` used to hand-map the IOAPIC and
+//! LocalAPIC and device code read registers back through raw `*const usize`
+//! casts - no type checking, no volatile guarantee, easy to get the
+//! endianness or width wrong. `Mmio<T>`/`Pio<T>` wrap a mapped address/port
+//! behind a typed, volatile `read`/`write` (plus `readf`/`writef` for
+//! testing and setting a single bit-flag), so a device's register block can
+//! just be described as a plain `#[repr(C)]` struct of `Mmio<u32>` fields.
+//! `Dma<T>` hands out the matching buffer side: a physically-contiguous
+//! frame range, mapped non-cacheable at its `KERNEL_OFFSET`-relative address,
+//! for handing the physical address to a DMA-capable device while the driver
+//! reads/writes it through the virtual one.
+
+use core::marker::PhantomData;
+use core::ops::{BitAnd, BitOr, Not};
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::consts::KERNEL_OFFSET;
+use crate::memory::{active_table, FRAME_ALLOCATOR};
+use rcore_memory::paging::PageTable;
+use rcore_memory::PAGE_SIZE;
+
+/// A scalar register width `Mmio`/`Pio` can test/set a single bit-flag of.
+pub trait Bits: Copy + PartialEq + BitAnd<Output = Self> + BitOr<Output = Self> + Not<Output = Self> {
+    fn zero() -> Self;
+}
+
+macro_rules! impl_bits {
+    ($($t:ty),*) => {
+        $(impl Bits for $t {
+            fn zero() -> Self { 0 }
+        })*
+    };
+}
+impl_bits!(u8, u16, u32, u64, usize);
+
+/// A typed view over a register (or block of registers) at an already-mapped
+/// MMIO address. `#[repr(transparent)]` so a device's register block can be
+/// described as a plain struct of `Mmio<u32>` fields and overlaid directly
+/// onto the mapped address with [`Mmio::from_vaddr`]/[`Mmio::from_phys`].
+#[repr(transparent)]
+pub struct Mmio<T> {
+    value: T,
+}
+
+impl<T> Mmio<T> {
+    /// View the already-mapped `vaddr` as `&'static mut Self`.
+    ///
+    /// # Safety
+    /// `vaddr` must be mapped, live for `'static`, and not aliased by any
+    /// other non-volatile access.
+    pub unsafe fn from_vaddr(vaddr: usize) -> &'static mut Self {
+        &mut *(vaddr as *mut Self)
+    }
+
+    /// View the physical address `phys`, mapped at its `KERNEL_OFFSET`
+    /// direct-map address, as `&'static mut Self`.
+    ///
+    /// # Safety
+    /// Same as [`Mmio::from_vaddr`]; `phys` must actually be mapped at its
+    /// direct-map address (`init_device_vm_map` maps only the IOAPIC and
+    /// local APIC pages there).
+    pub unsafe fn from_phys(phys: usize) -> &'static mut Self {
+        Self::from_vaddr(KERNEL_OFFSET + phys)
+    }
+}
+
+impl<T: Copy> Mmio<T> {
+    pub fn read(&self) -> T {
+        unsafe { read_volatile(&self.value) }
+    }
+
+    pub fn write(&mut self, value: T) {
+        unsafe { write_volatile(&mut self.value, value) }
+    }
+}
+
+impl<T: Bits> Mmio<T> {
+    /// Test whether every bit of `flag` is set.
+    pub fn readf(&self, flag: T) -> bool {
+        self.read() & flag == flag
+    }
+
+    /// Set or clear every bit of `flag`, leaving the rest of the register
+    /// untouched.
+    pub fn writef(&mut self, flag: T, set: bool) {
+        let value = self.read();
+        self.write(if set { value | flag } else { value & !flag });
+    }
+}
+
+/// A single in/out-capable x86 I/O port, typed by the width it's read and
+/// written at (`u8`/`u16`/`u32`).
+pub struct Pio<T> {
+    port: u16,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Pio<T> {
+    pub const fn new(port: u16) -> Self {
+        Pio {
+            port,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// Width-specific `in`/`out` instruction pair a `Pio<T>` dispatches to.
+pub trait PortOps: Copy {
+    unsafe fn port_read(port: u16) -> Self;
+    unsafe fn port_write(port: u16, value: Self);
+}
+
+impl PortOps for u8 {
+    unsafe fn port_read(port: u16) -> u8 {
+        let value: u8;
+        asm!("inb %dx, %al" : "={al}"(value) : "{dx}"(port) :: "volatile");
+        value
+    }
+    unsafe fn port_write(port: u16, value: u8) {
+        asm!("outb %al, %dx" :: "{dx}"(port), "{al}"(value) :: "volatile");
+    }
+}
+
+impl PortOps for u16 {
+    unsafe fn port_read(port: u16) -> u16 {
+        let value: u16;
+        asm!("inw %dx, %ax" : "={ax}"(value) : "{dx}"(port) :: "volatile");
+        value
+    }
+    unsafe fn port_write(port: u16, value: u16) {
+        asm!("outw %ax, %dx" :: "{dx}"(port), "{ax}"(value) :: "volatile");
+    }
+}
+
+impl PortOps for u32 {
+    unsafe fn port_read(port: u16) -> u32 {
+        let value: u32;
+        asm!("inl %dx, %eax" : "={eax}"(value) : "{dx}"(port) :: "volatile");
+        value
+    }
+    unsafe fn port_write(port: u16, value: u32) {
+        asm!("outl %eax, %dx" :: "{dx}"(port), "{eax}"(value) :: "volatile");
+    }
+}
+
+impl<T: PortOps> Pio<T> {
+    pub fn read(&self) -> T {
+        unsafe { T::port_read(self.port) }
+    }
+
+    pub fn write(&mut self, value: T) {
+        unsafe { T::port_write(self.port, value) }
+    }
+}
+
+impl<T: PortOps + Bits> Pio<T> {
+    pub fn readf(&self, flag: T) -> bool {
+        self.read() & flag == flag
+    }
+
+    pub fn writef(&mut self, flag: T, set: bool) {
+        let value = self.read();
+        self.write(if set { value | flag } else { value & !flag });
+    }
+}
+
+/// A physically-contiguous, non-cacheable buffer sized for `T`, suitable for
+/// handing to a DMA-capable device: [`Dma::paddr`] is what the device is
+/// told, [`Dma::as_mut`]/[`Dma::as_ref`] is how the driver reads and writes
+/// the same memory.
+pub struct Dma<T> {
+    vaddr: usize,
+    paddr: usize,
+    pages: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Dma<T> {
+    /// Allocate and map a buffer big enough for one `T`.
+    pub fn new() -> Self {
+        let pages = ((core::mem::size_of::<T>() + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+        let start_frame = FRAME_ALLOCATOR
+            .lock()
+            .alloc_contiguous(pages, 0)
+            .expect("Dma: out of physical memory");
+        let paddr = start_frame * PAGE_SIZE;
+        let vaddr = KERNEL_OFFSET + paddr;
+
+        let mut page_table = active_table();
+        for i in 0..pages {
+            let offset = i * PAGE_SIZE;
+            // `set_mmio(1)` marks the mapping non-cacheable, same as a real
+            // MMIO register range, so the device and the CPU agree on what's
+            // in the buffer without needing an explicit cache flush.
+            page_table
+                .map(vaddr + offset, paddr + offset)
+                .set_mmio(1)
+                .update();
+        }
+
+        Dma {
+            vaddr,
+            paddr,
+            pages,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The physical address to hand to the DMA-capable device.
+    pub fn paddr(&self) -> usize {
+        self.paddr
+    }
+
+    pub fn as_ptr(&self) -> *mut T {
+        self.vaddr as *mut T
+    }
+
+    pub fn as_ref(&self) -> &T {
+        unsafe { &*self.as_ptr() }
+    }
+
+    pub fn as_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.as_ptr() }
+    }
+}
+
+impl<T> Drop for Dma<T> {
+    fn drop(&mut self) {
+        let mut page_table = active_table();
+        let mut ba = FRAME_ALLOCATOR.lock();
+        for i in 0..self.pages {
+            let offset = i * PAGE_SIZE;
+            page_table.unmap(self.vaddr + offset);
+            ba.dealloc(self.paddr / PAGE_SIZE + i);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Cross-CPU TLB shootdown.
+//!
+//! The KSEG2 page tables are shared by every CPU, so once `VirtualArea::new`
+//! or `unmap` changes an entry, the change is visible everywhere - but each
+//! core's TLB still caches the old translation until something flushes it.
+//! A bare `invlpg` only flushes the local core, so a newly (re)mapped or
+//! freed KSEG2 range could be served stale on another hart, e.g. one still
+//! spinning on `AP_CAN_INIT` in `kernel_main` before it has even looked at
+//! the range. This broadcasts an IPI asking every other online CPU to flush
+//! `[start, end)` itself and spins until all of them have acknowledged.
+
+use crate::arch::cpu;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use rcore_memory::PAGE_SIZE;
+
+/// The IPI vector `idt::init()` routes to `handle_tlb_shootdown_ipi` on
+/// every core.
+pub const TLB_SHOOTDOWN_VECTOR: u8 = 0xf0;
+
+/// The one shootdown request in flight. `generation` lets a CPU tell "I
+/// already flushed this one" from "a new request just landed for the same
+/// range"; `origin` records who to wake when everyone has acked.
+struct ShootdownRequest {
+    start: AtomicUsize,
+    end: AtomicUsize,
+    origin: AtomicUsize,
+    generation: AtomicUsize,
+}
+
+static REQUEST: ShootdownRequest = ShootdownRequest {
+    start: AtomicUsize::new(0),
+    end: AtomicUsize::new(0),
+    origin: AtomicUsize::new(0),
+    generation: AtomicUsize::new(0),
+};
+
+lazy_static! {
+    /// Per-CPU: the generation number of the last shootdown this CPU has
+    /// flushed locally and acknowledged.
+    static ref ACKED_GENERATION: Vec<AtomicUsize> =
+        (0..cpu::count()).map(|_| AtomicUsize::new(0)).collect();
+}
+
+fn flush_range_locally(start: usize, end: usize) {
+    let mut addr = start & !(PAGE_SIZE - 1);
+    while addr < end {
+        unsafe {
+            asm!("invlpg ($0)" :: "r"(addr) : "memory" : "volatile");
+        }
+        addr += PAGE_SIZE;
+    }
+}
+
+/// Broadcasts `TLB_SHOOTDOWN_VECTOR` to every online CPU but the caller, and
+/// (if `wait`) spins until each one has acknowledged flushing `[start, end)`.
+/// Flushes the caller's own TLB first, same as it always needs to regardless
+/// of whether anyone else is listening.
+pub fn invoke_on_allcpu(f: fn((usize, usize)), range: (usize, usize), wait: bool) {
+    let (start, end) = range;
+    f((start, end));
+
+    let me = cpu::id();
+    let generation = REQUEST.generation.fetch_add(1, Ordering::SeqCst) + 1;
+    REQUEST.start.store(start, Ordering::SeqCst);
+    REQUEST.end.store(end, Ordering::SeqCst);
+    REQUEST.origin.store(me, Ordering::SeqCst);
+
+    send_ipi_allbutself(TLB_SHOOTDOWN_VECTOR);
+
+    if wait {
+        for target in 0..cpu::count() {
+            if target == me {
+                continue;
+            }
+            while ACKED_GENERATION[target].load(Ordering::SeqCst) < generation {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+/// Convenience wrapper used by `VirtualArea::new`/`unmap`: flushes and
+/// shoots down `[start, end)`, waiting for every online CPU to ack.
+pub fn tlb_shootdown(range: (usize, usize)) {
+    invoke_on_allcpu(flush_range_locally, range, true);
+}
+
+/// Runs on every CPU that receives `TLB_SHOOTDOWN_VECTOR`: flush the
+/// requested range locally, then ack by publishing the generation we just
+/// handled so the initiator's wait loop can see it.
+pub fn handle_tlb_shootdown_ipi() {
+    let start = REQUEST.start.load(Ordering::SeqCst);
+    let end = REQUEST.end.load(Ordering::SeqCst);
+    let generation = REQUEST.generation.load(Ordering::SeqCst);
+    flush_range_locally(start, end);
+    ACKED_GENERATION[cpu::id()].store(generation, Ordering::SeqCst);
+}
+
+/// Sends `vector` to every online CPU except the caller, through the local
+/// APIC's all-excluding-self IPI in `driver::apic::lapic`; the receiving
+/// half is `handle_tlb_shootdown_ipi`, run for `TLB_SHOOTDOWN_VECTOR`.
+fn send_ipi_allbutself(vector: u8) {
+    crate::arch::driver::apic::lapic::send_ipi_all_but_self(vector);
+}
@@ -0,0 +1,193 @@
+//! Booting under the [Limine boot protocol](https://github.com/limine-bootloader/limine)
+//! instead of the `bootloader` 0.9 crate.
+//!
+//! Limine hands the kernel a linked list of typed "responses" instead of one
+//! big struct: we only care about the memory map (to seed the frame
+//! allocator), the HHDM (higher-half direct map) response, which tells us
+//! the fixed offset added to a physical address to get a mapping that's
+//! already present with no temporary mapping required, and the module
+//! response (to find an initramfs). Each is requested by placing a request
+//! struct in the `.limine_reqs` section, which the bootloader scans for
+//! before jumping to `_start`.
+//!
+//! Gated behind the `limine` feature: with it off, `x86_64::mod` still boots
+//! through the `bootloader` crate's `BootInfo` as before.
+
+use crate::arch::boot::{ArchBoot, BootParams, MemoryRegion};
+use rcore_memory::PAGE_SIZE;
+
+const LIMINE_COMMON_MAGIC: [u64; 2] = [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b];
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimineMemmapType {
+    Usable = 0,
+    Reserved = 1,
+    AcpiReclaimable = 2,
+    AcpiNvs = 3,
+    BadMemory = 4,
+    BootloaderReclaimable = 5,
+    KernelAndModules = 6,
+    Framebuffer = 7,
+}
+
+#[repr(C)]
+pub struct LimineMemmapEntry {
+    pub base: u64,
+    pub length: u64,
+    pub ty: LimineMemmapType,
+    _unused: u32,
+}
+
+#[repr(C)]
+pub struct LimineMemmapResponse {
+    pub revision: u64,
+    pub entry_count: u64,
+    pub entries: *const *const LimineMemmapEntry,
+}
+
+#[repr(C)]
+pub struct LimineMemmapRequest {
+    id: [u64; 4],
+    revision: u64,
+    pub response: *const LimineMemmapResponse,
+}
+
+#[repr(C)]
+pub struct LimineHhdmResponse {
+    pub revision: u64,
+    /// Offset added to a physical address to reach its identity-ish mapping
+    /// in the direct map window, e.g. the `0xffff_8000_0000_0000`-style
+    /// offset other higher-half kernels use.
+    pub offset: u64,
+}
+
+#[repr(C)]
+pub struct LimineHhdmRequest {
+    id: [u64; 4],
+    revision: u64,
+    pub response: *const LimineHhdmResponse,
+}
+
+#[used]
+#[link_section = ".limine_reqs"]
+pub static MEMMAP_REQUEST: LimineMemmapRequest = LimineMemmapRequest {
+    id: [LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1], 0x67cf3d9d378a806f, 0xe304acdfc50c3c62],
+    revision: 0,
+    response: core::ptr::null(),
+};
+
+#[used]
+#[link_section = ".limine_reqs"]
+pub static HHDM_REQUEST: LimineHhdmRequest = LimineHhdmRequest {
+    id: [LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1], 0x48dcf1cb8ad2b852, 0x63984e959a98244b],
+    revision: 0,
+    response: core::ptr::null(),
+};
+
+/// One file Limine loaded alongside the kernel (a `module_path:` entry in
+/// its config). Only the fields we actually read are declared; as long as
+/// they're a correctly-ordered, correctly-typed prefix of the real
+/// `#[repr(C)]` struct, the trailing fields we never touch don't need to be
+/// named here.
+#[repr(C)]
+pub struct LimineFile {
+    pub revision: u64,
+    /// Already mapped and readable, same as every other Limine response
+    /// pointer - no physical-to-virtual translation needed.
+    pub address: *const u8,
+    pub size: u64,
+    pub path: *const u8,
+    pub cmdline: *const u8,
+}
+
+#[repr(C)]
+pub struct LimineModuleResponse {
+    pub revision: u64,
+    pub module_count: u64,
+    pub modules: *const *const LimineFile,
+}
+
+#[repr(C)]
+pub struct LimineModuleRequest {
+    id: [u64; 4],
+    revision: u64,
+    pub response: *const LimineModuleResponse,
+}
+
+#[used]
+#[link_section = ".limine_reqs"]
+pub static MODULE_REQUEST: LimineModuleRequest = LimineModuleRequest {
+    id: [LIMINE_COMMON_MAGIC[0], LIMINE_COMMON_MAGIC[1], 0x3e7e279702be32af, 0xca1c4f3bd1280cee],
+    revision: 0,
+    response: core::ptr::null(),
+};
+
+unsafe fn cstr_len(mut ptr: *const u8) -> usize {
+    let mut len = 0;
+    while !ptr.is_null() && *ptr != 0 {
+        len += 1;
+        ptr = ptr.add(1);
+    }
+    len
+}
+
+/// Scan the modules Limine loaded for one whose path ends in
+/// `initramfs.cpio` (the name the build scripts bundle it under), returning
+/// its bytes if found.
+unsafe fn find_initramfs_module() -> Option<&'static [u8]> {
+    let response = MODULE_REQUEST.response;
+    if response.is_null() {
+        return None;
+    }
+    let response = &*response;
+    for i in 0..response.module_count as usize {
+        let module = &**response.modules.add(i);
+        let path = core::slice::from_raw_parts(module.path, cstr_len(module.path));
+        if let Ok(path) = core::str::from_utf8(path) {
+            if path.ends_with("initramfs.cpio") {
+                return Some(core::slice::from_raw_parts(module.address, module.size as usize));
+            }
+        }
+    }
+    None
+}
+
+/// Boot record populated from the Limine memmap/HHDM responses. Implements
+/// the same `ArchBoot` the `bootloader`-based path does, so `memory::init`
+/// doesn't need to know which protocol actually booted the kernel.
+pub struct LimineBoot;
+
+impl ArchBoot for LimineBoot {
+    fn boot_params(&self) -> BootParams {
+        let mut memory_map = alloc::vec::Vec::new();
+        unsafe {
+            let memmap_response = MEMMAP_REQUEST.response;
+            if !memmap_response.is_null() {
+                let response = &*memmap_response;
+                for i in 0..response.entry_count as usize {
+                    let entry = &**response.entries.add(i);
+                    memory_map.push(MemoryRegion {
+                        start_frame: (entry.base as usize) / PAGE_SIZE,
+                        end_frame: ((entry.base + entry.length) as usize) / PAGE_SIZE,
+                        usable: entry.ty == LimineMemmapType::Usable,
+                    });
+                }
+            }
+            let physical_memory_offset = if HHDM_REQUEST.response.is_null() {
+                // No HHDM response (ancient loader?): fall back to the same
+                // direct-map window offset other higher-half kernels default
+                // to, so `phys + offset` is still a sane identity mapping.
+                const DEFAULT_HHDM_OFFSET: usize = 0xFFFF_8000_0000_0000;
+                DEFAULT_HHDM_OFFSET
+            } else {
+                (*HHDM_REQUEST.response).offset as usize
+            };
+            BootParams {
+                memory_map,
+                physical_memory_offset,
+                initramfs: find_initramfs_module(),
+            }
+        }
+    }
+}
@@ -1,41 +1,44 @@
-use crate::consts::KERNEL_OFFSET;
+use crate::arch::boot::BootParams;
+use crate::consts::{KERNEL_OFFSET, KSEG2_START};
 use bitmap_allocator::BitAlloc;
-// Depends on kernel
-use super::{BootInfo, MemoryRegionType};
 use crate::memory::{active_table, init_heap, FRAME_ALLOCATOR};
 use log::*;
 use rcore_memory::paging::*;
 use rcore_memory::PAGE_SIZE;
 
-pub fn init(boot_info: &BootInfo) {
+pub fn init(boot_params: &BootParams) {
     //assert_has_not_been_called!("memory::init must be called only once");
-    init_frame_allocator(boot_info);
+    init_frame_allocator(boot_params);
     init_device_vm_map();
     init_kernel_kseg2_map();
     init_heap();
 }
 
-/// Init FrameAllocator and insert all 'Usable' regions from BootInfo.
-fn init_frame_allocator(boot_info: &BootInfo) {
+/// Init FrameAllocator and insert all usable regions from the normalized
+/// boot-time memory map.
+fn init_frame_allocator(boot_params: &BootParams) {
     let mut ba = FRAME_ALLOCATOR.lock();
-    for region in boot_info.memory_map.iter() {
-        if region.region_type == MemoryRegionType::Usable {
-            ba.insert(
-                region.range.start_frame_number as usize..region.range.end_frame_number as usize,
-            );
+    for region in boot_params.memory_map.iter() {
+        if region.usable {
+            ba.insert(region.start_frame..region.end_frame);
         }
     }
 }
 
 fn init_device_vm_map() {
     let mut page_table = active_table();
-    // IOAPIC
+    // LocalAPIC registers are read through `arch::io::Mmio` (see
+    // `driver::apic`) rather than raw pointer casts; `set_mmio(1)` here
+    // marks both the IOAPIC and LocalAPIC ranges non-cacheable the same way
+    // `Dma` marks its buffers - register reads/writes must never be served
+    // from a cache.
     page_table
         .map(KERNEL_OFFSET + 0xfec00000, 0xfec00000)
+        .set_mmio(1)
         .update();
-    // LocalAPIC
     page_table
         .map(KERNEL_OFFSET + 0xfee00000, 0xfee00000)
+        .set_mmio(1)
         .update();
 }
 
@@ -54,8 +57,8 @@ fn init_kernel_kseg2_map() {
     debug!("Page table[509] before mapped: {}", unsafe {
         *(0xffff_ffff_ffff_ffe8 as *const usize)
     });
-    page_table.map(0xfffffe8000000000, 0x0).update();
-    page_table.unmap(0xfffffe8000000000);
+    page_table.map(KSEG2_START, 0x0).update();
+    page_table.unmap(KSEG2_START);
     debug!("Page table[509] after mapped: {}", unsafe {
         *(0xffff_ffff_ffff_ffe8 as *const usize)
     });
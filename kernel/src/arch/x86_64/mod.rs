@@ -1,7 +1,10 @@
+#[cfg(not(feature = "limine"))]
 use bootloader::bootinfo::{BootInfo, MemoryRegionType};
 use core::sync::atomic::*;
 use log::*;
 
+use crate::arch::boot::{ApBringup, ArchBoot, BootParams, MemoryRegion};
+
 pub mod consts;
 pub mod cpu;
 pub mod driver;
@@ -9,6 +12,8 @@ pub mod gdt;
 pub mod idt;
 pub mod interrupt;
 pub mod io;
+#[cfg(feature = "limine")]
+pub mod limine;
 pub mod memory;
 pub mod paging;
 pub mod rand;
@@ -18,9 +23,61 @@ pub mod timer;
 
 static AP_CAN_INIT: AtomicBool = ATOMIC_BOOL_INIT;
 
-/// The entry point of kernel
+/// Marker type implementing the arch-neutral `ApBringup` trait for x86_64;
+/// `ArchBoot` is implemented by the boot record itself (`BootInfo`, or
+/// `limine::LimineBoot` under the `limine` feature).
+pub struct X86Boot;
+
+#[cfg(not(feature = "limine"))]
+impl ArchBoot for BootInfo {
+    fn boot_params(&self) -> BootParams {
+        BootParams {
+            memory_map: self
+                .memory_map
+                .iter()
+                .map(|region| MemoryRegion {
+                    start_frame: region.range.start_frame_number as usize,
+                    end_frame: region.range.end_frame_number as usize,
+                    usable: region.region_type == MemoryRegionType::Usable,
+                })
+                .collect(),
+            physical_memory_offset: consts::KERNEL_OFFSET,
+            // The `bootloader` crate has no concept of boot modules.
+            initramfs: None,
+        }
+    }
+}
+
+impl ApBringup for X86Boot {
+    fn start_other_cpu(_hart_id: usize, _entry: usize) {
+        // There's no ACPI/APIC bring-up here: APs are already parked in
+        // `_start`'s spin-wait below, so "starting" one on x86 just means
+        // letting it past the flag riscv64 would instead set via SBI HSM.
+        AP_CAN_INIT.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The entry point of kernel, booted by the `bootloader` 0.9 crate: it
+/// leaves us a `BootInfo` to normalize via `ArchBoot`.
+#[cfg(not(feature = "limine"))]
 #[no_mangle] // don't mangle the name of this function
 pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
+    kernel_main(boot_info.boot_params());
+}
+
+/// The entry point of kernel, booted under the Limine protocol: unlike
+/// `bootloader`, Limine doesn't hand us a pointer at all - everything comes
+/// from the `LimineMemmapRequest`/`LimineHhdmRequest`/`LimineModuleRequest`
+/// responses it filled in before jumping here, which `LimineBoot` reads.
+#[cfg(feature = "limine")]
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    kernel_main(limine::LimineBoot.boot_params());
+}
+
+/// Shared init path once the boot protocol has been normalized into
+/// `BootParams`, regardless of which one actually ran.
+fn kernel_main(boot_params: BootParams) -> ! {
     let cpu_id = cpu::id();
     println!("Hello world! from CPU {}!", cpu_id);
 
@@ -33,7 +90,11 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
     //println!("Start logging");
     crate::logging::init();
     //println!("End logging");
-    info!("{:#?}", boot_info);
+    info!("{:#?}", boot_params);
+
+    if let Some(archive) = boot_params.initramfs {
+        crate::fs::set_initramfs(archive);
+    }
 
     // Init trap handling.
     //println!("idt");
@@ -45,7 +106,7 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
     //println!("memory");
     //println!("memory");
     //println!("memory");
-    memory::init(boot_info);
+    memory::init(&boot_params);
 
     // Now heap is available
     //println!("gdt");
@@ -69,7 +130,7 @@ pub extern "C" fn _start(boot_info: &'static BootInfo) -> ! {
     crate::lkm::manager::ModuleManager::init();
 
     crate::lkm::cdev::CDevManager::init();
-    AP_CAN_INIT.store(true, Ordering::Relaxed);
+    X86Boot::start_other_cpu(0, 0);
 
     crate::kmain();
 }
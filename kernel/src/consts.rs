@@ -0,0 +1,33 @@
+//! Constants shared across the kernel, gated per-arch where the value itself
+//! is arch-specific (e.g. the high-half layout differs between x86_64 and
+//! riscv64's Sv39/Sv48).
+
+#[cfg(target_arch = "x86_64")]
+pub use self::x86_64::*;
+#[cfg(target_arch = "riscv64")]
+pub use self::riscv64::*;
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64 {
+    /// Added to a physical address to reach its kernel-space mapping.
+    pub const KERNEL_OFFSET: usize = 0xffff_ff00_0000_0000;
+
+    /// Start of the KSEG2 arena the LKM loader's `VirtualSpace`/`VirtualArea`
+    /// and the buddy manager carve kernel-module virtual memory out of.
+    pub const KSEG2_START: usize = 0xffff_fe80_0000_0000;
+    /// 512 GiB: large enough that internal fragmentation never matters.
+    pub const KSEG2_SIZE: usize = 0x0000_0080_0000_0000;
+}
+
+#[cfg(target_arch = "riscv64")]
+mod riscv64 {
+    /// Added to a physical address to reach its kernel-space mapping.
+    pub const KERNEL_OFFSET: usize = 0xffff_ffff_8000_0000;
+
+    /// Start of the KSEG2 arena, picked well clear of `KERNEL_OFFSET` so the
+    /// two never collide under Sv39's narrower canonical high half.
+    pub const KSEG2_START: usize = 0xffff_ffc0_0000_0000;
+    /// 128 GiB: half of the 256 GiB top half Sv39 gives us, ending at
+    /// `0xffff_ffe0_0000_0000`, below `KERNEL_OFFSET`'s window.
+    pub const KSEG2_SIZE: usize = 0x0000_0020_0000_0000;
+}
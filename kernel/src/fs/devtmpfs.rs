@@ -0,0 +1,36 @@
+//! `devtmpfs`: a `TmpFS` pre-populated with one device node per block driver
+//! currently registered in `crate::drivers::BLK_DRIVERS`, so mounting it at
+//! `/dev` gives every driver a `/dev/sdaN` entry without anyone having to
+//! hand-register device files.
+
+use alloc::format;
+use alloc::sync::Arc;
+
+use rcore_fs::vfs::FileType;
+
+use crate::drivers::BlockDriver;
+
+use super::tmpfs::{makedev, TmpFS};
+
+/// Major number `/dev/sdaN` block devices are mknod'd with, same as a real
+/// `sd` driver would use on Linux.
+const SD_MAJOR: u32 = 8;
+
+/// Builds a fresh tmpfs and fills it with a `/dev/sdaN` node per block
+/// driver currently registered in `crate::drivers::BLK_DRIVERS`.
+pub fn init() -> Arc<TmpFS> {
+    let fs = TmpFS::new();
+    let root = fs.root();
+    for (i, driver) in crate::drivers::BLK_DRIVERS.read().iter().enumerate() {
+        let name = format!("sda{}", i);
+        let device = Arc::new(BlockDriver(driver.clone()));
+        let rdev = makedev(SD_MAJOR, i as u32);
+        if root
+            .make_device_node(&name, FileType::BlockDevice, rdev, device, 0o660)
+            .is_err()
+        {
+            info!("devtmpfs: failed to create /dev/{}", name);
+        }
+    }
+    fs
+}
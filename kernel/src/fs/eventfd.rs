@@ -0,0 +1,185 @@
+//! `eventfd`: a 64-bit counter plus a `Condvar`, usable as a cross-thread
+//! wakeup primitive the same way a pipe is, but without the byte-stream
+//! overhead - one `write` adds to the counter, one `read` drains it.
+//!
+//! Implements `INode` the same way `Pipe` does, so `sys_eventfd2` can hand
+//! it to `INodeContainer::anonymous_inode` and wrap the result as a plain
+//! `FileLike::File` - no dedicated `FileLike` variant needed.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::any::Any;
+
+use rcore_fs::vfs::{FileSystem, FileType, FsError, INode, Metadata, PollStatus, Result};
+use spin::Mutex;
+
+use crate::sync::Condvar;
+
+/// `read` decrements the counter by 1 and returns 1, instead of draining
+/// the whole counter - same as Linux's `EFD_SEMAPHORE`.
+pub const EFD_SEMAPHORE: usize = 1;
+/// `read`/`write` return `EAGAIN` instead of blocking when they otherwise
+/// would - same as Linux's `EFD_NONBLOCK`.
+pub const EFD_NONBLOCK: usize = 0x800;
+
+pub struct EventFd {
+    counter: Mutex<u64>,
+    semaphore: bool,
+    nonblock: bool,
+    ready: Condvar,
+}
+
+lazy_static! {
+    /// Every `EventFd` notifies this alongside its own `ready`, so
+    /// `sys_poll`/`sys_select` only need one extra entry in their
+    /// `Condvar::wait_events` array to wake for a write to ANY eventfd,
+    /// not just one known ahead of time.
+    pub static ref EVENTFD_ACTIVITY: Condvar = Condvar::new();
+}
+
+impl EventFd {
+    pub fn new(initval: u64, flags: usize) -> EventFd {
+        EventFd {
+            counter: Mutex::new(initval),
+            semaphore: flags & EFD_SEMAPHORE != 0,
+            nonblock: flags & EFD_NONBLOCK != 0,
+            ready: Condvar::new(),
+        }
+    }
+
+    /// Blocks (unless `EFD_NONBLOCK`) until the counter is non-zero, then
+    /// either drains it to 0 or, in `EFD_SEMAPHORE` mode, decrements it by
+    /// 1 - returning the value read either way.
+    fn take(&self) -> Result<u64> {
+        let mut counter = self.counter.lock();
+        loop {
+            if *counter != 0 {
+                return Ok(if self.semaphore {
+                    *counter -= 1;
+                    1
+                } else {
+                    let value = *counter;
+                    *counter = 0;
+                    value
+                });
+            }
+            if self.nonblock {
+                return Err(FsError::Again);
+            }
+            counter = self.ready.wait(counter);
+        }
+    }
+
+    /// Adds `value` to the counter, waking every waiter. If the sum would
+    /// exceed `u64::MAX - 1` (`eventfd(2)`'s own counter limit), the counter
+    /// is left unchanged and `FsError::Again` is returned rather than
+    /// blocking for the counter to drain, since nothing in this tree wakes a
+    /// writer-side wait on a `read`.
+    fn add(&self, value: u64) -> Result<()> {
+        if value == core::u64::MAX {
+            return Err(FsError::InvalidParam);
+        }
+        let mut counter = self.counter.lock();
+        let sum = counter.saturating_add(value);
+        if sum >= core::u64::MAX {
+            return Err(FsError::Again);
+        }
+        *counter = sum;
+        drop(counter);
+        self.ready.notify_all();
+        EVENTFD_ACTIVITY.notify_all();
+        Ok(())
+    }
+}
+
+impl INode for EventFd {
+    /// Ignores `offset`: like a pipe, an eventfd has no seekable byte
+    /// stream, just the one 8-byte counter value. `buf` must be at least
+    /// 8 bytes, same as `eventfd(2)` requires of `read`.
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if buf.len() < 8 {
+            return Err(FsError::InvalidParam);
+        }
+        let value = self.take()?;
+        buf[..8].copy_from_slice(&value.to_ne_bytes());
+        Ok(8)
+    }
+
+    /// Ignores `offset`, same as `read_at`. `buf` must be exactly the
+    /// 8-byte counter value being added, same as `eventfd(2)` requires of
+    /// `write`.
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        if buf.len() != 8 {
+            return Err(FsError::InvalidParam);
+        }
+        let mut value = [0u8; 8];
+        value.copy_from_slice(buf);
+        self.add(u64::from_ne_bytes(value))?;
+        Ok(8)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        let counter = *self.counter.lock();
+        Ok(PollStatus {
+            read: counter != 0,
+            write: counter < core::u64::MAX - 1,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Err(FsError::NotSupported)
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resize(&self, _len: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn create(&self, _name: &str, _type_: FileType, _mode: u32) -> Result<Arc<INode>> {
+        Err(FsError::NotDir)
+    }
+
+    fn link(&self, _name: &str, _other: &Arc<INode>) -> Result<()> {
+        Err(FsError::NotDir)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(FsError::NotDir)
+    }
+
+    fn move_(&self, _old_name: &str, _target: &Arc<INode>, _new_name: &str) -> Result<()> {
+        Err(FsError::NotDir)
+    }
+
+    fn find(&self, _name: &str) -> Result<Arc<INode>> {
+        Err(FsError::NotDir)
+    }
+
+    fn get_entry(&self, _id: usize) -> Result<String> {
+        Err(FsError::NotDir)
+    }
+
+    fn io_control(&self, _cmd: u32, _data: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn fs(&self) -> Arc<FileSystem> {
+        unimplemented!("eventfd is anonymous and has no backing filesystem")
+    }
+
+    fn as_any_ref(&self) -> &Any {
+        self
+    }
+}
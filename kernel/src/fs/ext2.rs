@@ -0,0 +1,1117 @@
+//! Read/write ext2 filesystem adapter on top of a `BlockDriver`/`BlockCache`.
+//!
+//! This mirrors the structure of the external `ext2-rs` crate (superblock /
+//! block_group / inode / volume split) but implements just enough of it to
+//! satisfy the `rcore_fs::vfs::{FileSystem, INode}` traits so an ext2 volume
+//! can be `mount`ed anywhere in the tree alongside SFS.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use rcore_fs::dev::Device;
+use rcore_fs::vfs::*;
+use spin::{Mutex, RwLock};
+
+const EXT2_MAGIC: u16 = 0xEF53;
+const EXT2_ROOT_INODE: u32 = 2;
+const EXT2_NDIR_BLOCKS: usize = 12;
+const EXT2_IND_BLOCK: usize = 12;
+const EXT2_DIND_BLOCK: usize = 13;
+const EXT2_TIND_BLOCK: usize = 14;
+
+/// Errors that can happen while parsing or walking the on-disk layout.
+/// These get translated into `FsError` at the trait boundary.
+#[derive(Debug)]
+enum Ext2Error {
+    BadMagic,
+    InodeNotFound,
+    NotADirectory,
+    NotFound,
+    OutOfBounds,
+}
+
+impl From<Ext2Error> for FsError {
+    fn from(e: Ext2Error) -> FsError {
+        match e {
+            Ext2Error::BadMagic => FsError::WrongFs,
+            Ext2Error::InodeNotFound => FsError::EntryNotFound,
+            Ext2Error::NotADirectory => FsError::NotDir,
+            Ext2Error::NotFound => FsError::EntryNotFound,
+            Ext2Error::OutOfBounds => FsError::DeviceError,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Superblock {
+    inodes_count: u32,
+    blocks_count: u32,
+    r_blocks_count: u32,
+    free_blocks_count: u32,
+    free_inodes_count: u32,
+    first_data_block: u32,
+    log_block_size: u32,
+    log_frag_size: u32,
+    blocks_per_group: u32,
+    frags_per_group: u32,
+    inodes_per_group: u32,
+    mtime: u32,
+    wtime: u32,
+    mnt_count: u16,
+    max_mnt_count: u16,
+    magic: u16,
+    state: u16,
+    errors: u16,
+    minor_rev_level: u16,
+    lastcheck: u32,
+    checkinterval: u32,
+    creator_os: u32,
+    rev_level: u32,
+    inode_size: u16,
+}
+
+impl Superblock {
+    fn parse(raw: &[u8]) -> Result<Superblock> {
+        if raw.len() < 88 {
+            return Err(Ext2Error::OutOfBounds.into());
+        }
+        let sb = Superblock {
+            inodes_count: le_u32(raw, 0),
+            blocks_count: le_u32(raw, 4),
+            r_blocks_count: le_u32(raw, 8),
+            free_blocks_count: le_u32(raw, 12),
+            free_inodes_count: le_u32(raw, 16),
+            first_data_block: le_u32(raw, 20),
+            log_block_size: le_u32(raw, 24),
+            log_frag_size: le_u32(raw, 28),
+            blocks_per_group: le_u32(raw, 32),
+            frags_per_group: le_u32(raw, 36),
+            inodes_per_group: le_u32(raw, 40),
+            mtime: le_u32(raw, 44),
+            wtime: le_u32(raw, 48),
+            mnt_count: le_u16(raw, 52),
+            max_mnt_count: le_u16(raw, 54),
+            magic: le_u16(raw, 56),
+            state: le_u16(raw, 58),
+            errors: le_u16(raw, 60),
+            minor_rev_level: le_u16(raw, 62),
+            lastcheck: le_u32(raw, 64),
+            checkinterval: le_u32(raw, 68),
+            creator_os: le_u32(raw, 72),
+            rev_level: le_u32(raw, 76),
+            inode_size: if raw.len() >= 90 { le_u16(raw, 88) } else { 128 },
+        };
+        if sb.magic != EXT2_MAGIC {
+            return Err(Ext2Error::BadMagic.into());
+        }
+        Ok(sb)
+    }
+    fn block_size(&self) -> usize {
+        1024usize << self.log_block_size
+    }
+    fn inode_size(&self) -> usize {
+        if self.inode_size == 0 {
+            128
+        } else {
+            self.inode_size as usize
+        }
+    }
+    fn groups_count(&self) -> usize {
+        ((self.blocks_count + self.blocks_per_group - 1) / self.blocks_per_group) as usize
+    }
+}
+
+fn le_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+fn le_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+fn write_u16(buf: &mut [u8], off: usize, val: u16) {
+    buf[off..off + 2].copy_from_slice(&val.to_le_bytes());
+}
+fn write_u32(buf: &mut [u8], off: usize, val: u32) {
+    buf[off..off + 4].copy_from_slice(&val.to_le_bytes());
+}
+
+/// Round up to a 4-byte boundary, as ext2 directory entry `rec_len`s require.
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// Find the index of the first zero bit within the first `limit` bits of a
+/// little-endian bitmap block, if any.
+fn find_free_bit(bitmap: &[u8], limit: usize) -> Option<usize> {
+    for bit in 0..limit {
+        let byte = bitmap[bit / 8];
+        if byte & (1 << (bit % 8)) == 0 {
+            return Some(bit);
+        }
+    }
+    None
+}
+fn set_bit(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] |= 1 << (bit % 8);
+}
+fn clear_bit(bitmap: &mut [u8], bit: usize) {
+    bitmap[bit / 8] &= !(1 << (bit % 8));
+}
+
+/// Write one ext2 directory entry (inode, rec_len, name_len, file_type, name) at `off`.
+fn write_dir_entry(buf: &mut [u8], off: usize, inode: u32, rec_len: usize, name: &str, file_type: u8) {
+    write_u32(buf, off, inode);
+    write_u16(buf, off + 4, rec_len as u16);
+    buf[off + 6] = name.len() as u8;
+    buf[off + 7] = file_type;
+    buf[off + 8..off + 8 + name.len()].copy_from_slice(name.as_bytes());
+}
+
+const EXT2_FT_UNKNOWN: u8 = 0;
+const EXT2_FT_REG_FILE: u8 = 1;
+const EXT2_FT_DIR: u8 = 2;
+const EXT2_FT_CHRDEV: u8 = 3;
+const EXT2_FT_BLKDEV: u8 = 4;
+const EXT2_FT_SYMLINK: u8 = 7;
+
+fn file_type_byte(t: FileType) -> u8 {
+    match t {
+        FileType::File => EXT2_FT_REG_FILE,
+        FileType::Dir => EXT2_FT_DIR,
+        FileType::CharDevice => EXT2_FT_CHRDEV,
+        FileType::BlockDevice => EXT2_FT_BLKDEV,
+        FileType::SymLink => EXT2_FT_SYMLINK,
+        _ => EXT2_FT_UNKNOWN,
+    }
+}
+
+/// Reverse of `file_type_byte`, for decoding a directory entry's on-disk
+/// `file_type` byte back into a `FileType` (e.g. for `readdir`).
+fn file_type_from_byte(b: u8) -> FileType {
+    match b {
+        EXT2_FT_DIR => FileType::Dir,
+        EXT2_FT_CHRDEV => FileType::CharDevice,
+        EXT2_FT_BLKDEV => FileType::BlockDevice,
+        EXT2_FT_SYMLINK => FileType::SymLink,
+        _ => FileType::File,
+    }
+}
+
+fn type_mode_bits(t: FileType) -> u16 {
+    match t {
+        FileType::Dir => EXT2_S_IFDIR,
+        FileType::SymLink => EXT2_S_IFLNK,
+        FileType::CharDevice => EXT2_S_IFCHR,
+        FileType::BlockDevice => EXT2_S_IFBLK,
+        _ => EXT2_S_IFREG,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct GroupDesc {
+    block_bitmap: u32,
+    inode_bitmap: u32,
+    inode_table: u32,
+    free_blocks_count: u16,
+    free_inodes_count: u16,
+    used_dirs_count: u16,
+}
+
+impl GroupDesc {
+    fn parse(raw: &[u8]) -> GroupDesc {
+        GroupDesc {
+            block_bitmap: le_u32(raw, 0),
+            inode_bitmap: le_u32(raw, 4),
+            inode_table: le_u32(raw, 8),
+            free_blocks_count: le_u16(raw, 12),
+            free_inodes_count: le_u16(raw, 14),
+            used_dirs_count: le_u16(raw, 16),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct RawInode {
+    mode: u16,
+    uid: u16,
+    size_lo: u32,
+    atime: u32,
+    ctime: u32,
+    mtime: u32,
+    dtime: u32,
+    gid: u16,
+    links_count: u16,
+    blocks: u32,
+    block: [u32; 15],
+}
+
+const EXT2_S_IFMT: u16 = 0xF000;
+const EXT2_S_IFDIR: u16 = 0x4000;
+const EXT2_S_IFREG: u16 = 0x8000;
+const EXT2_S_IFLNK: u16 = 0xA000;
+const EXT2_S_IFCHR: u16 = 0x2000;
+const EXT2_S_IFBLK: u16 = 0x6000;
+
+impl RawInode {
+    fn parse(raw: &[u8]) -> RawInode {
+        let mut block = [0u32; 15];
+        for i in 0..15 {
+            block[i] = le_u32(raw, 40 + i * 4);
+        }
+        RawInode {
+            mode: le_u16(raw, 0),
+            uid: le_u16(raw, 2),
+            size_lo: le_u32(raw, 4),
+            atime: le_u32(raw, 8),
+            ctime: le_u32(raw, 12),
+            mtime: le_u32(raw, 16),
+            dtime: le_u32(raw, 20),
+            gid: le_u16(raw, 24),
+            links_count: le_u16(raw, 26),
+            blocks: le_u32(raw, 28),
+            block,
+        }
+    }
+    fn file_type(&self) -> FileType {
+        match self.mode & EXT2_S_IFMT {
+            EXT2_S_IFDIR => FileType::Dir,
+            EXT2_S_IFLNK => FileType::SymLink,
+            EXT2_S_IFCHR => FileType::CharDevice,
+            EXT2_S_IFBLK => FileType::BlockDevice,
+            _ => FileType::File,
+        }
+    }
+}
+
+/// A mounted ext2 volume.
+pub struct Ext2FileSystem {
+    device: Arc<Device>,
+    sb: Superblock,
+    /// Block holding the group descriptor table; cached at open time since
+    /// it never moves for the lifetime of the mount.
+    gdt_block: u32,
+    /// Group descriptors, behind a lock since `create`/`unlink`/`resize`
+    /// mutate per-group free counts through a shared `&Ext2FileSystem`.
+    groups: Mutex<Vec<GroupDesc>>,
+    /// Superblock-wide free counts, split out of `sb` (which stays a plain
+    /// snapshot of what was on disk at mount time) so they can be updated
+    /// without re-parsing the whole block.
+    free_blocks_count: AtomicU32,
+    free_inodes_count: AtomicU32,
+    self_ref: RwLock<Option<Arc<Ext2FileSystem>>>,
+}
+
+impl Ext2FileSystem {
+    pub fn open(device: Arc<Device>) -> Result<Arc<Self>> {
+        let mut raw_sb = vec![0u8; 1024];
+        device.read_at(1024, &mut raw_sb).map_err(|_| FsError::DeviceError)?;
+        let sb = Superblock::parse(&raw_sb)?;
+        let block_size = sb.block_size();
+        let gdt_block = if block_size == 1024 { 2 } else { 1 };
+        let ngroups = sb.groups_count();
+        let mut groups = Vec::with_capacity(ngroups);
+        let mut buf = vec![0u8; ngroups * 32];
+        device
+            .read_at(gdt_block * block_size, &mut buf)
+            .map_err(|_| FsError::DeviceError)?;
+        for i in 0..ngroups {
+            groups.push(GroupDesc::parse(&buf[i * 32..i * 32 + 32]));
+        }
+        let free_blocks_count = sb.free_blocks_count;
+        let free_inodes_count = sb.free_inodes_count;
+        let fs = Arc::new(Ext2FileSystem {
+            device,
+            gdt_block: gdt_block as u32,
+            sb,
+            groups: Mutex::new(groups),
+            free_blocks_count: AtomicU32::new(free_blocks_count),
+            free_inodes_count: AtomicU32::new(free_inodes_count),
+            self_ref: RwLock::new(None),
+        });
+        *fs.self_ref.write() = Some(fs.clone());
+        Ok(fs)
+    }
+
+    fn block_size(&self) -> usize {
+        self.sb.block_size()
+    }
+
+    fn read_block(&self, block: u32, buf: &mut [u8]) -> Result<()> {
+        self.device
+            .read_at(block as usize * self.block_size(), buf)
+            .map_err(|_| FsError::DeviceError)?;
+        Ok(())
+    }
+
+    fn write_block(&self, block: u32, buf: &[u8]) -> Result<()> {
+        self.device
+            .write_at(block as usize * self.block_size(), buf)
+            .map_err(|_| FsError::DeviceError)?;
+        Ok(())
+    }
+
+    fn read_raw_inode(&self, ino: u32) -> Result<RawInode> {
+        let byte_off = self.inode_byte_offset(ino)?;
+        let inode_size = self.sb.inode_size();
+        let mut raw = vec![0u8; inode_size];
+        self.device
+            .read_at(byte_off, &mut raw)
+            .map_err(|_| FsError::DeviceError)?;
+        Ok(RawInode::parse(&raw))
+    }
+
+    /// Read-modify-write a raw inode back to disk, preserving whatever
+    /// on-disk bytes our simplified `RawInode` doesn't model (e.g. the
+    /// extended attribute fields some images use).
+    fn write_raw_inode(&self, ino: u32, inode: &RawInode) -> Result<()> {
+        let byte_off = self.inode_byte_offset(ino)?;
+        let inode_size = self.sb.inode_size();
+        let mut raw = vec![0u8; inode_size];
+        self.device
+            .read_at(byte_off, &mut raw)
+            .map_err(|_| FsError::DeviceError)?;
+        write_u16(&mut raw, 0, inode.mode);
+        write_u16(&mut raw, 2, inode.uid);
+        write_u32(&mut raw, 4, inode.size_lo);
+        write_u32(&mut raw, 8, inode.atime);
+        write_u32(&mut raw, 12, inode.ctime);
+        write_u32(&mut raw, 16, inode.mtime);
+        write_u32(&mut raw, 20, inode.dtime);
+        write_u16(&mut raw, 24, inode.gid);
+        write_u16(&mut raw, 26, inode.links_count);
+        write_u32(&mut raw, 28, inode.blocks);
+        for i in 0..15 {
+            write_u32(&mut raw, 40 + i * 4, inode.block[i]);
+        }
+        self.device
+            .write_at(byte_off, &raw)
+            .map_err(|_| FsError::DeviceError)?;
+        Ok(())
+    }
+
+    fn inode_byte_offset(&self, ino: u32) -> Result<usize> {
+        if ino == 0 {
+            return Err(Ext2Error::InodeNotFound.into());
+        }
+        let group = (ino - 1) / self.sb.inodes_per_group;
+        let index = (ino - 1) % self.sb.inodes_per_group;
+        let inode_table = self
+            .groups
+            .lock()
+            .get(group as usize)
+            .ok_or(Ext2Error::InodeNotFound)?
+            .inode_table;
+        let inode_size = self.sb.inode_size();
+        Ok(inode_table as usize * self.block_size() + index as usize * inode_size)
+    }
+
+    /// Allocate a free data block from whichever group has one, returning
+    /// its absolute block number. Updates the group's bitmap, the group
+    /// descriptor's free count and the superblock-wide free count.
+    fn alloc_block(&self) -> Result<u32> {
+        let bs = self.block_size();
+        let ngroups = self.sb.groups_count();
+        for gi in 0..ngroups {
+            let (bitmap_block, group_start, blocks_in_group) = {
+                let groups = self.groups.lock();
+                let gd = &groups[gi];
+                let start = self.sb.first_data_block + gi as u32 * self.sb.blocks_per_group;
+                let count = self
+                    .sb
+                    .blocks_per_group
+                    .min(self.sb.blocks_count - start);
+                (gd.block_bitmap, start, count)
+            };
+            let mut bitmap = vec![0u8; bs];
+            self.read_block(bitmap_block, &mut bitmap)?;
+            if let Some(bit) = find_free_bit(&bitmap, blocks_in_group as usize) {
+                set_bit(&mut bitmap, bit);
+                self.write_block(bitmap_block, &bitmap)?;
+                let mut groups = self.groups.lock();
+                groups[gi].free_blocks_count -= 1;
+                let gd = groups[gi];
+                drop(groups);
+                self.write_group_desc(gi, &gd)?;
+                self.free_blocks_count.fetch_sub(1, Ordering::SeqCst);
+                return Ok(group_start + bit as u32);
+            }
+        }
+        Err(FsError::NoDeviceSpace)
+    }
+
+    fn free_block(&self, block: u32) -> Result<()> {
+        let gi = ((block - self.sb.first_data_block) / self.sb.blocks_per_group) as usize;
+        let bit = ((block - self.sb.first_data_block) % self.sb.blocks_per_group) as usize;
+        let bitmap_block = self.groups.lock()[gi].block_bitmap;
+        let bs = self.block_size();
+        let mut bitmap = vec![0u8; bs];
+        self.read_block(bitmap_block, &mut bitmap)?;
+        clear_bit(&mut bitmap, bit);
+        self.write_block(bitmap_block, &bitmap)?;
+        let mut groups = self.groups.lock();
+        groups[gi].free_blocks_count += 1;
+        let gd = groups[gi];
+        drop(groups);
+        self.write_group_desc(gi, &gd)?;
+        self.free_blocks_count.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Allocate a free inode number, returning it (1-based, as on disk).
+    fn alloc_inode(&self) -> Result<u32> {
+        let bs = self.block_size();
+        let ngroups = self.sb.groups_count();
+        for gi in 0..ngroups {
+            let bitmap_block = self.groups.lock()[gi].inode_bitmap;
+            let mut bitmap = vec![0u8; bs];
+            self.read_block(bitmap_block, &mut bitmap)?;
+            if let Some(bit) = find_free_bit(&bitmap, self.sb.inodes_per_group as usize) {
+                set_bit(&mut bitmap, bit);
+                self.write_block(bitmap_block, &bitmap)?;
+                let mut groups = self.groups.lock();
+                groups[gi].free_inodes_count -= 1;
+                let gd = groups[gi];
+                drop(groups);
+                self.write_group_desc(gi, &gd)?;
+                self.free_inodes_count.fetch_sub(1, Ordering::SeqCst);
+                return Ok(gi as u32 * self.sb.inodes_per_group + bit as u32 + 1);
+            }
+        }
+        Err(FsError::NoDeviceSpace)
+    }
+
+    fn free_inode(&self, ino: u32) -> Result<()> {
+        let gi = ((ino - 1) / self.sb.inodes_per_group) as usize;
+        let bit = ((ino - 1) % self.sb.inodes_per_group) as usize;
+        let bitmap_block = self.groups.lock()[gi].inode_bitmap;
+        let bs = self.block_size();
+        let mut bitmap = vec![0u8; bs];
+        self.read_block(bitmap_block, &mut bitmap)?;
+        clear_bit(&mut bitmap, bit);
+        self.write_block(bitmap_block, &bitmap)?;
+        let mut groups = self.groups.lock();
+        groups[gi].free_inodes_count += 1;
+        let gd = groups[gi];
+        drop(groups);
+        self.write_group_desc(gi, &gd)?;
+        self.free_inodes_count.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn write_group_desc(&self, gi: usize, gd: &GroupDesc) -> Result<()> {
+        let mut buf = vec![0u8; 32];
+        write_u32(&mut buf, 0, gd.block_bitmap);
+        write_u32(&mut buf, 4, gd.inode_bitmap);
+        write_u32(&mut buf, 8, gd.inode_table);
+        write_u16(&mut buf, 12, gd.free_blocks_count);
+        write_u16(&mut buf, 14, gd.free_inodes_count);
+        write_u16(&mut buf, 16, gd.used_dirs_count);
+        self.device
+            .write_at(
+                self.gdt_block as usize * self.block_size() + gi * 32,
+                &buf,
+            )
+            .map_err(|_| FsError::DeviceError)?;
+        Ok(())
+    }
+
+    /// Free every data block (direct and indirect) referenced by an inode
+    /// that's about to be deleted, including the indirect blocks themselves.
+    fn free_inode_data(&self, raw: &RawInode) -> Result<()> {
+        let bs = self.block_size();
+        let nblocks = (raw.size_lo as usize + bs - 1) / bs;
+        for b in 0..nblocks {
+            let block = self.block_of(raw, b)?;
+            if block != 0 {
+                self.free_block(block)?;
+            }
+        }
+        if raw.block[EXT2_IND_BLOCK] != 0 {
+            self.free_block(raw.block[EXT2_IND_BLOCK])?;
+        }
+        self.free_indirect_tree(raw.block[EXT2_DIND_BLOCK], 1)?;
+        self.free_indirect_tree(raw.block[EXT2_TIND_BLOCK], 2)?;
+        Ok(())
+    }
+
+    /// Free an indirect block and, if `depth > 0`, everything it points to
+    /// (recursively, for double/triple indirection).
+    fn free_indirect_tree(&self, block: u32, depth: usize) -> Result<()> {
+        if block == 0 {
+            return Ok(());
+        }
+        if depth > 0 {
+            let bs = self.block_size();
+            let mut buf = vec![0u8; bs];
+            self.read_block(block, &mut buf)?;
+            for i in 0..(bs / 4) {
+                let child = le_u32(&buf, i * 4);
+                if child != 0 {
+                    self.free_indirect_tree(child, depth - 1)?;
+                }
+            }
+        }
+        self.free_block(block)
+    }
+
+    /// Resolve the `idx`-th data block of a file, following indirect blocks.
+    fn block_of(&self, raw: &RawInode, idx: usize) -> Result<u32> {
+        let bs = self.block_size();
+        let ptrs_per_block = bs / 4;
+        if idx < EXT2_NDIR_BLOCKS {
+            return Ok(raw.block[idx]);
+        }
+        let idx = idx - EXT2_NDIR_BLOCKS;
+        if idx < ptrs_per_block {
+            return self.indirect_lookup(raw.block[EXT2_IND_BLOCK], idx);
+        }
+        let idx = idx - ptrs_per_block;
+        if idx < ptrs_per_block * ptrs_per_block {
+            let outer = idx / ptrs_per_block;
+            let inner = idx % ptrs_per_block;
+            let mid = self.indirect_lookup(raw.block[EXT2_DIND_BLOCK], outer)?;
+            return self.indirect_lookup(mid, inner);
+        }
+        let idx = idx - ptrs_per_block * ptrs_per_block;
+        let l0 = idx / (ptrs_per_block * ptrs_per_block);
+        let rem = idx % (ptrs_per_block * ptrs_per_block);
+        let l1 = rem / ptrs_per_block;
+        let l2 = rem % ptrs_per_block;
+        let a = self.indirect_lookup(raw.block[EXT2_TIND_BLOCK], l0)?;
+        let b = self.indirect_lookup(a, l1)?;
+        self.indirect_lookup(b, l2)
+    }
+
+    fn indirect_lookup(&self, block: u32, idx: usize) -> Result<u32> {
+        if block == 0 {
+            return Ok(0);
+        }
+        let bs = self.block_size();
+        let mut buf = vec![0u8; bs];
+        self.read_block(block, &mut buf)?;
+        if idx * 4 + 4 > buf.len() {
+            return Err(Ext2Error::OutOfBounds.into());
+        }
+        Ok(le_u32(&buf, idx * 4))
+    }
+}
+
+impl FileSystem for Ext2FileSystem {
+    fn sync(&self) -> Result<()> {
+        self.device.sync().map_err(|_| FsError::DeviceError)
+    }
+
+    fn root_inode(&self) -> Arc<INode> {
+        Arc::new(Ext2INode {
+            ino: EXT2_ROOT_INODE,
+            fs: self.self_ref.read().clone().unwrap(),
+        })
+    }
+
+    fn info(&self) -> FsInfo {
+        let bfree = self.free_blocks_count.load(Ordering::SeqCst) as usize;
+        let ffree = self.free_inodes_count.load(Ordering::SeqCst) as usize;
+        FsInfo {
+            bsize: self.block_size(),
+            frsize: self.block_size(),
+            blocks: self.sb.blocks_count as usize,
+            bfree,
+            bavail: bfree,
+            files: self.sb.inodes_count as usize,
+            ffree,
+            namemax: 255,
+        }
+    }
+}
+
+pub struct Ext2INode {
+    ino: u32,
+    fs: Arc<Ext2FileSystem>,
+}
+
+impl Ext2INode {
+    fn raw(&self) -> Result<RawInode> {
+        self.fs.read_raw_inode(self.ino)
+    }
+
+    /// Linear scan of linked-list directory entries (inode, rec_len, name_len, file_type, name).
+    fn dir_entries(&self, raw: &RawInode) -> Result<Vec<(u32, u8, String)>> {
+        let bs = self.fs.block_size();
+        let size = raw.size_lo as usize;
+        let nblocks = (size + bs - 1) / bs;
+        let mut out = Vec::new();
+        for b in 0..nblocks {
+            let block = self.fs.block_of(raw, b)?;
+            if block == 0 {
+                continue;
+            }
+            let mut buf = vec![0u8; bs];
+            self.fs.read_block(block, &mut buf)?;
+            let mut off = 0;
+            while off + 8 <= bs {
+                let inode = le_u32(&buf, off);
+                let rec_len = le_u16(&buf, off + 4) as usize;
+                let name_len = buf[off + 6] as usize;
+                let file_type = buf[off + 7];
+                if rec_len == 0 {
+                    break;
+                }
+                if inode != 0 && off + 8 + name_len <= bs {
+                    let name = String::from_utf8_lossy(&buf[off + 8..off + 8 + name_len])
+                        .into_owned();
+                    out.push((inode, file_type, name));
+                }
+                off += rec_len;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Insert `name -> child_ino` into this directory, reusing a tombstone
+    /// entry (`inode == 0`, left behind by `unlink`) or splitting the slack
+    /// at the end of a valid entry's `rec_len` before falling back to
+    /// growing the directory by a new direct block.
+    fn add_dir_entry(&self, child_ino: u32, file_type: u8, name: &str) -> Result<()> {
+        let bs = self.fs.block_size();
+        let needed = align4(8 + name.len());
+        let mut raw = self.raw()?;
+        let nblocks = (raw.size_lo as usize + bs - 1) / bs;
+        for b in 0..nblocks {
+            let block = self.fs.block_of(&raw, b)?;
+            if block == 0 {
+                continue;
+            }
+            let mut buf = vec![0u8; bs];
+            self.fs.read_block(block, &mut buf)?;
+            let mut off = 0;
+            while off + 8 <= bs {
+                let inode = le_u32(&buf, off);
+                let rec_len = le_u16(&buf, off + 4) as usize;
+                if rec_len == 0 || off + rec_len > bs {
+                    break;
+                }
+                if inode == 0 && rec_len >= needed {
+                    write_dir_entry(&mut buf, off, child_ino, rec_len, name, file_type);
+                    return self.fs.write_block(block, &buf);
+                }
+                let used_len = align4(8 + buf[off + 6] as usize);
+                if inode != 0 && rec_len >= used_len + needed {
+                    write_u16(&mut buf, off + 4, used_len as u16);
+                    let new_off = off + used_len;
+                    write_dir_entry(&mut buf, new_off, child_ino, rec_len - used_len, name, file_type);
+                    return self.fs.write_block(block, &buf);
+                }
+                off += rec_len;
+            }
+        }
+        // No existing block has room for the new entry: grow the directory
+        // by one direct block. This simplified allocator never grows a
+        // directory's indirect blocks, which bounds how many entries a
+        // directory can ever hold.
+        if nblocks >= EXT2_NDIR_BLOCKS {
+            return Err(FsError::NoDeviceSpace);
+        }
+        let new_block = self.fs.alloc_block()?;
+        let mut buf = vec![0u8; bs];
+        write_dir_entry(&mut buf, 0, child_ino, bs, name, file_type);
+        self.fs.write_block(new_block, &buf)?;
+        raw.block[nblocks] = new_block;
+        raw.size_lo += bs as u32;
+        raw.blocks += (bs / 512) as u32;
+        self.fs.write_raw_inode(self.ino, &raw)
+    }
+
+    /// Tombstone the entry named `name` (set its `inode` to 0, leaving the
+    /// slot for `add_dir_entry` to reuse later) and return the inode it
+    /// pointed at.
+    fn remove_dir_entry(&self, name: &str) -> Result<u32> {
+        let raw = self.raw()?;
+        let bs = self.fs.block_size();
+        let nblocks = (raw.size_lo as usize + bs - 1) / bs;
+        for b in 0..nblocks {
+            let block = self.fs.block_of(&raw, b)?;
+            if block == 0 {
+                continue;
+            }
+            let mut buf = vec![0u8; bs];
+            self.fs.read_block(block, &mut buf)?;
+            let mut off = 0;
+            while off + 8 <= bs {
+                let inode = le_u32(&buf, off);
+                let rec_len = le_u16(&buf, off + 4) as usize;
+                if rec_len == 0 || off + rec_len > bs {
+                    break;
+                }
+                let name_len = buf[off + 6] as usize;
+                if inode != 0 && off + 8 + name_len <= bs {
+                    let entry_name = String::from_utf8_lossy(&buf[off + 8..off + 8 + name_len]);
+                    if entry_name == name {
+                        write_u32(&mut buf, off, 0);
+                        self.fs.write_block(block, &buf)?;
+                        return Ok(inode);
+                    }
+                }
+                off += rec_len;
+            }
+        }
+        Err(Ext2Error::NotFound.into())
+    }
+}
+
+impl INode for Ext2INode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let raw = self.raw()?;
+        if raw.file_type() == FileType::Dir {
+            return Err(FsError::IsDir);
+        }
+        let size = raw.size_lo as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let bs = self.fs.block_size();
+        let to_read = (size - offset).min(buf.len());
+        let mut done = 0;
+        while done < to_read {
+            let pos = offset + done;
+            let block_idx = pos / bs;
+            let block_off = pos % bs;
+            let block = self.fs.block_of(&raw, block_idx)?;
+            let chunk = (bs - block_off).min(to_read - done);
+            if block == 0 {
+                for b in &mut buf[done..done + chunk] {
+                    *b = 0;
+                }
+            } else {
+                let mut blk = vec![0u8; bs];
+                self.fs.read_block(block, &mut blk)?;
+                buf[done..done + chunk].copy_from_slice(&blk[block_off..block_off + chunk]);
+            }
+            done += chunk;
+        }
+        Ok(done)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let raw = self.raw()?;
+        if raw.file_type() == FileType::Dir {
+            return Err(FsError::IsDir);
+        }
+        let bs = self.fs.block_size();
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let block_idx = pos / bs;
+            let block_off = pos % bs;
+            let block = self.fs.block_of(&raw, block_idx)?;
+            if block == 0 {
+                // Sparse/unallocated region: `write_at` never allocates
+                // blocks (only `resize` does), so this write fails.
+                return Err(FsError::NoDeviceSpace);
+            }
+            let chunk = (bs - block_off).min(buf.len() - done);
+            let mut blk = vec![0u8; bs];
+            self.fs.read_block(block, &mut blk)?;
+            blk[block_off..block_off + chunk].copy_from_slice(&buf[done..done + chunk]);
+            self.fs.write_block(block, &blk)?;
+            done += chunk;
+        }
+        Ok(done)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let raw = self.raw()?;
+        Ok(Metadata {
+            dev: 0,
+            inode: self.ino as usize,
+            size: raw.size_lo as usize,
+            blk_size: self.fs.block_size(),
+            blocks: raw.blocks as usize / 2,
+            atime: Timespec {
+                sec: raw.atime as i64,
+                nsec: 0,
+            },
+            mtime: Timespec {
+                sec: raw.mtime as i64,
+                nsec: 0,
+            },
+            ctime: Timespec {
+                sec: raw.ctime as i64,
+                nsec: 0,
+            },
+            type_: raw.file_type(),
+            mode: raw.mode & 0xFFF,
+            nlinks: raw.links_count as usize,
+            uid: raw.uid as usize,
+            gid: raw.gid as usize,
+            rdev: 0,
+        })
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<()> {
+        // Metadata updates require rewriting the on-disk inode; not yet implemented.
+        Err(FsError::NotSupported)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        self.fs.device.sync().map_err(|_| FsError::DeviceError)
+    }
+
+    /// Identical to `sync_all`: every mutation already rewrites the raw
+    /// on-disk inode synchronously (see `raw()`/`set_metadata`'s own note
+    /// that metadata updates aren't even implemented yet), so there is no
+    /// deferred atime/mtime write for this to skip ahead of a plain data
+    /// flush - both just need the device's write-back cache pushed out.
+    fn sync_data(&self) -> Result<()> {
+        self.fs.device.sync().map_err(|_| FsError::DeviceError)
+    }
+
+    fn resize(&self, len: usize) -> Result<()> {
+        let mut raw = self.raw()?;
+        if raw.file_type() == FileType::Dir {
+            return Err(FsError::IsDir);
+        }
+        let bs = self.fs.block_size();
+        let old_blocks = (raw.size_lo as usize + bs - 1) / bs;
+        let new_blocks = (len + bs - 1) / bs;
+        if new_blocks > EXT2_NDIR_BLOCKS {
+            // This simplified allocator only grows a file's direct blocks;
+            // going past them would mean allocating indirect blocks too.
+            return Err(FsError::NoDeviceSpace);
+        }
+        if new_blocks > old_blocks {
+            let zeros = vec![0u8; bs];
+            for i in old_blocks..new_blocks {
+                let block = self.fs.alloc_block()?;
+                self.fs.write_block(block, &zeros)?;
+                raw.block[i] = block;
+                raw.blocks += (bs / 512) as u32;
+            }
+        } else if new_blocks < old_blocks {
+            for i in new_blocks..old_blocks {
+                if raw.block[i] != 0 {
+                    self.fs.free_block(raw.block[i])?;
+                    raw.block[i] = 0;
+                    raw.blocks = raw.blocks.saturating_sub((bs / 512) as u32);
+                }
+            }
+        }
+        raw.size_lo = len as u32;
+        self.fs.write_raw_inode(self.ino, &raw)
+    }
+
+    fn create(&self, name: &str, type_: FileType, mode: u32) -> Result<Arc<INode>> {
+        // Device nodes are created through `mknod`-style paths elsewhere;
+        // a plain ext2 `create` only ever makes regular files, directories
+        // and symlinks (matching what real ext2 volumes use CREATE for).
+        match type_ {
+            FileType::Dir | FileType::File | FileType::SymLink => {}
+            _ => return Err(FsError::NotSupported),
+        }
+        let mut dir_raw = self.raw()?;
+        if dir_raw.file_type() != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        for (_, _, existing) in self.dir_entries(&dir_raw)? {
+            if existing == name {
+                return Err(FsError::EntryExist);
+            }
+        }
+        let child_ino = self.fs.alloc_inode()?;
+        let mut child = RawInode {
+            mode: type_mode_bits(type_) | (mode as u16 & 0xFFF),
+            uid: 0,
+            size_lo: 0,
+            atime: 0,
+            ctime: 0,
+            mtime: 0,
+            dtime: 0,
+            gid: 0,
+            links_count: if type_ == FileType::Dir { 2 } else { 1 },
+            blocks: 0,
+            block: [0; 15],
+        };
+        if type_ == FileType::Dir {
+            let block = self.fs.alloc_block()?;
+            let bs = self.fs.block_size();
+            let mut buf = vec![0u8; bs];
+            write_dir_entry(&mut buf, 0, child_ino, 12, ".", EXT2_FT_DIR);
+            write_dir_entry(&mut buf, 12, self.ino, bs - 12, "..", EXT2_FT_DIR);
+            self.fs.write_block(block, &buf)?;
+            child.block[0] = block;
+            child.size_lo = bs as u32;
+            child.blocks = (bs / 512) as u32;
+        }
+        self.fs.write_raw_inode(child_ino, &child)?;
+        self.add_dir_entry(child_ino, file_type_byte(type_), name)?;
+        if type_ == FileType::Dir {
+            // The new subdirectory's ".." now points back at us.
+            dir_raw = self.raw()?;
+            dir_raw.links_count += 1;
+            self.fs.write_raw_inode(self.ino, &dir_raw)?;
+        }
+        Ok(Arc::new(Ext2INode {
+            ino: child_ino,
+            fs: self.fs.clone(),
+        }))
+    }
+
+    fn link(&self, name: &str, other: &Arc<INode>) -> Result<()> {
+        let other = other
+            .downcast_ref::<Ext2INode>()
+            .ok_or(FsError::NotSameFs)?;
+        let dir_raw = self.raw()?;
+        if dir_raw.file_type() != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        for (_, _, existing) in self.dir_entries(&dir_raw)? {
+            if existing == name {
+                return Err(FsError::EntryExist);
+            }
+        }
+        let mut other_raw = other.raw()?;
+        if other_raw.file_type() == FileType::Dir {
+            // Hard-linking directories would turn the tree into a graph;
+            // ext2 (like every real filesystem) only allows it for files.
+            return Err(FsError::NotSupported);
+        }
+        self.add_dir_entry(other.ino, file_type_byte(other_raw.file_type()), name)?;
+        other_raw.links_count += 1;
+        self.fs.write_raw_inode(other.ino, &other_raw)
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        if name == "." || name == ".." {
+            return Err(FsError::InvalidParam);
+        }
+        let dir_raw = self.raw()?;
+        if dir_raw.file_type() != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        let target_ino = self
+            .dir_entries(&dir_raw)?
+            .into_iter()
+            .find(|(_, _, entry_name)| entry_name == name)
+            .map(|(ino, _, _)| ino)
+            .ok_or(FsError::EntryNotFound)?;
+        let mut target_raw = self.fs.read_raw_inode(target_ino)?;
+        if target_raw.file_type() == FileType::Dir {
+            let target = Ext2INode {
+                ino: target_ino,
+                fs: self.fs.clone(),
+            };
+            let has_children = target
+                .dir_entries(&target_raw)?
+                .iter()
+                .any(|(_, _, n)| n != "." && n != "..");
+            if has_children {
+                return Err(FsError::DirNotEmpty);
+            }
+        }
+        self.remove_dir_entry(name)?;
+        if target_raw.file_type() == FileType::Dir {
+            // The removed directory's ".." no longer references us.
+            let mut parent_raw = self.raw()?;
+            parent_raw.links_count = parent_raw.links_count.saturating_sub(1);
+            self.fs.write_raw_inode(self.ino, &parent_raw)?;
+        }
+        // A directory holds two links of its own - the parent's entry for it
+        // (just removed above) and its own "." - so both go away here. A
+        // plain file only ever had the one link this entry was providing.
+        let removed_links = if target_raw.file_type() == FileType::Dir { 2 } else { 1 };
+        target_raw.links_count = target_raw.links_count.saturating_sub(removed_links);
+        if target_raw.links_count == 0 {
+            self.fs.free_inode_data(&target_raw)?;
+            self.fs.free_inode(target_ino)?;
+        } else {
+            self.fs.write_raw_inode(target_ino, &target_raw)?;
+        }
+        Ok(())
+    }
+
+    fn move_(&self, _old_name: &str, _target: &Arc<INode>, _new_name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<INode>> {
+        let raw = self.raw()?;
+        if raw.file_type() != FileType::Dir {
+            return Err(Ext2Error::NotADirectory.into());
+        }
+        match name {
+            "." => Ok(Arc::new(Ext2INode {
+                ino: self.ino,
+                fs: self.fs.clone(),
+            })),
+            _ => {
+                for (ino, _ftype, entry_name) in self.dir_entries(&raw)? {
+                    if entry_name == name {
+                        return Ok(Arc::new(Ext2INode {
+                            ino,
+                            fs: self.fs.clone(),
+                        }));
+                    }
+                }
+                Err(Ext2Error::NotFound.into())
+            }
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        let raw = self.raw()?;
+        if raw.file_type() != FileType::Dir {
+            return Err(Ext2Error::NotADirectory.into());
+        }
+        let entries = self.dir_entries(&raw)?;
+        entries
+            .get(id)
+            .map(|(_, _, name)| name.clone())
+            .ok_or(FsError::EntryNotFound)
+    }
+
+    /// Overrides the `get_entry`-based default to resolve inode number and
+    /// type straight from the directory entry already scanned off disk,
+    /// instead of a second `find`+`metadata` lookup per entry.
+    fn readdir(&self, cursor: usize) -> Result<Option<(DirEntryInfo, usize)>> {
+        let raw = self.raw()?;
+        if raw.file_type() != FileType::Dir {
+            return Err(Ext2Error::NotADirectory.into());
+        }
+        let entries = self.dir_entries(&raw)?;
+        Ok(entries.get(cursor).map(|(ino, ftype, name)| {
+            (
+                DirEntryInfo {
+                    name: name.clone(),
+                    inode: *ino as usize,
+                    type_: file_type_from_byte(*ftype),
+                },
+                cursor + 1,
+            )
+        }))
+    }
+
+    fn io_control(&self, _cmd: u32, _data: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn fs(&self) -> Arc<FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &Any {
+        self
+    }
+}
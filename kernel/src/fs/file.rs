@@ -2,16 +2,23 @@
 
 use crate::thread;
 use alloc::{string::String, sync::Arc};
+use core::cmp::min;
 use core::fmt;
 
 use rcore_fs_mountfs::MNode as INodeContainer;
 use rcore_fs::vfs::{FsError, INode, Metadata, PollStatus, Result};
 
+use crate::syscall::fs::RWFlags;
+
 #[derive(Clone)]
 pub struct FileHandle {
     pub inode_container: Arc<INodeContainer>,
     offset: u64,
     options: OpenOptions,
+    /// `FD_CLOEXEC`, as set by `fcntl(F_SETFD)`. Lives on the handle itself
+    /// rather than a separate fd-table side channel, since nothing in this
+    /// tree threads one through yet.
+    cloexec: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -30,12 +37,39 @@ pub enum SeekFrom {
     Current(i64),
 }
 
+/// `fcntl` command numbers, matching their Linux values (this is also why
+/// the pre-existing nonblock special case checked the magic pair
+/// `arg == 2048 && cmd == 4`: `O_NONBLOCK` and `F_SETFL`).
+const F_DUPFD: usize = 0;
+const F_GETFD: usize = 1;
+const F_SETFD: usize = 2;
+const F_GETFL: usize = 3;
+const F_SETFL: usize = 4;
+const F_DUPFD_CLOEXEC: usize = 1030;
+
+const FD_CLOEXEC: usize = 1;
+
+const O_WRONLY: usize = 1;
+const O_RDWR: usize = 2;
+const O_APPEND: usize = 0x400;
+const O_NONBLOCK: usize = 0x800;
+
+/// What a successful `fcntl` hands back to its caller: either a plain
+/// integer result, or (for `F_DUPFD`/`F_DUPFD_CLOEXEC`) a new handle that
+/// the caller - which owns the fd table `FileHandle` doesn't have access to
+/// - is responsible for installing at a free descriptor.
+pub enum FcntlResult {
+    Value(usize),
+    Dup(FileHandle),
+}
+
 impl FileHandle {
     pub fn new(inode_container: Arc<INodeContainer>, options: OpenOptions) -> Self {
         return FileHandle {
             inode_container,
             offset: 0,
             options,
+            cloexec: false,
         };
     }
 
@@ -140,11 +174,248 @@ impl FileHandle {
         self.inode_container.clone()
     }
 
-    pub fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<()> {
-        if arg == 2048 && cmd == 4 {
+    /// Current seek offset, e.g. for resolving a `fcntl` lock range's
+    /// `SEEK_CUR` relative to wherever this handle currently sits.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn cloexec(&self) -> bool {
+        self.cloexec
+    }
+
+    pub fn set_cloexec(&mut self, cloexec: bool) {
+        self.cloexec = cloexec;
+    }
+
+    /// Copy up to `len` bytes from `self` into `dst` without bouncing them
+    /// through a userspace buffer: loop over page-sized extents, `read_at`
+    /// each one into a reusable kernel scratch buffer, `write_at` it to
+    /// `dst`, and advance both offsets. Returns the number of bytes actually
+    /// transferred, which is less than `len` on a short read at EOF (and 0
+    /// for a zero-length request).
+    ///
+    /// `self` and `dst` may be independent `FileHandle`s onto the same
+    /// `INodeContainer` (overlapping ranges within one inode): each 4096-byte
+    /// extent is fully read into the scratch buffer before it's written back
+    /// out, but a forward copy whose ranges overlap across extents reads,
+    /// in later extents, bytes that earlier extents already wrote.
+    ///
+    /// When `self` and `dst` are on the same filesystem, tries
+    /// `INode::copy_range` first so a filesystem that can share or clone
+    /// blocks gets to do that instead; falls back to the buffered loop
+    /// below if that returns `NotSupported` (the default, and the only
+    /// implementation anywhere in this tree so far) or the fds are on
+    /// different filesystems.
+    ///
+    /// Either way this is already offset-addressed rather than reading
+    /// through some single shared cursor: `sys_copy_file_range` seeks
+    /// `self`/`dst` to the right position (or leaves a handle's own offset
+    /// where it was, for the "in/out offset pointer is null" case) before
+    /// calling in here, so `read_at`/`write_at` - and `INode::copy_range`'s
+    /// explicit `src_off`/`dst_off` - always land exactly where the caller
+    /// asked, with no intermediate userspace buffer on either path.
+    pub fn copy_range(&mut self, dst: &mut FileHandle, len: usize) -> Result<usize> {
+        if Arc::ptr_eq(&self.inode_container.fs(), &dst.inode_container.fs()) {
+            let src = self.inode_container.clone() as Arc<INode>;
+            match dst
+                .inode_container
+                .copy_range(&src, self.offset as usize, dst.offset as usize, len)
+            {
+                Ok(copied) => {
+                    self.offset += copied as u64;
+                    dst.offset += copied as u64;
+                    return Ok(copied);
+                }
+                Err(FsError::NotSupported) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        const CHUNK_SIZE: usize = 4096;
+        let mut buf = [0u8; CHUNK_SIZE];
+        let mut copied = 0;
+        while copied < len {
+            let want = min(buf.len(), len - copied);
+            let read = self.read(&mut buf[..want])?;
+            if read == 0 {
+                break;
+            }
+            let mut written = 0;
+            while written < read {
+                let n = dst.write(&buf[written..read])?;
+                if n == 0 {
+                    return Err(FsError::DeviceError);
+                }
+                written += n;
+            }
+            copied += read;
+        }
+        Ok(copied)
+    }
+
+    /// True scatter-gather read: fills each of `bufs` directly off the
+    /// file's own offset, instead of `sys_readv` reading into one bounce
+    /// buffer (`IoVecs::new_buf`) and copying back out through
+    /// `IoVecs::write_all_from_slice`. Stops at the first short slice
+    /// (EOF, or a non-blocking call that would otherwise block) since a
+    /// later iovec wouldn't be contiguous with the offset consumed so far.
+    pub fn read_vectored(&mut self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        self.read_vectored_at(None, bufs, RWFlags::empty())
+    }
+
+    /// The `write_vectored` counterpart of `read_vectored`: drains each of
+    /// `bufs` directly instead of `sys_writev` coalescing them into one
+    /// buffer via `IoVecs::read_all_to_vec` first.
+    pub fn write_vectored(&mut self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        self.write_vectored_at(None, bufs, RWFlags::empty())
+    }
+
+    /// The `preadv2(2)` path behind both `read_vectored` (plain `readv`,
+    /// `offset: None`, no flags) and `sys_preadv2`: `offset` of `None` means
+    /// use - and advance - the handle's own cursor like `readv`, `Some` means
+    /// positional like `preadv` and leaves the cursor alone. `RWF_NOWAIT`
+    /// turns this into a single non-blocking attempt instead of
+    /// blocking-and-retrying through the usual `read_at` loop, regardless of
+    /// whether the fd itself was opened `O_NONBLOCK` - this lets an async
+    /// runtime probe readiness without a separate `poll`/`epoll_wait` call.
+    pub fn read_vectored_at(
+        &mut self,
+        offset: Option<usize>,
+        bufs: &mut [&mut [u8]],
+        flags: RWFlags,
+    ) -> Result<usize> {
+        let prev_nonblock = self.options.nonblock;
+        if flags.contains(RWFlags::NOWAIT) {
             self.options.nonblock = true;
         }
-        Ok(())
+        let mut total = 0;
+        let mut err = None;
+        for buf in bufs.iter_mut() {
+            if buf.is_empty() {
+                continue;
+            }
+            let result = match offset {
+                Some(off) => self.read_at(off + total, buf),
+                None => self.read(buf),
+            };
+            match result {
+                Ok(n) => {
+                    total += n;
+                    if n < buf.len() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    err = Some(e);
+                    break;
+                }
+            }
+        }
+        self.options.nonblock = prev_nonblock;
+        match err {
+            Some(e) => Err(e),
+            None => Ok(total),
+        }
+    }
+
+    /// The `pwritev2(2)` counterpart of `read_vectored_at`. `RWF_APPEND`
+    /// forces the write to land at end-of-file regardless of `offset`, so
+    /// two fds sharing a file description get an atomic append instead of
+    /// racing a separate `lseek(SEEK_END)`; `RWF_DSYNC`/`RWF_SYNC` flush
+    /// afterwards, the same as `O_DSYNC`/`O_SYNC` but scoped to this write.
+    pub fn write_vectored_at(
+        &mut self,
+        offset: Option<usize>,
+        bufs: &mut [&mut [u8]],
+        flags: RWFlags,
+    ) -> Result<usize> {
+        let prev_nonblock = self.options.nonblock;
+        if flags.contains(RWFlags::NOWAIT) {
+            self.options.nonblock = true;
+        }
+        let offset = if flags.contains(RWFlags::APPEND) {
+            match self.metadata() {
+                Ok(meta) => Some(meta.size),
+                Err(e) => {
+                    self.options.nonblock = prev_nonblock;
+                    return Err(e);
+                }
+            }
+        } else {
+            offset
+        };
+        let mut total = 0;
+        let mut err = None;
+        for buf in bufs.iter_mut() {
+            if buf.is_empty() {
+                continue;
+            }
+            let result = match offset {
+                Some(off) => self.write_at(off + total, buf),
+                None => self.write(buf),
+            };
+            match result {
+                Ok(n) => {
+                    total += n;
+                    if n < buf.len() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    err = Some(e);
+                    break;
+                }
+            }
+        }
+        self.options.nonblock = prev_nonblock;
+        let result = match err {
+            Some(e) => Err(e),
+            None => Ok(total),
+        };
+        if result.is_ok() && flags.intersects(RWFlags::DSYNC | RWFlags::SYNC) {
+            if flags.contains(RWFlags::SYNC) {
+                self.sync_all()?;
+            } else {
+                self.sync_data()?;
+            }
+        }
+        result
+    }
+
+    pub fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<FcntlResult> {
+        match cmd {
+            F_DUPFD | F_DUPFD_CLOEXEC => {
+                let mut dup = self.clone();
+                dup.cloexec = cmd == F_DUPFD_CLOEXEC;
+                Ok(FcntlResult::Dup(dup))
+            }
+            F_GETFD => Ok(FcntlResult::Value(if self.cloexec { FD_CLOEXEC } else { 0 })),
+            F_SETFD => {
+                self.cloexec = arg & FD_CLOEXEC != 0;
+                Ok(FcntlResult::Value(0))
+            }
+            F_GETFL => {
+                let mut flags = match (self.options.read, self.options.write) {
+                    (true, true) => O_RDWR,
+                    (false, true) => O_WRONLY,
+                    _ => 0, // O_RDONLY
+                };
+                if self.options.append {
+                    flags |= O_APPEND;
+                }
+                if self.options.nonblock {
+                    flags |= O_NONBLOCK;
+                }
+                Ok(FcntlResult::Value(flags))
+            }
+            F_SETFL => {
+                self.options.append = arg & O_APPEND != 0;
+                self.options.nonblock = arg & O_NONBLOCK != 0;
+                Ok(FcntlResult::Value(0))
+            }
+            _ => Err(FsError::InvalidParam),
+        }
     }
 }
 
@@ -0,0 +1,208 @@
+//! POSIX advisory record locking (`fcntl(F_GETLK/F_SETLK/F_SETLKW)`).
+//!
+//! Locks live in a global table keyed by the identity of the `Arc<INodeContainer>`
+//! they were taken through, rather than on the handle that took them, so two
+//! independently-opened file descriptors onto the same file still see each
+//! other's locks - the same way `fs::vfs`'s `INODE_CACHE` hands out one
+//! shared container per inode so repeated lookups agree on identity. Each
+//! inode's locks are a flat list of byte ranges tagged with the owning pid;
+//! `set_lock`/`set_lock_wait` clip and merge that list as ranges are added or
+//! released, and `get_lock` just reports whatever would conflict without
+//! touching it.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use rcore_fs::vfs::{FsError, Result};
+use rcore_fs_mountfs::MNode as INodeContainer;
+use spin::Mutex;
+
+use crate::sync::Condvar;
+
+pub const F_RDLCK: i16 = 0;
+pub const F_WRLCK: i16 = 1;
+pub const F_UNLCK: i16 = 2;
+
+#[derive(Clone, Copy)]
+struct Lock {
+    // `end == u64::MAX` stands for "to the end of file", the `l_len == 0`
+    // case - it is never shrunk as the file grows or shrinks, matching
+    // Linux's own unbounded-range treatment of l_len == 0.
+    start: u64,
+    end: u64,
+    kind: i16,
+    pid: usize,
+}
+
+fn overlaps(a_start: u64, a_end: u64, b_start: u64, b_end: u64) -> bool {
+    a_start < b_end && b_start < a_end
+}
+
+fn touches(a_start: u64, a_end: u64, b_start: u64, b_end: u64) -> bool {
+    a_start <= b_end && b_start <= a_end
+}
+
+fn union_end(a: u64, b: u64) -> u64 {
+    if a == core::u64::MAX || b == core::u64::MAX {
+        core::u64::MAX
+    } else {
+        a.max(b)
+    }
+}
+
+lazy_static! {
+    static ref LOCK_TABLE: Mutex<BTreeMap<usize, Vec<Lock>>> = Mutex::new(BTreeMap::new());
+    /// Notified whenever any inode's lock list changes, so `set_lock_wait`
+    /// has something to wake up on - coarse-grained the same way
+    /// `eventfd::EVENTFD_ACTIVITY` is one Condvar shared by every eventfd
+    /// rather than one per object.
+    static ref LOCK_RELEASED: Condvar = Condvar::new();
+}
+
+/// Identifies the locked file: the address of the `INodeContainer` itself,
+/// which is shared by every handle opened onto the same inode.
+fn lock_key(container: &Arc<INodeContainer>) -> usize {
+    Arc::as_ptr(container) as *const () as usize
+}
+
+/// Removes (trimming, not just dropping) every part of `pid`'s own locks
+/// that falls inside `[start, end)`, splitting a lock that only partially
+/// overlaps into the piece(s) left outside the range.
+fn clip_pid_range(locks: &mut Vec<Lock>, pid: usize, start: u64, end: u64) {
+    let mut kept = Vec::with_capacity(locks.len());
+    for lock in locks.drain(..) {
+        if lock.pid != pid || !overlaps(lock.start, lock.end, start, end) {
+            kept.push(lock);
+            continue;
+        }
+        if lock.start < start {
+            kept.push(Lock { end: start, ..lock });
+        }
+        if lock.end > end {
+            kept.push(Lock { start: end, ..lock });
+        }
+    }
+    *locks = kept;
+}
+
+/// Finds a lock belonging to some other pid that conflicts with a `kind`
+/// request over `[start, end)` - a write request conflicts with any
+/// overlapping lock, a read request only with an overlapping write lock.
+fn find_conflict(locks: &[Lock], pid: usize, start: u64, end: u64, kind: i16) -> Option<Lock> {
+    locks
+        .iter()
+        .find(|l| {
+            l.pid != pid
+                && overlaps(l.start, l.end, start, end)
+                && (kind == F_WRLCK || l.kind == F_WRLCK)
+        })
+        .cloned()
+}
+
+/// Clips `pid`'s own overlapping locks out of `[start, end)`, then inserts
+/// the new lock, merging it into any now-adjacent-or-overlapping lock of the
+/// same pid and kind instead of leaving the list needlessly fragmented.
+fn insert_merged(locks: &mut Vec<Lock>, pid: usize, mut start: u64, mut end: u64, kind: i16) {
+    clip_pid_range(locks, pid, start, end);
+    locks.retain(|l| {
+        if l.pid == pid && l.kind == kind && touches(l.start, l.end, start, end) {
+            start = start.min(l.start);
+            end = union_end(end, l.end);
+            false
+        } else {
+            true
+        }
+    });
+    locks.push(Lock { start, end, kind, pid });
+}
+
+/// `F_GETLK`: reports a lock that would conflict with a `kind` request over
+/// `[start, end)`, or `F_UNLCK`/0/0/0 if the region is free. Never blocks,
+/// never modifies the table.
+pub fn get_lock(
+    container: &Arc<INodeContainer>,
+    pid: usize,
+    kind: i16,
+    start: u64,
+    end: u64,
+) -> (i16, u64, u64, usize) {
+    let table = LOCK_TABLE.lock();
+    match table
+        .get(&lock_key(container))
+        .and_then(|locks| find_conflict(locks, pid, start, end, kind))
+    {
+        Some(c) => (c.kind, c.start, c.end, c.pid),
+        None => (F_UNLCK, 0, 0, 0),
+    }
+}
+
+/// `F_SETLK`: non-blocking set (`F_RDLCK`/`F_WRLCK`) or clear (`F_UNLCK`) of
+/// `[start, end)`. Returns `Err(FsError::Again)` - mapped to `EAGAIN` by
+/// `From<FsError> for SysError` - if an incompatible lock is already held by
+/// another pid.
+pub fn set_lock(container: &Arc<INodeContainer>, pid: usize, kind: i16, start: u64, end: u64) -> Result<()> {
+    let key = lock_key(container);
+    let mut table = LOCK_TABLE.lock();
+    let locks = table.entry(key).or_insert_with(Vec::new);
+    if kind == F_UNLCK {
+        clip_pid_range(locks, pid, start, end);
+    } else {
+        if find_conflict(locks, pid, start, end, kind).is_some() {
+            return Err(FsError::Again);
+        }
+        insert_merged(locks, pid, start, end, kind);
+    }
+    if locks.is_empty() {
+        table.remove(&key);
+    }
+    drop(table);
+    LOCK_RELEASED.notify_all();
+    Ok(())
+}
+
+/// `F_SETLKW`: like `set_lock`, but blocks the calling thread until the
+/// conflicting lock is released instead of returning `EAGAIN`.
+pub fn set_lock_wait(container: &Arc<INodeContainer>, pid: usize, kind: i16, start: u64, end: u64) -> Result<()> {
+    let key = lock_key(container);
+    let mut table = LOCK_TABLE.lock();
+    loop {
+        let conflict = if kind == F_UNLCK {
+            None
+        } else {
+            table.get(&key).and_then(|locks| find_conflict(locks, pid, start, end, kind))
+        };
+        if conflict.is_none() {
+            let locks = table.entry(key).or_insert_with(Vec::new);
+            if kind == F_UNLCK {
+                clip_pid_range(locks, pid, start, end);
+            } else {
+                insert_merged(locks, pid, start, end, kind);
+            }
+            if locks.is_empty() {
+                table.remove(&key);
+            }
+            drop(table);
+            LOCK_RELEASED.notify_all();
+            return Ok(());
+        }
+        table = LOCK_RELEASED.wait(table);
+    }
+}
+
+/// Drops every lock `pid` holds on `container`'s inode. Called when a file
+/// descriptor referencing it is closed, mirroring POSIX's rule that closing
+/// *any* fd onto a file releases all of that process's locks on it, not just
+/// ones taken through that particular fd.
+pub fn release_process_locks(container: &Arc<INodeContainer>, pid: usize) {
+    let key = lock_key(container);
+    let mut table = LOCK_TABLE.lock();
+    if let Some(locks) = table.get_mut(&key) {
+        locks.retain(|l| l.pid != pid);
+        if locks.is_empty() {
+            table.remove(&key);
+        }
+    }
+    drop(table);
+    LOCK_RELEASED.notify_all();
+}
@@ -0,0 +1,114 @@
+//! Parses a "newc" ASCII cpio archive (the format `cpio -o -H newc` or
+//! `dracut`-style initramfs builders produce) and reconstructs it as a
+//! `TmpFS` tree, so it can be handed to `MountFS::new` as the root
+//! filesystem before any block driver - or `SimpleFileSystem::open` - has
+//! run. This is what resolves the long-standing "boot from initramfs,
+//! remount the real device later" TODO on `fs::VIRTUAL_FS`: a kernel booted
+//! with an `initramfs.cpio` module (currently wired up for Limine, see
+//! `arch::x86_64::limine::find_initramfs_module`) mounts this instead of
+//! opening the SFS device directly.
+//!
+//! Each newc record is a fixed 110-byte ASCII-hex header, immediately
+//! followed by the (NUL-terminated) file name and then the file's data,
+//! each individually padded to a 4-byte boundary. The archive ends at a
+//! record named `TRAILER!!!`. We only care about enough of the header to
+//! place the entry (mode, for file type, and size); uid/gid/mtime/nlink and
+//! friends are skipped over unparsed rather than modeled, since tmpfs has no
+//! use for them and nothing downstream reads them back off an initramfs
+//! inode.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use rcore_fs::vfs::*;
+
+use super::tmpfs::TmpFS;
+
+const MAGIC: &[u8] = b"070701";
+/// 6-byte magic + 13 fixed 8-hex-digit fields.
+const HEADER_LEN: usize = 6 + 13 * 8;
+const TRAILER_NAME: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn hex_field(bytes: &[u8]) -> Result<u32> {
+    let s = core::str::from_utf8(bytes).map_err(|_| FsError::WrongFs)?;
+    u32::from_str_radix(s, 16).map_err(|_| FsError::WrongFs)
+}
+
+/// Walk `path`'s components under `root`, creating any missing intermediate
+/// directories, then create (or reuse, for a directory entry that appears
+/// more than once) the leaf and write `data` into it if it isn't itself a
+/// directory.
+fn place_entry(root: &Arc<INode>, path: &str, mode: u32, data: &[u8]) -> Result<()> {
+    let mut components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    // Some archives include a "." entry for the root directory itself.
+    let leaf = match components.pop() {
+        Some(leaf) => leaf,
+        None => return Ok(()),
+    };
+    let mut dir = root.clone();
+    for component in components {
+        dir = match dir.find(component) {
+            Ok(existing) => existing,
+            Err(FsError::EntryNotFound) => dir.create(component, FileType::Dir, 0o755)?,
+            Err(e) => return Err(e),
+        };
+    }
+    let type_ = match mode & S_IFMT {
+        S_IFDIR => FileType::Dir,
+        S_IFLNK => FileType::SymLink,
+        _ => FileType::File,
+    };
+    let inode = match dir.find(leaf) {
+        Ok(existing) if type_ == FileType::Dir => existing,
+        Ok(_) => return Err(FsError::EntryExist),
+        Err(FsError::EntryNotFound) => dir.create(leaf, type_, mode & 0o777)?,
+        Err(e) => return Err(e),
+    };
+    if type_ != FileType::Dir {
+        inode.write_at(0, data)?;
+    }
+    Ok(())
+}
+
+/// Parse `archive` and build a fresh `TmpFS` containing its directory tree.
+pub fn load(archive: &[u8]) -> Result<Arc<TmpFS>> {
+    let fs = TmpFS::new();
+    let root: Arc<INode> = fs.root();
+    let mut off = 0;
+    loop {
+        if off + HEADER_LEN > archive.len() || &archive[off..off + 6] != MAGIC {
+            return Err(FsError::WrongFs);
+        }
+        let field = |i: usize| hex_field(&archive[off + 6 + i * 8..off + 6 + (i + 1) * 8]);
+        let mode = field(1)?;
+        let filesize = field(6)? as usize;
+        let namesize = field(11)? as usize;
+
+        let name_start = off + HEADER_LEN;
+        let name_end = name_start + namesize - 1; // drop the trailing NUL
+        if namesize == 0 || name_end > archive.len() {
+            return Err(FsError::WrongFs);
+        }
+        let name = core::str::from_utf8(&archive[name_start..name_end]).map_err(|_| FsError::WrongFs)?;
+
+        let data_start = align4(name_start + namesize);
+        let data_end = data_start + filesize;
+        if data_end > archive.len() {
+            return Err(FsError::WrongFs);
+        }
+        if name == TRAILER_NAME {
+            break;
+        }
+        place_entry(&root, name, mode, &archive[data_start..data_end])?;
+        off = align4(data_end);
+    }
+    Ok(fs)
+}
@@ -0,0 +1,524 @@
+//! Read-only ISO9660 filesystem adapter on top of a `BlockDriver`/`BlockCache`.
+//!
+//! Mirrors the structure of `fs::ext2` (volume descriptor / directory record
+//! split, on-disk structs parsed by hand into plain Rust structs) but, since
+//! ISO9660 volumes are never written to once burned, only the read half of
+//! `rcore_fs::vfs::{FileSystem, INode}` does anything - every mutating method
+//! returns `FsError::NotSupported`.
+//!
+//! Understands the Joliet (UCS-2 names) and Rock Ridge (`NM`/`PX`/`SL`/`CL`
+//! SUSP entries) extensions well enough to report POSIX-ish names, modes and
+//! symlinks where present, falling back to the plain ISO9660 (`;1`-suffixed
+//! d-character) name and directory/file distinction otherwise.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use rcore_fs::dev::Device;
+use rcore_fs::vfs::*;
+use spin::RwLock;
+
+const SECTOR_SIZE: usize = 2048;
+const SYSTEM_AREA_SECTORS: usize = 16;
+const VD_TYPE_PRIMARY: u8 = 1;
+const VD_TYPE_SUPPLEMENTARY: u8 = 2;
+const VD_TYPE_TERMINATOR: u8 = 255;
+const VD_ID: &[u8] = b"CD001";
+
+const ISO_FLAG_DIR: u8 = 0x02;
+
+/// Errors that can happen while parsing the on-disk layout. Translated into
+/// `FsError` at the trait boundary, same split as `fs::ext2::Ext2Error`.
+#[derive(Debug)]
+enum IsoError {
+    BadMagic,
+    NoRootDirectory,
+    OutOfBounds,
+}
+
+impl From<IsoError> for FsError {
+    fn from(e: IsoError) -> FsError {
+        match e {
+            IsoError::BadMagic => FsError::WrongFs,
+            IsoError::NoRootDirectory => FsError::WrongFs,
+            IsoError::OutOfBounds => FsError::DeviceError,
+        }
+    }
+}
+
+fn le_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+fn le_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+/// A parsed ISO9660/Rock Ridge directory record, stripped of everything the
+/// `INode` impl doesn't need.
+#[derive(Clone, Debug)]
+struct DirRecord {
+    extent: u32,
+    size: u32,
+    is_dir: bool,
+    /// The plain ISO9660 (or Joliet, if that volume descriptor was used)
+    /// name, with any trailing `;1` version suffix and `.` separator for
+    /// extensionless files already stripped.
+    name: String,
+    /// Rock Ridge `PX` mode bits, if present; overrides the `is_dir`-derived
+    /// default permissions in `metadata()`.
+    rr_mode: Option<u32>,
+    rr_uid: Option<u32>,
+    rr_gid: Option<u32>,
+    /// Rock Ridge `SL` symlink target, if this record is a symlink.
+    rr_symlink: Option<String>,
+    /// Rock Ridge `CL`: the real directory data for this record lives at
+    /// this extent instead (used for directories relocated to keep the
+    /// tree within ISO9660's 8-level depth limit).
+    rr_relocated: Option<u32>,
+}
+
+impl DirRecord {
+    /// Parses the length-prefixed record starting at `buf[off]`, also
+    /// returning its on-disk length so the caller can advance past it.
+    /// Returns `None` for a zero-length record (padding to the end of a
+    /// sector - the caller should skip to the next sector instead).
+    fn parse(buf: &[u8], off: usize, joliet: bool) -> Option<(DirRecord, usize)> {
+        let len = *buf.get(off)? as usize;
+        if len == 0 || off + len > buf.len() {
+            return None;
+        }
+        let extent = le_u32(buf, off + 2);
+        let size = le_u32(buf, off + 10);
+        let flags = buf[off + 25];
+        let name_len = buf[off + 32] as usize;
+        let name_off = off + 33;
+        let mut name = if name_len == 1 && buf[name_off] == 0 {
+            String::from(".")
+        } else if name_len == 1 && buf[name_off] == 1 {
+            String::from("..")
+        } else if joliet {
+            let units: Vec<u16> = buf[name_off..name_off + name_len]
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect();
+            String::from_utf16(&units).unwrap_or_default()
+        } else {
+            String::from_utf8_lossy(&buf[name_off..name_off + name_len]).into_owned()
+        };
+        if !joliet && name != "." && name != ".." {
+            // Plain ISO9660 d-characters: strip the `;<version>` suffix and
+            // a trailing empty extension separator (`FOO.` -> `FOO`).
+            if let Some(semi) = name.find(';') {
+                name.truncate(semi);
+            }
+            if name.ends_with('.') {
+                name.pop();
+            }
+        }
+        let su_off = name_off + name_len + if name_len % 2 == 0 { 1 } else { 0 };
+        let (rr_mode, rr_uid, rr_gid, rr_symlink, rr_relocated, rr_name) =
+            parse_system_use(&buf[su_off..off + len]);
+        if let Some(rr_name) = rr_name {
+            name = rr_name;
+        }
+        Some((
+            DirRecord {
+                extent,
+                size,
+                is_dir: flags & ISO_FLAG_DIR != 0,
+                name,
+                rr_mode,
+                rr_uid,
+                rr_gid,
+                rr_symlink,
+                rr_relocated,
+            },
+            len,
+        ))
+    }
+}
+
+/// Walks a directory record's Rock Ridge SUSP area, picking out the entries
+/// this adapter understands (`NM`, `PX`, `SL`, `CL`). Anything else (`RR`,
+/// `SP`, `TF`, `ST`, ...) is skipped over unread.
+fn parse_system_use(
+    mut buf: &[u8],
+) -> (
+    Option<u32>,
+    Option<u32>,
+    Option<u32>,
+    Option<String>,
+    Option<u32>,
+    Option<String>,
+) {
+    let mut mode = None;
+    let mut uid = None;
+    let mut gid = None;
+    let mut symlink: Option<String> = None;
+    let mut relocated = None;
+    let mut name: Option<String> = None;
+    while buf.len() >= 4 {
+        let sig = &buf[0..2];
+        let len = buf[2] as usize;
+        if len < 4 || len > buf.len() {
+            break;
+        }
+        let data = &buf[4..len];
+        match sig {
+            b"NM" if !data.is_empty() => {
+                let piece = String::from_utf8_lossy(&data[1..]).into_owned();
+                name = Some(name.map_or(piece.clone(), |existing| existing + &piece));
+            }
+            b"PX" if data.len() >= 4 => {
+                // mode(8) / links(8) / uid(8) / gid(8), each a both-endian
+                // field whose first 4 bytes are the little-endian half.
+                mode = Some(le_u32(data, 0));
+                if data.len() >= 32 {
+                    uid = Some(le_u32(data, 16));
+                    gid = Some(le_u32(data, 24));
+                }
+            }
+            b"SL" if !data.is_empty() => {
+                symlink = Some(parse_symlink_components(&data[1..]));
+            }
+            b"CL" if data.len() >= 4 => {
+                relocated = Some(le_u32(data, 0));
+            }
+            _ => {}
+        }
+        buf = &buf[len..];
+    }
+    (mode, uid, gid, symlink, relocated, name)
+}
+
+/// Rebuilds a Rock Ridge `SL` target path out of its component records
+/// (each a flags byte, a length byte, then that many bytes of content).
+fn parse_symlink_components(mut buf: &[u8]) -> String {
+    const SL_CURRENT: u8 = 0x02;
+    const SL_PARENT: u8 = 0x04;
+    const SL_ROOT: u8 = 0x08;
+    let mut parts = Vec::new();
+    while buf.len() >= 2 {
+        let flags = buf[0];
+        let len = buf[1] as usize;
+        if 2 + len > buf.len() {
+            break;
+        }
+        if flags & SL_ROOT != 0 {
+            parts.push(String::from(""));
+        } else if flags & SL_CURRENT != 0 {
+            parts.push(String::from("."));
+        } else if flags & SL_PARENT != 0 {
+            parts.push(String::from(".."));
+        } else {
+            parts.push(String::from_utf8_lossy(&buf[2..2 + len]).into_owned());
+        }
+        buf = &buf[2 + len..];
+    }
+    parts.join("/")
+}
+
+/// A mounted ISO9660 volume.
+pub struct Iso9660FileSystem {
+    device: Arc<Device>,
+    root: DirRecord,
+    /// Whether the Joliet Supplementary Volume Descriptor was used instead
+    /// of the Primary one, which changes how names in every directory on
+    /// the volume are decoded.
+    joliet: bool,
+    volume_space_size: u32,
+    self_ref: RwLock<Option<Arc<Iso9660FileSystem>>>,
+}
+
+impl Iso9660FileSystem {
+    pub fn open(device: Arc<Device>) -> Result<Arc<Self>> {
+        let mut primary_root = None;
+        let mut joliet_root = None;
+        let mut volume_space_size = 0;
+        for sector in SYSTEM_AREA_SECTORS.. {
+            let mut buf = vec![0u8; SECTOR_SIZE];
+            device
+                .read_at(sector * SECTOR_SIZE, &mut buf)
+                .map_err(|_| FsError::DeviceError)?;
+            let vd_type = buf[0];
+            if &buf[1..6] != VD_ID {
+                return Err(IsoError::BadMagic.into());
+            }
+            if vd_type == VD_TYPE_TERMINATOR {
+                break;
+            }
+            if vd_type == VD_TYPE_PRIMARY {
+                volume_space_size = le_u32(&buf, 80);
+                let (root, _) =
+                    DirRecord::parse(&buf, 156, false).ok_or(IsoError::NoRootDirectory)?;
+                primary_root = Some(root);
+            } else if vd_type == VD_TYPE_SUPPLEMENTARY && is_joliet_escape(&buf[88..120]) {
+                let (root, _) =
+                    DirRecord::parse(&buf, 156, true).ok_or(IsoError::NoRootDirectory)?;
+                joliet_root = Some(root);
+            }
+        }
+        let (root, joliet) = match joliet_root {
+            Some(root) => (root, true),
+            None => (primary_root.ok_or(IsoError::NoRootDirectory)?, false),
+        };
+        let fs = Arc::new(Iso9660FileSystem {
+            device,
+            root,
+            joliet,
+            volume_space_size,
+            self_ref: RwLock::new(None),
+        });
+        *fs.self_ref.write() = Some(fs.clone());
+        Ok(fs)
+    }
+
+    fn read_sector(&self, sector: u32, buf: &mut [u8]) -> Result<()> {
+        self.device
+            .read_at(sector as usize * SECTOR_SIZE, buf)
+            .map_err(|_| FsError::DeviceError)
+    }
+
+    /// Linear scan of a directory's records, following Rock Ridge `CL`
+    /// relocation for any entry that carries it.
+    fn dir_entries(&self, record: &DirRecord) -> Result<Vec<DirRecord>> {
+        let extent = record.rr_relocated.unwrap_or(record.extent);
+        let nsectors = (record.size as usize + SECTOR_SIZE - 1) / SECTOR_SIZE;
+        let mut out = Vec::new();
+        for s in 0..nsectors {
+            let mut buf = vec![0u8; SECTOR_SIZE];
+            self.read_sector(extent + s as u32, &mut buf)?;
+            let mut off = 0;
+            while off < SECTOR_SIZE {
+                match DirRecord::parse(&buf, off, self.joliet) {
+                    Some((entry, len)) => {
+                        out.push(entry);
+                        off += len;
+                    }
+                    None => break,
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl FileSystem for Iso9660FileSystem {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<INode> {
+        Arc::new(Iso9660INode {
+            record: self.root.clone(),
+            fs: self.self_ref.read().clone().unwrap(),
+        })
+    }
+
+    fn info(&self) -> FsInfo {
+        let blocks = self.volume_space_size as usize;
+        FsInfo {
+            bsize: SECTOR_SIZE,
+            frsize: SECTOR_SIZE,
+            blocks,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 255,
+        }
+    }
+}
+
+fn is_joliet_escape(escape: &[u8]) -> bool {
+    // UCS-2 Level 1/2/3 escape sequences, the only three Joliet ever uses.
+    escape.starts_with(b"%/@") || escape.starts_with(b"%/C") || escape.starts_with(b"%/E")
+}
+
+pub struct Iso9660INode {
+    record: DirRecord,
+    fs: Arc<Iso9660FileSystem>,
+}
+
+impl INode for Iso9660INode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if self.record.is_dir {
+            return Err(FsError::IsDir);
+        }
+        let content: Vec<u8>;
+        let data: &[u8] = if let Some(target) = &self.record.rr_symlink {
+            content = target.clone().into_bytes();
+            &content
+        } else {
+            let size = self.record.size as usize;
+            if offset >= size {
+                return Ok(0);
+            }
+            let to_read = (size - offset).min(buf.len());
+            let mut done = 0;
+            while done < to_read {
+                let pos = offset + done;
+                let sector = self.record.extent + (pos / SECTOR_SIZE) as u32;
+                let sector_off = pos % SECTOR_SIZE;
+                let chunk = (SECTOR_SIZE - sector_off).min(to_read - done);
+                let mut sec = vec![0u8; SECTOR_SIZE];
+                self.fs.read_sector(sector, &mut sec)?;
+                buf[done..done + chunk].copy_from_slice(&sec[sector_off..sector_off + chunk]);
+                done += chunk;
+            }
+            return Ok(done);
+        };
+        if offset >= data.len() {
+            return Ok(0);
+        }
+        let to_read = (data.len() - offset).min(buf.len());
+        buf[..to_read].copy_from_slice(&data[offset..offset + to_read]);
+        Ok(to_read)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let type_ = if self.record.rr_symlink.is_some() {
+            FileType::SymLink
+        } else if self.record.is_dir {
+            FileType::Dir
+        } else {
+            FileType::File
+        };
+        let size = match &self.record.rr_symlink {
+            Some(target) => target.len(),
+            None => self.record.size as usize,
+        };
+        let default_mode = if self.record.is_dir { 0o555 } else { 0o444 };
+        Ok(Metadata {
+            dev: 0,
+            inode: self.record.extent as usize,
+            size,
+            blk_size: SECTOR_SIZE,
+            blocks: (size + SECTOR_SIZE - 1) / SECTOR_SIZE,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_,
+            mode: self.record.rr_mode.unwrap_or(default_mode) & 0xFFF,
+            nlinks: 1,
+            uid: self.record.rr_uid.unwrap_or(0) as usize,
+            gid: self.record.rr_gid.unwrap_or(0) as usize,
+            rdev: 0,
+        })
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resize(&self, _len: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn create(&self, _name: &str, _type_: FileType, _mode: u32) -> Result<Arc<INode>> {
+        Err(FsError::NotSupported)
+    }
+
+    fn link(&self, _name: &str, _other: &Arc<INode>) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn move_(&self, _old_name: &str, _target: &Arc<INode>, _new_name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<INode>> {
+        if !self.record.is_dir {
+            return Err(FsError::NotDir);
+        }
+        if name == "." {
+            return Ok(Arc::new(Iso9660INode {
+                record: self.record.clone(),
+                fs: self.fs.clone(),
+            }));
+        }
+        for entry in self.fs.dir_entries(&self.record)? {
+            if entry.name == name {
+                return Ok(Arc::new(Iso9660INode {
+                    record: entry,
+                    fs: self.fs.clone(),
+                }));
+            }
+        }
+        Err(FsError::EntryNotFound)
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        if !self.record.is_dir {
+            return Err(FsError::NotDir);
+        }
+        let entries = self.fs.dir_entries(&self.record)?;
+        entries
+            .get(id)
+            .map(|entry| entry.name.clone())
+            .ok_or(FsError::EntryNotFound)
+    }
+
+    /// Overrides the `get_entry`-based default to avoid a second
+    /// `find`+`metadata` per entry, same reasoning as
+    /// `Ext2INode::readdir`.
+    fn readdir(&self, cursor: usize) -> Result<Option<(DirEntryInfo, usize)>> {
+        if !self.record.is_dir {
+            return Err(FsError::NotDir);
+        }
+        let entries = self.fs.dir_entries(&self.record)?;
+        Ok(entries.get(cursor).map(|entry| {
+            (
+                DirEntryInfo {
+                    name: entry.name.clone(),
+                    inode: entry.extent as usize,
+                    type_: if entry.is_dir {
+                        FileType::Dir
+                    } else {
+                        FileType::File
+                    },
+                },
+                cursor + 1,
+            )
+        }))
+    }
+
+    fn io_control(&self, _cmd: u32, _data: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn fs(&self) -> Arc<FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &Any {
+        self
+    }
+}
@@ -7,6 +7,7 @@ use rcore_fs_sfs::SimpleFileSystem;
 
 use crate::drivers::BlockDriver;
 
+pub use self::eventfd::EventFd;
 pub use self::file::*;
 pub use self::file_like::*;
 pub use self::pipe::Pipe;
@@ -16,13 +17,25 @@ pub use self::vga::*;
 use core::mem::uninitialized;
 use spin::RwLock;
 
+pub mod dedupfs;
 mod device;
+pub mod devtmpfs;
+pub mod eventfd;
+pub mod ext2;
 mod file;
 mod file_like;
+pub mod flock;
+pub mod initramfs;
 mod ioctl;
+pub mod iso9660;
+pub mod mountns;
+pub mod ninep;
 mod pipe;
 mod pseudo;
+pub mod schemefs;
 mod stdio;
+pub mod tmpfs;
+pub mod unionfs;
 pub mod vfs;
 pub mod vga;
 
@@ -59,17 +72,35 @@ impl INodeExt for INode {
     }
 }
 
+/// The initramfs image the bootloader handed us, if any. Set by arch init
+/// (from `BootParams::initramfs`) before `VIRTUAL_FS` is first touched;
+/// `lazy_static` means any point before the first deref is early enough.
+static mut INITRAMFS: Option<&'static [u8]> = None;
+
+/// Record the initramfs image to boot from, so `VIRTUAL_FS` mounts it
+/// instead of opening the SFS block device. Must be called, if at all,
+/// before anything first dereferences `VIRTUAL_FS`.
+pub fn set_initramfs(archive: &'static [u8]) {
+    unsafe {
+        INITRAMFS = Some(archive);
+    }
+}
+
 lazy_static! {
-    // TODO: mount sfs onto root.
-    // This is somehow hard work to do: since you may want to unify the process.
-    // 1. Boot from a filesystem like initramfs, which can be a readonly SFS mounted onto root.
-    //    This means you can bundle kernel modules into kernel by packaging them in initramfs.
-    // 2. Mount /dev and place /dev/sda (while naming /dev/sda itself is a hard problem that is related with universal device management).
-    // 3. Remount root, replacing initramfs with /dev/sda (this requires connecting filesystem to device system).
+    // Boot from initramfs: a cpio archive unpacked into a TmpFS and mounted onto root.
+    // This means you can bundle kernel modules into kernel by packaging them in initramfs.
+    // TODO:
+    // 1. Mount /dev and place /dev/sda (while naming /dev/sda itself is a hard problem that is related with universal device management).
+    // 2. Remount root, replacing initramfs with /dev/sda (this requires connecting filesystem to device system).
     //    A hacky approach to avoid implementing re-mounting is to mount /dev/sda under initramfs and perform a chroot.
     //    But in this way you must simulate chroot-jailbreaking behaviour properly: even if some application breaks the jail, it should not ever touch initramfs, or you're caught cheating.
     //    Or... you can swap the SFS with VIRTUAL_FS?
     pub static ref VIRTUAL_FS: Arc<MountFS> = {
+        if let Some(archive) = unsafe { INITRAMFS } {
+            let ramfs = initramfs::load(archive).expect("failed to parse initramfs");
+            return MountFS::new(ramfs);
+        }
+
         #[cfg(not(feature = "link_user"))]
         let device = {
             #[cfg(any(
@@ -0,0 +1,55 @@
+//! Per-process mount namespaces.
+//!
+//! `VirtualFS` used to keep one global mountpoint tree, shared by every
+//! process, which made `chroot`/`unshare(CLONE_NEWNS)`/container-style
+//! isolation impossible. A `MountNamespace` instead owns a private view of
+//! the tree: forking a process shares the parent's namespace by `Arc`, while
+//! an explicit unshare clones it so mounts performed afterwards in the child
+//! (or the parent) stay invisible to the other side.
+
+use super::vfs::{INodeContainer, VirtualFS};
+use alloc::sync::Arc;
+use spin::RwLock;
+
+pub struct MountNamespace {
+    root_vfs: Arc<RwLock<VirtualFS>>,
+    root: Arc<INodeContainer>,
+}
+
+impl MountNamespace {
+    /// The initial namespace a freshly booted kernel starts with: a fresh
+    /// `VirtualFS` mounted from the boot SFS, which used to be the only
+    /// mount tree around.
+    pub fn init() -> Arc<MountNamespace> {
+        let root_vfs = VirtualFS::init();
+        let root = root_vfs.read().root_inode();
+        Arc::new(MountNamespace { root_vfs, root })
+    }
+
+    pub fn root(&self) -> Arc<INodeContainer> {
+        self.root.clone()
+    }
+
+    pub fn root_vfs(&self) -> Arc<RwLock<VirtualFS>> {
+        self.root_vfs.clone()
+    }
+
+    /// Used by `fork`: the child keeps seeing the same mounts as the parent
+    /// until it explicitly unshares.
+    pub fn share(self: &Arc<MountNamespace>) -> Arc<MountNamespace> {
+        self.clone()
+    }
+
+    /// `unshare(CLONE_NEWNS)`: deep-copy the mountpoint tree so that mounts
+    /// performed from now on, on either side, do not cross the namespace
+    /// boundary. The underlying filesystems are not duplicated, only the
+    /// tree recording where they are attached.
+    pub fn unshare(&self) -> Arc<MountNamespace> {
+        let cloned_vfs = Arc::new(RwLock::new(self.root_vfs.read().clone_mount_tree()));
+        let root = cloned_vfs.read().root_inode();
+        Arc::new(MountNamespace {
+            root_vfs: cloned_vfs,
+            root,
+        })
+    }
+}
@@ -0,0 +1,564 @@
+//! A 9P2000.L server exporting the `VirtualFS`/`INodeContainer` tree, so a
+//! host (or another rCore instance) can mount the guest's filesystem over
+//! `virtio-9p` or any other byte-stream transport that hands us whole
+//! messages.
+//!
+//! Borrows the fid-table and open/create flag-mapping design of the external
+//! `p9` server crate without depending on it, and speaks the wire format with hand-rolled
+//! little-endian encode/decode (the same style `fs::ext2` uses for its
+//! on-disk structures) instead of depending on std or a serde-style crate.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use rcore_fs::vfs::{FileType, FsError, Metadata, Result as FsResult};
+
+use crate::fs::vfs::INodeContainer;
+use crate::syscall::fs::{DirentType, StatMode};
+
+// 9P2000.L message types (T is the even request / R is the odd response, T + 1).
+// `pub(crate)` so `lkm::ffi::p9`'s client can address the same requests
+// this server dispatches on, instead of redefining the wire protocol.
+pub(crate) const TVERSION: u8 = 100;
+pub(crate) const RVERSION: u8 = 101;
+pub(crate) const RLERROR: u8 = 7;
+pub(crate) const TATTACH: u8 = 104;
+pub(crate) const RATTACH: u8 = 105;
+pub(crate) const TWALK: u8 = 110;
+pub(crate) const RWALK: u8 = 111;
+pub(crate) const TLOPEN: u8 = 12;
+pub(crate) const RLOPEN: u8 = 13;
+const TLCREATE: u8 = 14;
+const RLCREATE: u8 = 15;
+pub(crate) const TREADDIR: u8 = 40;
+pub(crate) const RREADDIR: u8 = 41;
+pub(crate) const TREAD: u8 = 116;
+pub(crate) const RREAD: u8 = 117;
+pub(crate) const TWRITE: u8 = 118;
+pub(crate) const RWRITE: u8 = 119;
+pub(crate) const TCLUNK: u8 = 120;
+pub(crate) const RCLUNK: u8 = 121;
+const TREMOVE: u8 = 122;
+const RREMOVE: u8 = 123;
+const TRENAME: u8 = 20;
+const RRENAME: u8 = 21;
+pub(crate) const TGETATTR: u8 = 24;
+pub(crate) const RGETATTR: u8 = 25;
+pub(crate) const TSETATTR: u8 = 26;
+pub(crate) const RSETATTR: u8 = 27;
+const TMKDIR: u8 = 72;
+const RMKDIR: u8 = 73;
+const TUNLINKAT: u8 = 76;
+const RUNLINKAT: u8 = 77;
+
+/// 9P2000.L `Tlopen`/`Tlcreate` flags (a subset of Linux's `open(2)` flags,
+/// as used on the wire).
+pub const P9_RDONLY: u32 = 0x0000;
+pub const P9_WRONLY: u32 = 0x0001;
+pub const P9_RDWR: u32 = 0x0002;
+pub const P9_CREATE: u32 = 0x0040;
+pub const P9_EXCL: u32 = 0x0080;
+pub const P9_TRUNC: u32 = 0x0200;
+pub const P9_SYNC: u32 = 0x1000;
+
+/// `(read, write)` this open mode grants, same table `OpenFlags::to_options`
+/// in `syscall::fs` builds from the Linux flag bits the wire values above
+/// are copied from.
+fn open_mode_rw(flags: u32) -> (bool, bool) {
+    match flags & 0x3 {
+        0 => (true, false),  // P9_RDONLY
+        1 => (false, true),  // P9_WRONLY
+        2 => (true, true),   // P9_RDWR
+        _ => (false, false),
+    }
+}
+
+/// `Tsetattr`'s `valid` bitmask selecting which `Metadata` fields to apply.
+const P9_SETATTR_MODE: u32 = 1 << 0;
+const P9_SETATTR_UID: u32 = 1 << 1;
+const P9_SETATTR_GID: u32 = 1 << 2;
+pub(crate) const P9_SETATTR_SIZE: u32 = 1 << 3;
+const P9_SETATTR_ATIME: u32 = 1 << 4;
+const P9_SETATTR_MTIME: u32 = 1 << 5;
+
+pub(crate) const QTDIR: u8 = 0x80;
+pub(crate) const QTSYMLINK: u8 = 0x02;
+pub(crate) const QTFILE: u8 = 0x00;
+
+/// Translate an `FsError` into the errno `Rlerror` carries back to the
+/// client, following the mapping already documented next to each
+/// `FsError` variant.
+fn errno_of(e: &FsError) -> u32 {
+    match e {
+        FsError::NotSupported => 95,  // ENOTSUP
+        FsError::NotFile => 21,       // EISDIR
+        FsError::IsDir => 21,         // EISDIR
+        FsError::NotDir => 20,        // ENOTDIR
+        FsError::EntryNotFound => 2,  // ENOENT
+        FsError::EntryExist => 17,    // EEXIST
+        FsError::NotSameFs => 18,     // EXDEV
+        FsError::InvalidParam => 22,  // EINVAL
+        FsError::NoDeviceSpace => 28, // ENOSPC
+        FsError::DirRemoved => 2,     // ENOENT
+        FsError::DirNotEmpty => 39,   // ENOTEMPTY
+        FsError::WrongFs => 22,       // EINVAL
+        FsError::DeviceError => 5,    // EIO
+        FsError::Busy => 16,          // EBUSY
+        FsError::SymLoop => 40,       // ELOOP
+        FsError::NoDevice => 6,       // ENXIO
+        FsError::Again => 11,         // EAGAIN
+    }
+}
+
+fn qid_type(type_: &FileType) -> u8 {
+    match type_ {
+        FileType::Dir => QTDIR,
+        FileType::SymLink => QTSYMLINK,
+        _ => QTFILE,
+    }
+}
+
+/// A decoder over one message's body, reading 9P's little-endian wire types
+/// in order and advancing its cursor as it goes.
+pub(crate) struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Decoder { buf, pos: 0 }
+    }
+    pub(crate) fn u8(&mut self) -> u8 {
+        let v = self.buf[self.pos];
+        self.pos += 1;
+        v
+    }
+    pub(crate) fn u16(&mut self) -> u16 {
+        let v = u16::from_le_bytes([self.buf[self.pos], self.buf[self.pos + 1]]);
+        self.pos += 2;
+        v
+    }
+    pub(crate) fn u32(&mut self) -> u32 {
+        let b = &self.buf[self.pos..self.pos + 4];
+        let v = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        self.pos += 4;
+        v
+    }
+    pub(crate) fn u64(&mut self) -> u64 {
+        let b = &self.buf[self.pos..self.pos + 8];
+        let mut a = [0u8; 8];
+        a.copy_from_slice(b);
+        self.pos += 8;
+        u64::from_le_bytes(a)
+    }
+    pub(crate) fn str(&mut self) -> String {
+        let len = self.u16() as usize;
+        let s = String::from_utf8_lossy(&self.buf[self.pos..self.pos + len]).into_owned();
+        self.pos += len;
+        s
+    }
+    pub(crate) fn bytes(&mut self, n: usize) -> &'a [u8] {
+        let b = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        b
+    }
+}
+
+/// An encoder building one message's body, mirroring `Decoder`'s wire types.
+#[derive(Default)]
+pub(crate) struct Encoder {
+    pub(crate) buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn u8(&mut self, v: u8) -> &mut Self {
+        self.buf.push(v);
+        self
+    }
+    pub(crate) fn u16(&mut self, v: u16) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+    pub(crate) fn u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+    pub(crate) fn u64(&mut self, v: u64) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+    pub(crate) fn str(&mut self, s: &str) -> &mut Self {
+        self.u16(s.len() as u16);
+        self.buf.extend_from_slice(s.as_bytes());
+        self
+    }
+    pub(crate) fn bytes(&mut self, b: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(b);
+        self
+    }
+    /// A qid is `type:u8, version:u32, path:u64`.
+    pub(crate) fn qid(&mut self, type_: u8, path: u64) -> &mut Self {
+        self.u8(type_).u32(0).u64(path)
+    }
+}
+
+/// A response, ready to be framed by the transport (`size[4] type[1] tag[2]`
+/// followed by the body) and sent back over the wire.
+pub struct Response {
+    pub msg_type: u8,
+    pub tag: u16,
+    pub body: Vec<u8>,
+}
+
+/// One client connection's worth of server state: just the fid table, since
+/// everything else (the tree itself, permission checks, ...) lives in the
+/// `INode`s the fids point at.
+pub struct Ninep9Server {
+    root: Arc<INodeContainer>,
+    fids: BTreeMap<u32, Arc<INodeContainer>>,
+}
+
+impl Ninep9Server {
+    pub fn new(root: Arc<INodeContainer>) -> Self {
+        Ninep9Server {
+            root,
+            fids: BTreeMap::new(),
+        }
+    }
+
+    /// Handle one already-framed message (`msg_type`, `tag`, and its body
+    /// past the 9P header) and produce the response to send back.
+    pub fn dispatch(&mut self, msg_type: u8, tag: u16, body: &[u8]) -> Response {
+        let result = match msg_type {
+            TVERSION => self.version(body),
+            TATTACH => self.attach(body),
+            TWALK => self.walk(body),
+            TLOPEN => self.lopen(body),
+            TLCREATE => self.lcreate(body),
+            TREAD => self.read(body),
+            TWRITE => self.write(body),
+            TREADDIR => self.readdir(body),
+            TGETATTR => self.getattr(body),
+            TSETATTR => self.setattr(body),
+            TCLUNK => self.clunk(body),
+            TREMOVE => self.remove(body),
+            TRENAME => self.rename(body),
+            TMKDIR => self.mkdir(body),
+            TUNLINKAT => self.unlinkat(body),
+            _ => Err(FsError::NotSupported),
+        };
+        match result {
+            Ok((rtype, body)) => Response {
+                msg_type: rtype,
+                tag,
+                body,
+            },
+            Err(e) => {
+                let mut enc = Encoder::default();
+                enc.u32(errno_of(&e));
+                Response {
+                    msg_type: RLERROR,
+                    tag,
+                    body: enc.buf,
+                }
+            }
+        }
+    }
+
+    fn fid(&self, fid: u32) -> FsResult<&Arc<INodeContainer>> {
+        self.fids.get(&fid).ok_or(FsError::InvalidParam)
+    }
+
+    fn version(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let msize = d.u32();
+        let version = d.str();
+        let mut enc = Encoder::default();
+        enc.u32(msize);
+        if version == "9P2000.L" {
+            enc.str("9P2000.L");
+        } else {
+            enc.str("unknown");
+        }
+        Ok((RVERSION, enc.buf))
+    }
+
+    fn attach(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let _afid = d.u32();
+        let _uname = d.str();
+        let _aname = d.str();
+        let _n_uname = d.u32();
+        let root = self.root.clone();
+        let meta = root.metadata()?;
+        self.fids.insert(fid, root);
+        let mut enc = Encoder::default();
+        enc.qid(qid_type(&meta.type_), meta.inode as u64);
+        Ok((RATTACH, enc.buf))
+    }
+
+    fn walk(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let newfid = d.u32();
+        let nwname = d.u16();
+        let names: Vec<String> = (0..nwname).map(|_| d.str()).collect();
+
+        let mut cur = self.fid(fid)?.clone();
+        let mut qids = Vec::new();
+        for name in &names {
+            match cur.find(cur.is_very_root(), name) {
+                Ok(next) => {
+                    let meta = next.metadata()?;
+                    qids.push((qid_type(&meta.type_), meta.inode as u64));
+                    cur = next;
+                }
+                Err(_) => break,
+            }
+        }
+        if names.is_empty() || qids.len() == names.len() {
+            self.fids.insert(newfid, cur);
+        }
+        let mut enc = Encoder::default();
+        enc.u16(qids.len() as u16);
+        for (t, path) in qids {
+            enc.qid(t, path);
+        }
+        Ok((RWALK, enc.buf))
+    }
+
+    fn lopen(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let flags = d.u32();
+        let ic = self.fid(fid)?.clone();
+        let meta = ic.metadata()?;
+        let (_, write) = open_mode_rw(flags);
+        if flags & P9_TRUNC != 0 && write {
+            ic.resize(0)?;
+        }
+        let mut enc = Encoder::default();
+        enc.qid(qid_type(&meta.type_), meta.inode as u64);
+        enc.u32(meta.blk_size as u32); // iounit
+        Ok((RLOPEN, enc.buf))
+    }
+
+    fn lcreate(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let name = d.str();
+        let flags = d.u32();
+        let mode = d.u32();
+        let _gid = d.u32();
+        let dir = self.fid(fid)?.clone();
+        // `create` already fails with `EntryExist` whenever `name` is
+        // already taken, which is exactly `P9_EXCL`'s semantics - there's
+        // no separate "create or reuse" mode to special-case here.
+        let child = dir.create(&name, FileType::File, mode)?;
+        if flags & P9_TRUNC != 0 {
+            child.resize(0)?;
+        }
+        let meta = child.metadata()?;
+        self.fids.insert(fid, child);
+        let mut enc = Encoder::default();
+        enc.qid(qid_type(&meta.type_), meta.inode as u64);
+        enc.u32(meta.blk_size as u32);
+        Ok((RLCREATE, enc.buf))
+    }
+
+    fn read(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let offset = d.u64();
+        let count = d.u32();
+        let ic = self.fid(fid)?.clone();
+        let mut data = vec![0u8; count as usize];
+        let n = ic.read_at(offset as usize, &mut data)?;
+        let mut enc = Encoder::default();
+        enc.u32(n as u32);
+        enc.bytes(&data[..n]);
+        Ok((RREAD, enc.buf))
+    }
+
+    fn write(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let offset = d.u64();
+        let count = d.u32();
+        let data = d.bytes(count as usize);
+        let ic = self.fid(fid)?.clone();
+        let n = ic.write_at(offset as usize, data)?;
+        let mut enc = Encoder::default();
+        enc.u32(n as u32);
+        Ok((RWRITE, enc.buf))
+    }
+
+    /// Streams directory entries from `get_entry`, addressing them by the
+    /// same plain entry index `INodeContainer::find_name_by_child` uses
+    /// rather than a real on-disk byte offset - there's no stable byte
+    /// layout to seek into since entries are produced on demand.
+    fn readdir(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let offset = d.u64();
+        let count = d.u32() as usize;
+        let ic = self.fid(fid)?.clone();
+        if ic.metadata()?.type_ != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        let mut enc = Encoder::default();
+        let mut idx = offset as usize;
+        loop {
+            let name = match ic.get_entry(idx) {
+                Ok(name) => name,
+                Err(FsError::EntryNotFound) => break,
+                Err(e) => return Err(e),
+            };
+            let child = ic.find(ic.is_very_root(), &name)?;
+            let meta = child.metadata()?;
+            // qid(13) + offset(8) + type(1) + name(2+len)
+            let entry_len = 13 + 8 + 1 + 2 + name.len();
+            if !enc.buf.is_empty() && enc.buf.len() + entry_len > count {
+                break;
+            }
+            enc.qid(qid_type(&meta.type_), meta.inode as u64);
+            enc.u64((idx + 1) as u64);
+            enc.u8(DirentType::from_type(&meta.type_).bits());
+            enc.str(&name);
+            idx += 1;
+        }
+        let mut out = Encoder::default();
+        out.u32(enc.buf.len() as u32);
+        out.bytes(&enc.buf);
+        Ok((RREADDIR, out.buf))
+    }
+
+    fn getattr(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let _request_mask = d.u64();
+        let ic = self.fid(fid)?.clone();
+        let meta = ic.metadata()?;
+        let mut enc = Encoder::default();
+        enc.u64(0x0000_07ff); // valid: report all the basic stat fields
+        enc.qid(qid_type(&meta.type_), meta.inode as u64);
+        let mode = StatMode::from_type_mode(meta.type_, meta.mode).bits();
+        enc.u32(mode);
+        enc.u32(meta.uid as u32);
+        enc.u32(meta.gid as u32);
+        enc.u64(meta.nlinks as u64);
+        enc.u64(meta.rdev);
+        enc.u64(meta.size as u64);
+        enc.u64(meta.blk_size as u64);
+        enc.u64(meta.blocks as u64);
+        enc.u64(meta.atime.sec as u64).u64(meta.atime.nsec as u64);
+        enc.u64(meta.mtime.sec as u64).u64(meta.mtime.nsec as u64);
+        enc.u64(meta.ctime.sec as u64).u64(meta.ctime.nsec as u64);
+        enc.u64(0).u64(0); // btime: not tracked
+        enc.u64(0); // gen
+        enc.u64(0); // data_version
+        Ok((RGETATTR, enc.buf))
+    }
+
+    fn setattr(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let valid = d.u32();
+        let mode = d.u32();
+        let uid = d.u32();
+        let gid = d.u32();
+        let size = d.u64();
+        let atime_sec = d.u64();
+        let atime_nsec = d.u64();
+        let mtime_sec = d.u64();
+        let mtime_nsec = d.u64();
+        let ic = self.fid(fid)?.clone();
+        let mut meta = ic.metadata()?;
+        if valid & P9_SETATTR_MODE != 0 {
+            meta.mode = mode as u16;
+        }
+        if valid & P9_SETATTR_UID != 0 {
+            meta.uid = uid as usize;
+        }
+        if valid & P9_SETATTR_GID != 0 {
+            meta.gid = gid as usize;
+        }
+        if valid & P9_SETATTR_ATIME != 0 {
+            meta.atime.sec = atime_sec as i64;
+            meta.atime.nsec = atime_nsec as i32;
+        }
+        if valid & P9_SETATTR_MTIME != 0 {
+            meta.mtime.sec = mtime_sec as i64;
+            meta.mtime.nsec = mtime_nsec as i32;
+        }
+        ic.set_metadata(&meta)?;
+        if valid & P9_SETATTR_SIZE != 0 {
+            ic.resize(size as usize)?;
+        }
+        Ok((RSETATTR, Vec::new()))
+    }
+
+    fn clunk(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        self.fids.remove(&fid);
+        Ok((RCLUNK, Vec::new()))
+    }
+
+    fn remove(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let ic = self.fids.remove(&fid).ok_or(FsError::InvalidParam)?;
+        let parent = ic.find(ic.is_very_root(), "..")?;
+        let name = parent.find_name_by_child(&ic)?;
+        parent.unlink(&name)?;
+        Ok((RREMOVE, Vec::new()))
+    }
+
+    fn mkdir(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let dfid = d.u32();
+        let name = d.str();
+        let mode = d.u32();
+        let _gid = d.u32();
+        let dir = self.fid(dfid)?.clone();
+        let child = dir.create(&name, FileType::Dir, mode)?;
+        let meta = child.metadata()?;
+        let mut enc = Encoder::default();
+        enc.qid(qid_type(&meta.type_), meta.inode as u64);
+        Ok((RMKDIR, enc.buf))
+    }
+
+    /// Unlinks `name` out of the directory `dfid` points at, the same way
+    /// `remove` unlinks the fid's own entry out of its parent - except the
+    /// target here is named relative to an already-open directory fid
+    /// instead of being the subject fid itself, so it doesn't consume one.
+    fn unlinkat(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let dfid = d.u32();
+        let name = d.str();
+        let _flags = d.u32();
+        let dir = self.fid(dfid)?.clone();
+        dir.unlink(&name)?;
+        Ok((RUNLINKAT, Vec::new()))
+    }
+
+    fn rename(&mut self, body: &[u8]) -> FsResult<(u8, Vec<u8>)> {
+        let mut d = Decoder::new(body);
+        let fid = d.u32();
+        let dfid = d.u32();
+        let new_name = d.str();
+        let ic = self.fid(fid)?.clone();
+        let new_parent = self.fid(dfid)?.clone();
+        let old_parent = ic.find(ic.is_very_root(), "..")?;
+        let old_name = old_parent.find_name_by_child(&ic)?;
+        let new_parent_inode: Arc<rcore_fs::vfs::INode> = new_parent;
+        old_parent.move_(&old_name, &new_parent_inode, &new_name)?;
+        Ok((RRENAME, Vec::new()))
+    }
+}
@@ -1,112 +1,592 @@
-use crate::rcore_fs::*;
-use crate::rcore_fs::vfs::{INode, FileSystem, FsError, Metadata, FsInfo, FileType};
-use alloc::sync::Arc;
-use alloc::string::String;
+//! In-memory tmpfs: directories are a `BTreeMap<String, Arc<TmpFSInode>>`,
+//! regular files a growable `Vec<u8>`, symlinks the same `Vec<u8>` holding
+//! their target path. Nothing here touches a block device, so it mounts
+//! instantly and disappears on unmount - a writable scratch filesystem that
+//! `/tmp`, or a `devtmpfs` built on top of it (see `devtmpfs.rs`), can live on.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use core::any::Any;
-use core::cell::RefCell;
-/*
-// The point of designing filesystem is that filesystem just need to make sure that itself is correct.
-// If you mess up with your files, that is your fault.
-pub struct TempFS{
-    root: Arc<TempFSNode>,
-    inode_counter: usize
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use rcore_fs::dev::Device;
+use rcore_fs::vfs::*;
+use spin::{Mutex, RwLock};
+
+use crate::sync::Condvar;
+
+/// Current wall-clock time, read on every operation that POSIX says should
+/// bump atime/mtime/ctime. Forwards to `arch::timer`, declared for this
+/// target in `arch::x86_64::mod` (`pub mod timer;`) but not yet wired up to
+/// an actual clock source in this tree - the same gap
+/// `kernelvm::handle_page_fault` documents for the page-fault handler it's
+/// called from.
+pub(crate) fn now() -> Timespec {
+    crate::arch::timer::now()
+}
+
+/// Pack a (major, minor) pair into the single `u64` `Metadata::rdev` and
+/// `TmpFSInode::rdev` carry, matching how device numbers are encoded
+/// everywhere else in this tree.
+pub fn makedev(major: u32, minor: u32) -> u64 {
+    ((major as u64) << 8) | (minor as u64 & 0xff)
+}
+
+enum NodeContent {
+    Dir(BTreeMap<String, Arc<TmpFSInode>>),
+    Data(Vec<u8>),
+    /// Backs a `devtmpfs` device node: I/O is forwarded straight to the
+    /// driver instead of a RAM buffer.
+    Device(Arc<Device>),
+    /// Backs a named pipe (`mknod ... S_IFIFO`): a byte-stream ring buffer
+    /// shared by every `open()` of this directory entry, instead of the
+    /// one-shot pair of fds an anonymous `Pipe` hands out.
+    Fifo(Arc<Fifo>),
 }
 
-enum TempFSNodeContent{
-    Folder{
-        parent: TempFSNode
-    },
-    File{
+const FIFO_CAPACITY: usize = 0x10000;
+
+/// `Condvar`-guarded ring buffer backing a named pipe, the same blocking
+/// read/write shape `fs::pipe::Pipe` gives an anonymous pipe's two ends.
+struct Fifo {
+    buf: Mutex<VecDeque<u8>>,
+    readable: Condvar,
+    writable: Condvar,
+}
+
+impl Fifo {
+    fn new() -> Fifo {
+        Fifo {
+            buf: Mutex::new(VecDeque::new()),
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+        }
+    }
+
+    /// Blocks until at least one byte is queued, then drains up to
+    /// `out.len()` of it.
+    fn read(&self, out: &mut [u8]) -> Result<usize> {
+        let mut buf = self.buf.lock();
+        while buf.is_empty() {
+            buf = self.readable.wait(buf);
+        }
+        let n = buf.len().min(out.len());
+        for slot in out[..n].iter_mut() {
+            *slot = buf.pop_front().unwrap();
+        }
+        drop(buf);
+        self.writable.notify_all();
+        Ok(n)
+    }
+
+    /// Blocks while the buffer is full, queuing `input` a chunk at a time
+    /// as room frees up, same as a real pipe's bounded buffer.
+    fn write(&self, input: &[u8]) -> Result<usize> {
+        let mut buf = self.buf.lock();
+        let mut written = 0;
+        while written < input.len() {
+            while buf.len() >= FIFO_CAPACITY {
+                buf = self.writable.wait(buf);
+            }
+            let room = FIFO_CAPACITY - buf.len();
+            let n = room.min(input.len() - written);
+            buf.extend(input[written..written + n].iter().cloned());
+            written += n;
+            self.readable.notify_all();
+        }
+        Ok(written)
+    }
 
+    fn poll(&self) -> (bool, bool) {
+        let buf = self.buf.lock();
+        (!buf.is_empty(), buf.len() < FIFO_CAPACITY)
     }
 }
-pub struct TempFSNode{
-    metadata: RefCell<Metadata>,
-    data: TempFSNodeContent
+
+pub struct TmpFS {
+    root: Arc<TmpFSInode>,
+    next_ino: AtomicUsize,
+    self_ref: RwLock<Option<Arc<TmpFS>>>,
 }
-impl FileSystem for TempFS{
-    fn sync(&self) -> Result<(), FsError> {
-        // no need to synchronize, since tmpfs is tmpfs.
+
+impl TmpFS {
+    pub fn new() -> Arc<TmpFS> {
+        // The root inode's `fs`/`parent` back-references can't be filled in
+        // until `fs` itself exists, so it's built as an orphan and patched
+        // right after - the same problem `VirtualFS::wrap` solves for the
+        // mount tree, just without needing a raw-pointer trick since both
+        // fields are already behind an `RwLock`.
+        let root = TmpFSInode::new_orphan(1, FileType::Dir, 0o755, NodeContent::Dir(BTreeMap::new()));
+        let fs = Arc::new(TmpFS {
+            root: root.clone(),
+            next_ino: AtomicUsize::new(2),
+            self_ref: RwLock::new(None),
+        });
+        *fs.self_ref.write() = Some(fs.clone());
+        *root.fs.write() = Some(fs.clone());
+        *root.parent.write() = root.self_ref.clone();
+        fs
+    }
+
+    fn alloc_ino(&self) -> usize {
+        self.next_ino.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// The concrete root inode, for callers (like `devtmpfs`) that need to
+    /// poke tmpfs-specific constructors not exposed through `INode`.
+    pub fn root(&self) -> Arc<TmpFSInode> {
+        self.root.clone()
+    }
+}
+
+impl FileSystem for TmpFS {
+    fn sync(&self) -> Result<()> {
+        // Nothing to flush; tmpfs is memory-only.
         Ok(())
     }
 
     fn root_inode(&self) -> Arc<INode> {
-        Arc::clone(&self.root)
+        self.root.clone()
     }
 
     fn info(&self) -> FsInfo {
-        FsInfo{
-            bsize: 0,
-            frsize: 0,
+        FsInfo {
+            bsize: 0x1000,
+            frsize: 0x1000,
             blocks: 0,
             bfree: 0,
             bavail: 0,
             files: 0,
             ffree: 0,
-            namemax: 256
+            namemax: 255,
+        }
+    }
+}
+
+pub struct TmpFSInode {
+    fs: RwLock<Option<Arc<TmpFS>>>,
+    ino: usize,
+    type_: FileType,
+    parent: RwLock<Weak<TmpFSInode>>,
+    mode: RwLock<u32>,
+    nlinks: RwLock<usize>,
+    content: RwLock<NodeContent>,
+    self_ref: Weak<TmpFSInode>,
+    atime: RwLock<Timespec>,
+    mtime: RwLock<Timespec>,
+    ctime: RwLock<Timespec>,
+    /// Major/minor pair for `CharDevice`/`BlockDevice` nodes, as packed by
+    /// `makedev`; `0` for every other file type.
+    rdev: RwLock<u64>,
+}
+
+impl TmpFSInode {
+    /// Builds an inode with a dangling `fs`/`parent` back-reference; only
+    /// used by `TmpFS::new()` to bootstrap the very first (root) inode,
+    /// which is patched in place right after.
+    fn new_orphan(ino: usize, type_: FileType, mode: u32, content: NodeContent) -> Arc<TmpFSInode> {
+        let born = now();
+        let inode = Arc::new(TmpFSInode {
+            fs: RwLock::new(None),
+            ino,
+            type_,
+            parent: RwLock::new(Weak::default()),
+            mode: RwLock::new(mode),
+            nlinks: RwLock::new(1),
+            content: RwLock::new(content),
+            self_ref: Weak::default(),
+            atime: RwLock::new(born.clone()),
+            mtime: RwLock::new(born.clone()),
+            ctime: RwLock::new(born),
+            rdev: RwLock::new(0),
+        });
+        let weak = Arc::downgrade(&inode);
+        let ptr = Arc::into_raw(inode) as *mut TmpFSInode;
+        unsafe {
+            (*ptr).self_ref = weak;
+            Arc::from_raw(ptr)
+        }
+    }
+
+    fn new_child(
+        fs: Arc<TmpFS>,
+        parent: &Weak<TmpFSInode>,
+        type_: FileType,
+        mode: u32,
+        content: NodeContent,
+    ) -> Arc<TmpFSInode> {
+        let ino = fs.alloc_ino();
+        let born = now();
+        let inode = Arc::new(TmpFSInode {
+            fs: RwLock::new(Some(fs)),
+            ino,
+            type_,
+            parent: RwLock::new(parent.clone()),
+            mode: RwLock::new(mode),
+            nlinks: RwLock::new(1),
+            content: RwLock::new(content),
+            self_ref: Weak::default(),
+            atime: RwLock::new(born.clone()),
+            mtime: RwLock::new(born.clone()),
+            ctime: RwLock::new(born),
+            rdev: RwLock::new(0),
+        });
+        let weak = Arc::downgrade(&inode);
+        let ptr = Arc::into_raw(inode) as *mut TmpFSInode;
+        unsafe {
+            (*ptr).self_ref = weak;
+            Arc::from_raw(ptr)
+        }
+    }
+
+    /// Inserts a device node (`mknod`) dispatching straight to `device`,
+    /// bypassing the regular `File`/`Dir`/`SymLink` trio `create()` hands
+    /// out. `type_` must be `CharDevice` or `BlockDevice`; `rdev` (see
+    /// `makedev`) is what `metadata()` reports and is how a `FileHandle`
+    /// opening this node would know which driver it's talking to. Used by
+    /// `devtmpfs` to populate `/dev/sdaN` entries, and generally lets
+    /// `/dev/null`, `/dev/zero` and consoles live in a RamFS tree instead of
+    /// needing their own filesystem type.
+    pub fn make_device_node(
+        self: &Arc<Self>,
+        name: &str,
+        type_: FileType,
+        rdev: u64,
+        device: Arc<Device>,
+        mode: u32,
+    ) -> Result<()> {
+        match type_ {
+            FileType::CharDevice | FileType::BlockDevice => {}
+            _ => return Err(FsError::InvalidParam),
+        }
+        let mut content = self.content.write();
+        match &mut *content {
+            NodeContent::Dir(map) => {
+                if map.contains_key(name) {
+                    return Err(FsError::EntryExist);
+                }
+                let child = TmpFSInode::new_child(
+                    self.fs.read().clone().unwrap(),
+                    &self.self_ref,
+                    type_,
+                    mode,
+                    NodeContent::Device(device),
+                );
+                *child.rdev.write() = rdev;
+                map.insert(name.to_string(), child);
+                Ok(())
+            }
+            _ => Err(FsError::NotDir),
         }
     }
 }
 
-impl INode for TempFSNode{
-    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
-        unimplemented!()
+impl INode for TmpFSInode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        // A `Fifo`'s `read` blocks, so the clone-and-drop dance here keeps
+        // `self.content`'s `RwLock` from being held across the wait - a
+        // concurrent `write_at` needs its own write lock to make progress.
+        let fifo = match &*self.content.read() {
+            NodeContent::Fifo(fifo) => Some(fifo.clone()),
+            _ => None,
+        };
+        if let Some(fifo) = fifo {
+            let n = fifo.read(buf)?;
+            *self.atime.write() = now();
+            return Ok(n);
+        }
+        let result = match &*self.content.read() {
+            NodeContent::Data(data) => {
+                if offset >= data.len() {
+                    return Ok(0);
+                }
+                let n = (data.len() - offset).min(buf.len());
+                buf[..n].copy_from_slice(&data[offset..offset + n]);
+                Ok(n)
+            }
+            NodeContent::Device(device) => device.read_at(offset, buf).map_err(|_| FsError::DeviceError),
+            NodeContent::Fifo(_) => unreachable!(),
+            NodeContent::Dir(_) => Err(FsError::IsDir),
+        };
+        if result.is_ok() {
+            *self.atime.write() = now();
+        }
+        result
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        let fifo = match &*self.content.read() {
+            NodeContent::Fifo(fifo) => Some(fifo.clone()),
+            _ => None,
+        };
+        if let Some(fifo) = fifo {
+            let n = fifo.write(buf)?;
+            let at = now();
+            *self.mtime.write() = at.clone();
+            *self.ctime.write() = at;
+            return Ok(n);
+        }
+        let result = match &mut *self.content.write() {
+            NodeContent::Data(data) => {
+                let end = offset + buf.len();
+                if end > data.len() {
+                    data.resize(end, 0);
+                }
+                data[offset..end].copy_from_slice(buf);
+                Ok(buf.len())
+            }
+            NodeContent::Device(device) => device.write_at(offset, buf).map_err(|_| FsError::DeviceError),
+            NodeContent::Fifo(_) => unreachable!(),
+            NodeContent::Dir(_) => Err(FsError::IsDir),
+        };
+        if result.is_ok() {
+            let at = now();
+            *self.mtime.write() = at.clone();
+            *self.ctime.write() = at;
+        }
+        result
     }
 
-    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
-        unimplemented!()
+    fn poll(&self) -> Result<PollStatus> {
+        if let NodeContent::Fifo(fifo) = &*self.content.read() {
+            let (read, write) = fifo.poll();
+            return Ok(PollStatus {
+                read,
+                write,
+                error: false,
+            });
+        }
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
     }
 
-    fn metadata(&self) -> Result<Metadata, FsError> {
-        Ok(self.metadata.get_mut().clone())
+    fn metadata(&self) -> Result<Metadata> {
+        let size = match &*self.content.read() {
+            NodeContent::Data(data) => data.len(),
+            NodeContent::Dir(map) => map.len(),
+            NodeContent::Device(_) => 0,
+            NodeContent::Fifo(fifo) => fifo.buf.lock().len(),
+        };
+        // A block device reports the size of its own transfer unit rather
+        // than tmpfs's page size, same as a real block special file would.
+        let blk_size = match self.type_ {
+            FileType::BlockDevice => 0x10000,
+            _ => 0x1000,
+        };
+        Ok(Metadata {
+            dev: 0,
+            inode: self.ino,
+            size,
+            blk_size,
+            blocks: (size + blk_size - 1) / blk_size,
+            atime: self.atime.read().clone(),
+            mtime: self.mtime.read().clone(),
+            ctime: self.ctime.read().clone(),
+            type_: self.type_,
+            mode: *self.mode.read(),
+            nlinks: *self.nlinks.read(),
+            uid: 0,
+            gid: 0,
+            rdev: *self.rdev.read(),
+        })
     }
 
-    fn chmod(&self, mode: u16) -> Result<(), FsError> {
-        self.metadata.mode
+    fn set_metadata(&self, metadata: &Metadata) -> Result<()> {
+        *self.mode.write() = metadata.mode;
+        *self.atime.write() = metadata.atime.clone();
+        *self.mtime.write() = metadata.mtime.clone();
+        *self.ctime.write() = now();
+        *self.rdev.write() = metadata.rdev;
+        Ok(())
     }
 
-    fn sync_all(&self) -> Result<(), FsError> {
+    fn sync_all(&self) -> Result<()> {
         Ok(())
     }
 
-    fn sync_data(&self) -> Result<(), FsError> {
+    fn sync_data(&self) -> Result<()> {
         Ok(())
     }
 
-    fn resize(&self, len: usize) -> Result<(), FsError> {
-        unimplemented!()
+    fn resize(&self, len: usize) -> Result<()> {
+        match &mut *self.content.write() {
+            NodeContent::Data(data) => {
+                data.resize(len, 0);
+                let at = now();
+                *self.mtime.write() = at.clone();
+                *self.ctime.write() = at;
+                Ok(())
+            }
+            _ => Err(FsError::NotSupported),
+        }
     }
 
-    fn create(&self, name: &str, type_: FileType, mode: u32) -> Result<Arc<INode>, FsError> {
-        unimplemented!()
+    fn create(&self, name: &str, type_: FileType, mode: u32) -> Result<Arc<INode>> {
+        match type_ {
+            FileType::Dir
+            | FileType::File
+            | FileType::SymLink
+            | FileType::NamedPipe
+            | FileType::Socket
+            | FileType::CharDevice
+            | FileType::BlockDevice => {}
+            _ => return Err(FsError::NotSupported),
+        }
+        let mut content = self.content.write();
+        match &mut *content {
+            NodeContent::Dir(map) => {
+                if map.contains_key(name) {
+                    return Err(FsError::EntryExist);
+                }
+                let child_content = match type_ {
+                    FileType::Dir => NodeContent::Dir(BTreeMap::new()),
+                    FileType::NamedPipe => NodeContent::Fifo(Arc::new(Fifo::new())),
+                    // A `mknod`-created char/block device has no backing
+                    // driver of its own (unlike `make_device_node`'s
+                    // `/dev/sdaN` entries) - `sys_openat` dispatches
+                    // `CharDevice`s to `CDevManager` by major number
+                    // regardless of this node's content, and nothing in
+                    // this tree opens a raw `BlockDevice`/`Socket` node, so
+                    // an empty buffer is enough to make the entry exist and
+                    // `stat` correctly.
+                    _ => NodeContent::Data(Vec::new()),
+                };
+                let child = TmpFSInode::new_child(
+                    self.fs.read().clone().unwrap(),
+                    &self.self_ref,
+                    type_,
+                    mode,
+                    child_content,
+                );
+                map.insert(name.to_string(), child.clone());
+                drop(content);
+                let at = now();
+                *self.mtime.write() = at.clone();
+                *self.ctime.write() = at;
+                Ok(child)
+            }
+            _ => Err(FsError::NotDir),
+        }
     }
 
-    fn unlink(&self, name: &str) -> Result<(), FsError> {
-        unimplemented!()
+    fn link(&self, name: &str, other: &Arc<INode>) -> Result<()> {
+        let other = other.downcast_ref::<TmpFSInode>().ok_or(FsError::NotSameFs)?;
+        let mut content = self.content.write();
+        match &mut *content {
+            NodeContent::Dir(map) => {
+                if map.contains_key(name) {
+                    return Err(FsError::EntryExist);
+                }
+                let child = other.self_ref.upgrade().ok_or(FsError::NotSameFs)?;
+                *child.nlinks.write() += 1;
+                map.insert(name.to_string(), child.clone());
+                drop(content);
+                let at = now();
+                *self.mtime.write() = at.clone();
+                *self.ctime.write() = at.clone();
+                *child.ctime.write() = at;
+                Ok(())
+            }
+            _ => Err(FsError::NotDir),
+        }
     }
 
-    fn link(&self, name: &str, other: &Arc<INode>) -> Result<(), FsError> {
-        unimplemented!()
+    fn unlink(&self, name: &str) -> Result<()> {
+        let mut content = self.content.write();
+        match &mut *content {
+            NodeContent::Dir(map) => {
+                let child = map.get(name).ok_or(FsError::EntryNotFound)?;
+                if let NodeContent::Dir(sub) = &*child.content.read() {
+                    if !sub.is_empty() {
+                        return Err(FsError::DirNotEmpty);
+                    }
+                }
+                let child = map.remove(name).unwrap();
+                *child.nlinks.write() -= 1;
+                drop(content);
+                let at = now();
+                *self.mtime.write() = at.clone();
+                *self.ctime.write() = at.clone();
+                *child.ctime.write() = at;
+                Ok(())
+            }
+            _ => Err(FsError::NotDir),
+        }
     }
 
-    fn move_(&self, old_name: &str, target: &Arc<INode>, new_name: &str) -> Result<(), FsError> {
-        unimplemented!()
+    fn move_(&self, old_name: &str, target: &Arc<INode>, new_name: &str) -> Result<()> {
+        let target = target.downcast_ref::<TmpFSInode>().ok_or(FsError::NotSameFs)?;
+        let child = {
+            let mut content = self.content.write();
+            match &mut *content {
+                NodeContent::Dir(map) => map.remove(old_name).ok_or(FsError::EntryNotFound)?,
+                _ => return Err(FsError::NotDir),
+            }
+        };
+        let mut target_content = target.content.write();
+        match &mut *target_content {
+            NodeContent::Dir(map) => {
+                if map.contains_key(new_name) {
+                    return Err(FsError::EntryExist);
+                }
+                *child.parent.write() = target.self_ref.clone();
+                map.insert(new_name.to_string(), child);
+                Ok(())
+            }
+            _ => Err(FsError::NotDir),
+        }
     }
 
-    fn find(&self, name: &str) -> Result<Arc<INode>, FsError> {
-        unimplemented!()
+    fn find(&self, name: &str) -> Result<Arc<INode>> {
+        match name {
+            "." => Ok(self.self_ref.upgrade().unwrap()),
+            ".." => Ok(self
+                .parent
+                .read()
+                .upgrade()
+                .unwrap_or_else(|| self.self_ref.upgrade().unwrap())),
+            _ => match &*self.content.read() {
+                NodeContent::Dir(map) => {
+                    let child = map.get(name).ok_or(FsError::EntryNotFound)?;
+                    Ok(child.clone())
+                }
+                _ => Err(FsError::NotDir),
+            },
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match &*self.content.read() {
+            NodeContent::Dir(map) => match id {
+                0 => Ok(".".to_string()),
+                1 => Ok("..".to_string()),
+                _ => map
+                    .keys()
+                    .nth(id - 2)
+                    .cloned()
+                    .ok_or(FsError::EntryNotFound),
+            },
+            _ => Err(FsError::NotDir),
+        }
     }
 
-    fn get_entry(&self, id: usize) -> Result<String, FsError> {
-        unimplemented!()
+    fn io_control(&self, _cmd: u32, _data: usize) -> Result<()> {
+        // `rcore_fs::dev::Device` only exposes read_at/write_at/sync, so a
+        // device node backed by it has no ioctl to forward to.
+        Err(FsError::NotSupported)
     }
 
     fn fs(&self) -> Arc<FileSystem> {
-        unimplemented!()
+        self.fs.read().clone().unwrap()
     }
 
     fn as_any_ref(&self) -> &Any {
-        unimplemented!()
+        self
     }
 }
-*/
\ No newline at end of file
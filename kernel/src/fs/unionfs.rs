@@ -0,0 +1,327 @@
+//! Union/overlay filesystem: a read-only lower layer plus a writable upper
+//! layer, merged into a single tree and mountable anywhere through
+//! `INodeContainer::mount` like any other `FileSystem`.
+//!
+//! `find`/`get_entry` union the two layers' directory entries (upper shadows
+//! lower). Reads are served from whichever layer currently holds the file.
+//! The first `write_at`/`resize`/`create`/`set_metadata` touching a
+//! lower-only inode triggers copy-up: the file (and every still-lower-only
+//! ancestor directory) is recreated in the upper layer and all further
+//! operations on it redirect there. Deleting a lower-only entry can't
+//! actually remove it, so `unlink` instead drops a whiteout marker (a
+//! zero-sized char-dev entry named `.wh.<name>`) in the upper layer, which
+//! hides the lower entry from `find`/`get_entry` without touching the lower
+//! filesystem.
+
+use alloc::collections::btree_set::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use rcore_fs::vfs::*;
+use spin::RwLock;
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+fn whiteout_name(name: &str) -> String {
+    let mut s = String::from(WHITEOUT_PREFIX);
+    s.push_str(name);
+    s
+}
+
+pub struct OverlayFS {
+    lower: Arc<INode>,
+    upper: Arc<INode>,
+    self_ref: RwLock<Option<Arc<OverlayFS>>>,
+}
+
+impl OverlayFS {
+    pub fn new(lower: Arc<INode>, upper: Arc<INode>) -> Arc<OverlayFS> {
+        let fs = Arc::new(OverlayFS {
+            lower,
+            upper,
+            self_ref: RwLock::new(None),
+        });
+        *fs.self_ref.write() = Some(fs.clone());
+        fs
+    }
+}
+
+impl FileSystem for OverlayFS {
+    fn sync(&self) -> Result<()> {
+        self.upper.sync_all()
+    }
+
+    fn root_inode(&self) -> Arc<INode> {
+        OverlayINode {
+            fs: self.self_ref.read().clone().unwrap(),
+            parent: None,
+            name: String::new(),
+            lower: Some(self.lower.clone()),
+            upper: RwLock::new(Some(self.upper.clone())),
+            self_ref: Weak::default(),
+        }
+        .wrap()
+    }
+
+    fn info(&self) -> FsInfo {
+        self.upper.fs().info()
+    }
+}
+
+pub struct OverlayINode {
+    fs: Arc<OverlayFS>,
+    /// `None` only for the overlay's own root; `VirtualFS` intercepts ".."
+    /// at mount boundaries before it ever reaches us there.
+    parent: Option<Arc<OverlayINode>>,
+    name: String,
+    lower: Option<Arc<INode>>,
+    upper: RwLock<Option<Arc<INode>>>,
+    self_ref: Weak<OverlayINode>,
+}
+
+impl OverlayINode {
+    fn wrap(self) -> Arc<Self> {
+        let inode = Arc::new(self);
+        let weak = Arc::downgrade(&inode);
+        let ptr = Arc::into_raw(inode) as *mut Self;
+        unsafe {
+            (*ptr).self_ref = weak;
+            Arc::from_raw(ptr)
+        }
+    }
+
+    fn current_upper(&self) -> Option<Arc<INode>> {
+        self.upper.read().clone()
+    }
+
+    /// Materializes this inode (and, recursively, every still-lower-only
+    /// ancestor) into the upper layer, then returns the upper side.
+    fn ensure_upper(&self) -> Result<Arc<INode>> {
+        if let Some(upper) = self.current_upper() {
+            return Ok(upper);
+        }
+        let parent = self.parent.as_ref().ok_or(FsError::NotSupported)?;
+        let parent_upper = parent.ensure_upper()?;
+        let lower = self.lower.as_ref().ok_or(FsError::EntryNotFound)?;
+        let meta = lower.metadata()?;
+        // Clear a stale whiteout so the copied-up entry isn't immediately hidden.
+        let wh = whiteout_name(&self.name);
+        if parent_upper.find(&wh).is_ok() {
+            parent_upper.unlink(&wh)?;
+        }
+        let upper_child = parent_upper.create(&self.name, meta.type_, meta.mode)?;
+        if meta.type_ != FileType::Dir {
+            let mut buf = vec![0u8; 4096];
+            let mut off = 0;
+            loop {
+                let n = lower.read_at(off, &mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                upper_child.write_at(off, &buf[..n])?;
+                off += n;
+            }
+        }
+        *self.upper.write() = Some(upper_child.clone());
+        Ok(upper_child)
+    }
+
+    /// Upper entries first (minus whiteout markers, which instead hide the
+    /// matching lower entry), then whatever lower entries aren't shadowed.
+    fn merged_entries(&self) -> Result<Vec<String>> {
+        let mut whiteouts = BTreeSet::new();
+        let mut seen = BTreeSet::new();
+        let mut names = Vec::new();
+        if let Some(upper) = self.current_upper() {
+            for i in 0.. {
+                match upper.get_entry(i) {
+                    Ok(name) => {
+                        if name.starts_with(WHITEOUT_PREFIX) {
+                            whiteouts.insert(String::from(&name[WHITEOUT_PREFIX.len()..]));
+                            continue;
+                        }
+                        if seen.insert(name.clone()) {
+                            names.push(name);
+                        }
+                    }
+                    Err(FsError::EntryNotFound) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        if let Some(lower) = &self.lower {
+            for i in 0.. {
+                match lower.get_entry(i) {
+                    Ok(name) => {
+                        if whiteouts.contains(&name) {
+                            continue;
+                        }
+                        if seen.insert(name.clone()) {
+                            names.push(name);
+                        }
+                    }
+                    Err(FsError::EntryNotFound) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(names)
+    }
+}
+
+impl INode for OverlayINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        match self.current_upper() {
+            Some(upper) => upper.read_at(offset, buf),
+            None => self
+                .lower
+                .as_ref()
+                .ok_or(FsError::EntryNotFound)?
+                .read_at(offset, buf),
+        }
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.ensure_upper()?.write_at(offset, buf)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        match self.current_upper() {
+            Some(upper) => upper.poll(),
+            None => self.lower.as_ref().ok_or(FsError::EntryNotFound)?.poll(),
+        }
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        match self.current_upper() {
+            Some(upper) => upper.metadata(),
+            None => self
+                .lower
+                .as_ref()
+                .ok_or(FsError::EntryNotFound)?
+                .metadata(),
+        }
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<()> {
+        self.ensure_upper()?.set_metadata(metadata)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        match self.current_upper() {
+            Some(upper) => upper.sync_all(),
+            None => Ok(()), // Lower is read-only; nothing to flush.
+        }
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        match self.current_upper() {
+            Some(upper) => upper.sync_data(),
+            None => Ok(()),
+        }
+    }
+
+    fn resize(&self, len: usize) -> Result<()> {
+        self.ensure_upper()?.resize(len)
+    }
+
+    fn create(&self, name: &str, type_: FileType, mode: u32) -> Result<Arc<INode>> {
+        let upper = self.ensure_upper()?;
+        let wh = whiteout_name(name);
+        if upper.find(&wh).is_ok() {
+            upper.unlink(&wh)?;
+        }
+        upper.create(name, type_, mode)
+    }
+
+    fn link(&self, _name: &str, _other: &Arc<INode>) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        let upper = self.ensure_upper()?;
+        let existed_in_lower = self
+            .lower
+            .as_ref()
+            .map_or(false, |l| l.find(name).is_ok());
+        match upper.find(name) {
+            Ok(_) => upper.unlink(name)?,
+            Err(FsError::EntryNotFound) => {}
+            Err(e) => return Err(e),
+        }
+        if existed_in_lower {
+            let wh = whiteout_name(name);
+            if upper.find(&wh).is_err() {
+                upper.create(&wh, FileType::CharDevice, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn move_(&self, _old_name: &str, _target: &Arc<INode>, _new_name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<INode>> {
+        match name {
+            "." => Ok(self.self_ref.upgrade().unwrap()),
+            ".." => Ok(self.parent.clone().ok_or(FsError::EntryNotFound)?),
+            _ => {
+                if let Some(upper) = self.current_upper() {
+                    let wh = whiteout_name(name);
+                    if upper.find(&wh).is_ok() {
+                        return Err(FsError::EntryNotFound);
+                    }
+                    if let Ok(upper_child) = upper.find(name) {
+                        let lower_child = self.lower.as_ref().and_then(|l| l.find(name).ok());
+                        return Ok(OverlayINode {
+                            fs: self.fs.clone(),
+                            parent: Some(self.self_ref.upgrade().unwrap()),
+                            name: name.to_string(),
+                            lower: lower_child,
+                            upper: RwLock::new(Some(upper_child)),
+                            self_ref: Weak::default(),
+                        }
+                        .wrap());
+                    }
+                }
+                if let Some(lower) = &self.lower {
+                    if let Ok(lower_child) = lower.find(name) {
+                        return Ok(OverlayINode {
+                            fs: self.fs.clone(),
+                            parent: Some(self.self_ref.upgrade().unwrap()),
+                            name: name.to_string(),
+                            lower: Some(lower_child),
+                            upper: RwLock::new(None),
+                            self_ref: Weak::default(),
+                        }
+                        .wrap());
+                    }
+                }
+                Err(FsError::EntryNotFound)
+            }
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        self.merged_entries()?
+            .get(id)
+            .cloned()
+            .ok_or(FsError::EntryNotFound)
+    }
+
+    fn io_control(&self, _cmd: u32, _data: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn fs(&self) -> Arc<FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &Any {
+        self
+    }
+}
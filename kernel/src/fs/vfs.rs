@@ -5,13 +5,72 @@ use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::any::Any;
-use core::mem::uninitialized;
 use core::str;
 use rcore_fs::dev::block_cache::BlockCache;
 use rcore_fs::vfs::*;
 use rcore_fs_sfs::{INodeId, SimpleFileSystem};
 use spin::RwLock;
 
+/// Cache key: identifies an inode by the identity of the `VirtualFS` Arc it
+/// lives in plus its filesystem-local inode number. The `VirtualFS` identity
+/// (rather than the filesystem, which can outlive a mount) is what `find`
+/// already partitions lookups by, so reusing it keeps stale entries from a
+/// torn-down mount from shadowing a fresh one at the same inode number.
+type InodeCacheKey = (usize, INodeId);
+
+fn vfs_identity(vfs: &Arc<RwLock<VirtualFS>>) -> usize {
+    Arc::as_ptr(vfs) as usize
+}
+
+lazy_static! {
+    /// Global cache of live `INodeContainer`s, so repeated path-walks and the
+    /// O(n) `find_name_by_child` scan share one container (and one
+    /// `metadata()` round-trip) per inode instead of allocating and re-reading
+    /// metadata on every lookup. Entries are `Weak` so a container with no
+    /// other owners is dropped normally; a dead `Weak` is just a cache miss.
+    static ref INODE_CACHE: RwLock<BTreeMap<InodeCacheKey, Weak<INodeContainer>>> =
+        RwLock::new(BTreeMap::new());
+}
+
+/// Look up `(vfs, inode)` in the global cache, building and inserting a new
+/// `INodeContainer` only on a miss (dead weak ref or no entry).
+fn cached_container(vfs: &Arc<RwLock<VirtualFS>>, inode: Arc<INode>) -> Result<Arc<INodeContainer>> {
+    let key = (vfs_identity(vfs), inode.metadata()?.inode);
+    if let Some(cached) = INODE_CACHE.read().get(&key).and_then(Weak::upgrade) {
+        return Ok(cached);
+    }
+    let container = INodeContainer {
+        inode,
+        vfs: vfs.clone(),
+        self_ref: Weak::default(),
+    }
+    .wrap();
+    INODE_CACHE.write().insert(key, Arc::downgrade(&container));
+    Ok(container)
+}
+
+/// Drop a single `(vfs, inode)` entry, e.g. because the inode was just
+/// unlinked or moved and must not be resurrected by a later lookup.
+fn invalidate_cached(vfs: &Arc<RwLock<VirtualFS>>, inode_id: INodeId) {
+    INODE_CACHE.write().remove(&(vfs_identity(vfs), inode_id));
+}
+
+/// Drop every entry belonging to `vfs`, e.g. because it is being unmounted
+/// (replaced in the parent's `mountpoints` map) and its whole subtree of
+/// cached containers must stop being handed out.
+fn invalidate_cached_vfs(vfs: &Arc<RwLock<VirtualFS>>) {
+    let id = vfs_identity(vfs);
+    let mut cache = INODE_CACHE.write();
+    let stale: Vec<InodeCacheKey> = cache
+        .keys()
+        .filter(|&&(vfs_id, _)| vfs_id == id)
+        .cloned()
+        .collect();
+    for key in stale {
+        cache.remove(&key);
+    }
+}
+
 /// The filesystem on which all the other filesystems are mounted
 pub struct VirtualFS {
     filesystem: Arc<FileSystem>,
@@ -109,19 +168,34 @@ impl VirtualFS {
     }
 
     pub fn root_inode(&self) -> Arc<INodeContainer> {
-        INodeContainer {
-            inode: self.filesystem.root_inode(),
-            vfs: self.self_ref.upgrade().unwrap(),
+        let vfs = self.self_ref.upgrade().unwrap();
+        cached_container(&vfs, self.filesystem.root_inode()).unwrap()
+    }
+
+    /// Clone-on-write copy of the mount tree rooted at `self`, used by
+    /// `MountNamespace::unshare`. Sub-mounts are cloned recursively so a
+    /// namespace boundary holds no matter how deep a later mount happens;
+    /// the filesystems themselves are shared, only the tree that records
+    /// where they are attached is duplicated.
+    pub fn clone_mount_tree(&self) -> VirtualFS {
+        let mut mountpoints = BTreeMap::new();
+        for (&inode_id, sub_vfs) in self.mountpoints.iter() {
+            let cloned = sub_vfs.read().clone_mount_tree();
+            mountpoints.insert(inode_id, cloned.wrap());
+        }
+        VirtualFS {
+            filesystem: self.filesystem.clone(),
+            mountpoints,
+            self_mountpoint: self.self_mountpoint.clone(),
             self_ref: Weak::default(),
         }
-        .wrap()
     }
 }
 
 #[derive(Clone)]
 pub struct PathConfig {
-    pub root: Arc<INodeContainer>, // ensured to be a dir.
-    pub cwd: Arc<INodeContainer>,  // ensured to be a dir.
+    pub ns: Arc<super::mountns::MountNamespace>, // owns this process's view of the mount tree.
+    pub cwd: Arc<INodeContainer>,                // ensured to be a dir.
 }
 
 /// The enum used to represent result of a successful path resolve.
@@ -148,9 +222,30 @@ pub enum PathResolveResult {
 // A better name is "Filesystem Selector", like the "segment selector".
 impl PathConfig {
     pub fn init_root() -> PathConfig {
-        let root = super::get_virtual_fs().read().root_inode();
-        let cwd = root.clone();
-        PathConfig { root, cwd }
+        let ns = super::mountns::MountNamespace::init();
+        let cwd = ns.root();
+        PathConfig { ns, cwd }
+    }
+
+    pub fn root(&self) -> Arc<INodeContainer> {
+        self.ns.root()
+    }
+
+    /// `fork()`: the child shares the parent's mount namespace.
+    pub fn fork(&self) -> PathConfig {
+        PathConfig {
+            ns: self.ns.share(),
+            cwd: self.cwd.clone(),
+        }
+    }
+
+    /// `unshare(CLONE_NEWNS)`: give this process a private, clone-on-write
+    /// copy of the mount tree so its later mounts (and a pivot such as
+    /// swapping an initramfs root for `/dev/sda`) are invisible to others.
+    pub fn unshare_mounts(&self) -> PathConfig {
+        let ns = self.ns.unshare();
+        let cwd = ns.root();
+        PathConfig { ns, cwd }
     }
 
     pub fn path_resolve(
@@ -193,9 +288,10 @@ impl PathConfig {
         depth_counter: usize,
     ) -> Result<PathResolveResult> {
         debug!("Path resolution {}", path);
+        let root = self.root();
         let mut cwd = Arc::clone({
             if path.starts_with("/") {
-                &self.root
+                &root
             } else {
                 if cwd.inode.metadata().unwrap().type_ != FileType::Dir {
                     return Err(FsError::NotDir);
@@ -255,11 +351,6 @@ impl PathConfig {
     }
 
     /// Resolves symbol by one layer.
-    ///
-    /// TODO:
-    ///   Linux proc fs has some anti-POSIX magics here, like /proc/[pid]/root.
-    ///   In those cases, those magics points to strange places, without following symlink rules.
-    ///   This hack can be achieved here.
     pub fn resolve_symbol(
         &self,
         cwd: &Arc<INodeContainer>,
@@ -296,6 +387,26 @@ impl PathConfig {
         let mut current_symbol = Arc::clone(symbol);
         let mut current_name = String::new();
         while current_symbol.inode.metadata().unwrap().type_ == FileType::SymLink {
+            if let Some(magic) = current_symbol.inode.as_any_ref().downcast_ref::<MagicLink>() {
+                // Magic links (e.g. /proc/[pid]/root) already know their destination
+                // container; splice it in directly instead of decoding and
+                // re-resolving a path, but still burn the same loop guards a
+                // regular symlink would so a magic link can't be used to dodge them.
+                if depth_counter == 0 || *follow_counter == 0 {
+                    return Err(FsError::SymLoop);
+                }
+                *follow_counter -= 1;
+                let target = magic.get_link_target();
+                if target.inode.metadata().unwrap().type_ == FileType::Dir {
+                    return Ok(PathResolveResult::IsDir { dir: target });
+                }
+                current_name = current_symbol_dir
+                    .find_name_by_child(&target)
+                    .unwrap_or_default();
+                current_symbol_dir = self.resolve_parent(&target);
+                current_symbol = target;
+                continue;
+            }
             let resolve_result = self.resolve_symbol(
                 &current_symbol_dir,
                 &current_symbol,
@@ -329,8 +440,9 @@ impl PathConfig {
         }
     }
     pub fn has_reached_root(&self, current: &INodeContainer) -> bool {
-        Arc::ptr_eq(&current.vfs, &self.root.vfs)
-            && self.root.inode.metadata().unwrap().inode == current.inode.metadata().unwrap().inode
+        let root = self.ns.root();
+        Arc::ptr_eq(&current.vfs, &root.vfs)
+            && root.inode.metadata().unwrap().inode == current.inode.metadata().unwrap().inode
     }
 }
 
@@ -359,8 +471,19 @@ impl INodeContainer {
         }
     }
 
-    /// Mount file system `fs` at this INode
+    /// Mount file system `fs` at this INode. Rejects non-directories (you
+    /// can't cross into a mounted filesystem through a file) and busy
+    /// mountpoints (mounting over an existing mount would strand it:
+    /// nothing could ever reach it again to `umount`).
     pub fn mount(self: &Arc<Self>, fs: Arc<FileSystem>) -> Result<Arc<RwLock<VirtualFS>>> {
+        if self.metadata()?.type_ != FileType::Dir {
+            return Err(FsError::NotDir);
+        }
+        let inode_id = self.inode.metadata()?.inode;
+        let mut self_fs = self.vfs.write();
+        if self_fs.mountpoints.contains_key(&inode_id) {
+            return Err(FsError::Busy);
+        }
         let new_fs = VirtualFS {
             filesystem: fs,
             mountpoints: BTreeMap::new(),
@@ -368,12 +491,35 @@ impl INodeContainer {
             self_ref: Weak::default(),
         }
         .wrap();
-        let inode_id = self.inode.metadata()?.inode;
-        let mut self_fs = self.vfs.write();
         self_fs.mountpoints.insert(inode_id, new_fs.clone());
+        drop(self_fs);
         Ok(new_fs)
     }
 
+    /// Unmount whatever is mounted directly at this INode. Rejects it if
+    /// nothing is mounted here, or if the mounted filesystem still has
+    /// sub-mounts of its own: those would otherwise be orphaned, reachable
+    /// from nowhere in the tree but still holding their `VirtualFS` alive.
+    pub fn umount(self: &Arc<Self>) -> Result<()> {
+        let inode_id = self.inode.metadata()?.inode;
+        let mut self_fs = self.vfs.write();
+        match self_fs.mountpoints.get(&inode_id) {
+            None => Err(FsError::InvalidParam),
+            Some(mounted) => {
+                if !mounted.read().mountpoints.is_empty() {
+                    return Err(FsError::Busy);
+                }
+                let mounted = self_fs.mountpoints.remove(&inode_id).unwrap();
+                drop(self_fs);
+                // The mountpoint's whole subtree of cached containers now
+                // points into a `VirtualFS` nothing in the tree can reach
+                // any more; drop it before some stale lookup hands it out.
+                invalidate_cached_vfs(&mounted);
+                Ok(())
+            }
+        }
+    }
+
     /// Get the root INode of the mounted fs at here.
     /// Return self if no mounted fs.
     fn overlaid_mount_point(&self) -> Arc<INodeContainer> {
@@ -438,24 +584,13 @@ impl INodeContainer {
                     }
                 } else {
                     // Not trespassing filesystem border. Parent and myself in the same filesystem.
-                    Ok(INodeContainer {
-                        inode: self.inode.find(name)?, // Going up is handled by the filesystem. A better API?
-                        vfs: self.vfs.clone(),
-                        self_ref: Weak::default(),
-                    }
-                    .wrap())
+                    cached_container(&self.vfs, self.inode.find(name)?) // Going up is handled by the filesystem. A better API?
                 }
             }
             _ => {
                 // Going down may trespass the filesystem border.
                 // An INode replacement is required here.
-                Ok(INodeContainer {
-                    inode: self.inode.find(name)?,
-                    vfs: self.vfs.clone(),
-                    self_ref: Weak::default(),
-                }
-                .wrap()
-                .overlaid_mount_point())
+                Ok(cached_container(&self.vfs, self.inode.find(name)?)?.overlaid_mount_point())
             }
         }
     }
@@ -465,18 +600,19 @@ impl INodeContainer {
         self: &Arc<INodeContainer>,
         child: &Arc<INodeContainer>,
     ) -> Result<String> {
-        for index in 0.. {
-            let name = self.inode.get_entry(index)?;
-            match name.as_ref() {
+        let mut cursor = 0;
+        while let Some((entry, next)) = self.inode.readdir(cursor)? {
+            cursor = next;
+            match entry.name.as_ref() {
                 "." | ".." => {}
                 _ => {
-                    let queryback = self.find(false, &name)?.overlaid_mount_point();
+                    let queryback = self.find(false, &entry.name)?.overlaid_mount_point();
                     // TODO: mountpoint check!
-                    debug!("checking name {}", name);
+                    debug!("checking name {}", entry.name);
                     if Arc::ptr_eq(&queryback.vfs, &child.vfs)
                         && queryback.inode.metadata()?.inode == child.inode.metadata()?.inode
                     {
-                        return Ok(name);
+                        return Ok(entry.name);
                     }
                 }
             }
@@ -485,6 +621,109 @@ impl INodeContainer {
     }
 }
 
+/// A symlink-like `INode` whose target is an already-resolved live container
+/// rather than a path to decode and re-resolve - the building block for
+/// procfs-style "magic" links (`/proc/[pid]/{cwd,exe,root,fd/N}`) that must
+/// point straight at a kernel object instead of obeying normal symlink rules.
+///
+/// `rcore_fs::vfs::INode` is an external trait we can't add a method to, so
+/// a magic link is recognized the same way cross-filesystem `link`/`move_`
+/// recognize "is this the same concrete type": `resolve_symbol_recursively`
+/// downcasts through `as_any_ref()`. Any filesystem that wants a magic entry
+/// just hands out a `MagicLink::new(target)` for that name instead of a
+/// regular `FileType::SymLink` inode.
+pub struct MagicLink {
+    target: Arc<INodeContainer>,
+}
+
+impl MagicLink {
+    pub fn new(target: Arc<INodeContainer>) -> Arc<MagicLink> {
+        Arc::new(MagicLink { target })
+    }
+
+    pub fn get_link_target(&self) -> Arc<INodeContainer> {
+        self.target.clone()
+    }
+}
+
+impl INode for MagicLink {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        // Never consulted: the resolver recognizes `MagicLink` before it
+        // would otherwise call `read_at` to decode a symlink's target.
+        Err(FsError::NotSupported)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: false,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let mut meta = self.target.inode.metadata()?;
+        meta.type_ = FileType::SymLink;
+        Ok(meta)
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resize(&self, _len: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn create(&self, _name: &str, _type_: FileType, _mode: u32) -> Result<Arc<INode>> {
+        Err(FsError::NotSupported)
+    }
+
+    fn link(&self, _name: &str, _other: &Arc<INode>) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn move_(&self, _old_name: &str, _target: &Arc<INode>, _new_name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn find(&self, _name: &str) -> Result<Arc<INode>> {
+        Err(FsError::NotDir)
+    }
+
+    fn get_entry(&self, _id: usize) -> Result<String> {
+        Err(FsError::NotDir)
+    }
+
+    fn io_control(&self, _cmd: u32, _data: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn fs(&self) -> Arc<FileSystem> {
+        self.target.inode.fs()
+    }
+
+    fn as_any_ref(&self) -> &Any {
+        self
+    }
+}
+
 impl FileSystem for VirtualFS {
     fn sync(&self) -> Result<()> {
         self.filesystem.sync()?;
@@ -546,15 +785,48 @@ impl INode for INodeContainer {
     }
 
     fn unlink(&self, name: &str) -> Result<()> {
-        self.inode.unlink(name)
+        // Grab the victim's id before unlinking so a cached container for it
+        // can be dropped; otherwise a later lookup of the same inode number
+        // (recycled by the underlying filesystem) would hand back a
+        // container for the inode that used to live here.
+        let unlinked_id = self
+            .inode
+            .find(name)
+            .ok()
+            .and_then(|i| i.metadata().ok())
+            .map(|m| m.inode);
+        self.inode.unlink(name)?;
+        if let Some(id) = unlinked_id {
+            invalidate_cached(&self.vfs, id);
+        }
+        Ok(())
     }
 
     fn move_(&self, old_name: &str, target: &Arc<INode>, new_name: &str) -> Result<()> {
-        let target = &target
-            .downcast_ref::<Self>()
-            .ok_or(FsError::NotSameFs)?
-            .inode;
-        self.inode.move_(old_name, target, new_name)
+        let target = target.downcast_ref::<Self>().ok_or(FsError::NotSameFs)?;
+        let moved_id = self
+            .inode
+            .find(old_name)
+            .ok()
+            .and_then(|i| i.metadata().ok())
+            .map(|m| m.inode);
+        // `new_name` may already exist at the destination and get replaced;
+        // that inode number can be recycled afterwards, so its cache entry
+        // (if any) must not survive the move either.
+        let replaced_id = target
+            .inode
+            .find(new_name)
+            .ok()
+            .and_then(|i| i.metadata().ok())
+            .map(|m| m.inode);
+        self.inode.move_(old_name, &target.inode, new_name)?;
+        if let Some(id) = moved_id {
+            invalidate_cached(&self.vfs, id);
+        }
+        if let Some(id) = replaced_id {
+            invalidate_cached(&target.vfs, id);
+        }
+        Ok(())
     }
 
     fn find(&self, name: &str) -> Result<Arc<INode>> {
@@ -565,6 +837,10 @@ impl INode for INodeContainer {
         self.inode.get_entry(id)
     }
 
+    fn readdir(&self, cursor: usize) -> Result<Option<(DirEntryInfo, usize)>> {
+        self.inode.readdir(cursor)
+    }
+
     fn io_control(&self, cmd: u32, data: usize) -> Result<()> {
         self.inode.io_control(cmd, data)
     }
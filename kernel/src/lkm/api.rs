@@ -1,12 +1,41 @@
 use super::*;
 use crate::lkm::structs::LoadedModule;
+use alloc::collections::btree_map::BTreeMap;
 use alloc::string::String;
 use alloc::sync::Arc;
 
-pub fn get_module(this_module: usize)->&'static mut LoadedModule{
+/// `key=value` parameters of whichever module is currently running its
+/// `init_module` entry point, set by `ModuleManager::init_module_internal`
+/// right before calling into it and cleared right after. Queried by the
+/// module itself through `lkm_api_get_param` - there's no other use for
+/// params once a module has finished initializing, so this isn't kept
+/// around per-module beyond that window.
+pub static mut CURRENT_MODULE_PARAMS: Option<BTreeMap<String, String>> = None;
+
+pub fn set_current_params(params: BTreeMap<String, String>) {
+    unsafe {
+        CURRENT_MODULE_PARAMS = Some(params);
+    }
+}
+
+pub fn clear_current_params() {
+    unsafe {
+        CURRENT_MODULE_PARAMS = None;
+    }
+}
+
+/// Recover the `Arc<LoadedModule>` behind an FFI `this_module`/`parent_module`
+/// handle. Those handles are the module's address inside its owning `Arc`
+/// (the same pointer `Arc::as_ptr` would give back), not a standalone
+/// allocation, so this doesn't materialize a second owner out of thin air -
+/// it bumps the existing `Arc`'s strong count and reconstructs a real owned
+/// handle from the raw pointer, mirroring what `Arc::into_raw`/`Arc::from_raw`
+/// pairs do for a clone taken across an FFI boundary.
+pub fn get_module(this_module: usize)->Arc<LoadedModule>{
     unsafe {
-        let ptr=this_module as *mut LoadedModule;
-        &mut(*ptr) as (&'static mut LoadedModule)
+        let ptr=this_module as *const LoadedModule;
+        Arc::increment_strong_count(ptr);
+        Arc::from_raw(ptr)
     }
 }
 
@@ -25,9 +54,24 @@ pub extern "C" fn lkm_api_pong()-> usize{
 
 #[no_mangle]
 pub extern "C" fn lkm_api_debug(this_module: usize){
+    use core::sync::atomic::Ordering;
     let module=get_module(this_module);
-    module.lock.lock();
-    println!("[LKM] Current module info: name={} version={} api_version={}\nref_count={} dep_count={}", module.info.name, module.info.version, module.info.api_version, Arc::strong_count(&module.using_counts), module.used_counts);
+    println!("[LKM] Current module info: name={} version={} api_version={}\nref_count={} dep_count={}", module.info.name, module.info.version, module.info.api_version, module.using_counts.load(Ordering::SeqCst), module.used_counts.load(Ordering::SeqCst));
+}
+
+/// Look up `name` among the `init_module` parameters passed to
+/// `sys_init_module`, returning a pointer to a NUL-terminated value or a
+/// null pointer if `name` wasn't given. Only valid to call from inside the
+/// currently-loading module's own `init_module`.
+#[no_mangle]
+pub extern "C" fn lkm_api_get_param(name: *const u8)->*const u8{
+    let name=unsafe{cstr_to_str(name, 256)};
+    unsafe{
+        match CURRENT_MODULE_PARAMS.as_ref().and_then(|params| params.get(&name)){
+            Some(value)=>value.as_ptr(),
+            None=>core::ptr::null()
+        }
+    }
 }
 
 #[no_mangle]
@@ -55,7 +55,11 @@ pub fn dev_minor(dev: u64)->u32{
 }
 pub struct CharDev{
     pub parent_module: Option<Arc<ModuleRef>>,
-    pub file_op: Arc<FileOperations>
+    pub file_op: Arc<FileOperations>,
+    /// Set for a device that wants `readdir`/`getdents`-style traversal via
+    /// `FileOperations::read_entry` instead of being treated as a flat file,
+    /// e.g. a synthetic `/dev/<bus>/<device>` tree a module builds on the fly.
+    pub is_dir: bool
 }
 
 
@@ -0,0 +1,196 @@
+//! Per-module fault isolation.
+//!
+//! A loaded module runs with its image mapped directly into kseg2 and its
+//! entry points reached through `transmute`'d function pointers (see
+//! `manager::init_module_internal`'s call into `init_module`) - a bad
+//! relocation or a null deref would otherwise fault straight through to a
+//! kernel panic. This
+//! registers each module's mapped range so a fault handler can recognize
+//! "that address belongs to a module" and unwind back out of the call into
+//! it instead, the same trap-handler-catches-fault pattern bytecode VMs use
+//! to sandbox native callouts.
+//!
+//! Wiring this in is the other half: whichever of `arch::x86_64::idt`'s
+//! `#PF`/`#GP` handlers ends up landing in this tree needs to call
+//! `report_fault` with the faulting instruction pointer before falling back
+//! to its usual panic - the same gap `kernelvm::handle_page_fault` is
+//! already left with for the same reason.
+
+use crate::sync::SpinLock as Mutex;
+use alloc::string::String;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+/// One loaded module's mapped range, registered at load and removed at
+/// unload, so `report_fault` can map "faulting address" back to "which
+/// module, if any, owns it".
+struct ModuleRange {
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+lazy_static! {
+    static ref MODULE_RANGES: Mutex<Vec<ModuleRange>> = Mutex::new(Vec::new());
+}
+
+/// Registers `[base, base + len)` as `name`'s mapped range. Called by
+/// `ModuleManager::init_module_internal` right after the module's
+/// `VirtualSpace` is sized, before any of its code runs.
+pub fn register_module_range(name: &str, base: usize, len: usize) {
+    MODULE_RANGES.lock().push(ModuleRange {
+        name: String::from(name),
+        start: base,
+        end: base + len,
+    });
+}
+
+/// Drops `name`'s registered range, e.g. once its `VirtualSpace` has been
+/// torn down - a fault at that address afterwards belongs to whatever's
+/// been mapped there since, not to the module that used to live there.
+pub fn unregister_module_range(name: &str) {
+    MODULE_RANGES.lock().retain(|r| r.name != name);
+}
+
+pub(crate) fn module_at(addr: usize) -> Option<String> {
+    MODULE_RANGES
+        .lock()
+        .iter()
+        .find(|r| addr >= r.start && addr < r.end)
+        .map(|r| r.name.clone())
+}
+
+/// Callee-saved registers plus the stack/frame pointer, snapshotted by
+/// `guarded_call` immediately before it calls into a module and restored by
+/// `report_fault` to unwind straight back to `guarded_call`'s caller - a
+/// `longjmp` scoped to exactly this one call site, rather than a
+/// general-purpose `setjmp`/`longjmp` facility.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct SavedContext {
+    rsp: usize,
+    rbp: usize,
+    rbx: usize,
+    r12: usize,
+    r13: usize,
+    r14: usize,
+    r15: usize,
+    /// The return address `lkm_setjmp` saw on the stack when it was called -
+    /// where `lkm_longjmp` resumes once registers are restored.
+    resume_rip: usize,
+}
+
+/// Saves the caller's callee-saved registers and stack/frame pointer into
+/// `*ctx`, then returns 0 - same as glibc's `setjmp` on the "just saved"
+/// path. `report_fault` resumes here (via `lkm_longjmp`) with a nonzero
+/// return value instead.
+#[naked]
+unsafe extern "C" fn lkm_setjmp(ctx: *mut SavedContext) -> i32 {
+    asm!(
+        "lea 8(%rsp), %rax
+         mov %rax, 0($0)
+         mov %rbp, 8($0)
+         mov %rbx, 16($0)
+         mov %r12, 24($0)
+         mov %r13, 32($0)
+         mov %r14, 40($0)
+         mov %r15, 48($0)
+         mov (%rsp), %rax
+         mov %rax, 56($0)
+         xor %eax, %eax
+         ret"
+        :: "{rdi}"(ctx) : "rax" : "volatile"
+    );
+    unreachable!()
+}
+
+/// Restores `*ctx`'s registers and jumps to its saved resume address with
+/// `code` in the return-value register - never returns to its own caller,
+/// only to whoever called the matching `lkm_setjmp`.
+#[naked]
+unsafe extern "C" fn lkm_longjmp(ctx: *const SavedContext, code: i32) -> ! {
+    asm!(
+        "mov 0($0), %rsp
+         mov 8($0), %rbp
+         mov 16($0), %rbx
+         mov 24($0), %r12
+         mov 32($0), %r13
+         mov 40($0), %r14
+         mov 48($0), %r15
+         mov $1, %eax
+         jmp *56($0)"
+        :: "{rdi}"(ctx), "{esi}"(code) :: "volatile"
+    );
+    unreachable!()
+}
+
+lazy_static! {
+    /// Per-CPU: the module name and saved context for whichever
+    /// `guarded_call` is currently on this CPU's stack, if any. `None` means
+    /// this CPU isn't inside a guarded module call, so a fault here is a
+    /// real kernel bug and should panic as usual rather than being caught.
+    static ref GUARD_STATE: Vec<Mutex<Option<(String, SavedContext)>>> =
+        (0..crate::arch::cpu::count()).map(|_| Mutex::new(None)).collect();
+}
+
+/// Calls the module function at `entry` (assumed to lie inside `module`'s
+/// range already registered via `register_module_range`) with fault
+/// isolation: if `module` faults before `entry` returns, `report_fault`
+/// unwinds straight back here instead of letting the fault propagate, and
+/// this returns `Err(())` rather than `Ok(())`. On `Err`, `module`'s range
+/// has already been unregistered by `report_fault`; the caller still owns
+/// tearing down whatever state it was keeping for this call (the module's
+/// `VirtualSpace`, any bookkeeping tied to it) since it may not have been
+/// published anywhere `report_fault` itself could safely reach.
+pub fn guarded_call(module: &str, entry: usize) -> Result<(), ()> {
+    let mut ctx = SavedContext::default();
+    let code = unsafe { lkm_setjmp(&mut ctx as *mut SavedContext) };
+    if code != 0 {
+        // Resumed via `report_fault`'s `lkm_longjmp`: the module has
+        // already been unmapped, this is just unwinding back out.
+        return Err(());
+    }
+    GUARD_STATE[crate::arch::cpu::id()]
+        .lock()
+        .replace((String::from(module), ctx));
+    let f: extern "C" fn() = unsafe { core::mem::transmute(entry) };
+    f();
+    GUARD_STATE[crate::arch::cpu::id()].lock().take();
+    Ok(())
+}
+
+/// Called by the (not yet wired up in this tree) `#PF`/`#GP` handler with
+/// the faulting instruction pointer. If it lies inside a module's
+/// registered range and this CPU is currently inside a `guarded_call` for
+/// that same module, unregisters the module's range and longjmps back to
+/// `guarded_call` instead of returning - deliberately doesn't touch
+/// `ModuleManager` itself here, since whoever called into the module
+/// (`init_module_internal`, `delete_module`) may already be holding its
+/// lock; they're the ones who see `guarded_call`'s `Err` and clean up the
+/// module's own bookkeeping once this unwinds back to them. Returns
+/// `false` (meaning "not ours, keep panicking as usual") if the fault
+/// doesn't match a guarded module call; a `true` return never actually
+/// happens, since the `longjmp` never comes back here - it's the type a
+/// handler wired up in the future would check, same as
+/// `kernelvm::handle_page_fault`'s `bool`.
+pub fn report_fault(fault_pc: usize) -> bool {
+    let name = match module_at(fault_pc) {
+        Some(name) => name,
+        None => return false,
+    };
+    let cpu = crate::arch::cpu::id();
+    let saved = GUARD_STATE[cpu].lock().take();
+    let (guard_name, ctx) = match saved {
+        Some(pair) if pair.0 == name => pair,
+        Some(pair) => {
+            // This CPU is guarding a different module than the one that
+            // just faulted - nothing registered here to unwind to.
+            *GUARD_STATE[cpu].lock() = Some(pair);
+            return false;
+        }
+        None => return false,
+    };
+    error!("[LKM] module {} faulted at {:#x}, unwinding its guarded call", guard_name, fault_pc);
+    unregister_module_range(&guard_name);
+    unsafe { lkm_longjmp(&ctx as *const SavedContext, 1) }
+}
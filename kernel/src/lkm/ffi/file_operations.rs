@@ -16,8 +16,13 @@ pub struct FileOperations {
     pub close: Option<extern "C" fn(file: usize)>
 }
 */
+use super::{
+    fserror_from_neg_errno, patch_i64_to_u64, patch_isize_to_empty, patch_isize_to_usize,
+    MetadataFFI, PrimitiveCast, TimespecFFI,
+};
 use crate::fs::{FileHandle, SeekFrom};
 use crate::lkm::cdev::{CDevManager, CharDev, FileOperations};
+use crate::lkm::structs::ModuleRef;
 use crate::rcore_fs::vfs::{FsError, Metadata, PollStatus};
 use alloc::string::String;
 use alloc::sync::Arc;
@@ -34,8 +39,17 @@ pub struct FileOperationsFFI {
     pub set_len: extern "C" fn(file: usize, len: u64) -> isize,
     pub sync_all: extern "C" fn(file: usize) -> isize,
     pub sync_data: extern "C" fn(file: usize) -> isize,
-    //pub metadata: extern "C" fn(file: usize) -> isize,
-    //pub read_entry: extern "C" fn(file: usize) -> isize,
+    /// Fill `*out` with this file's attributes, nanosecond timestamps
+    /// included (`MetadataFFI`'s `atime`/`mtime`/`ctime` are each a
+    /// `TimespecFFI { sec, nsec }`). `None` if the device has nothing of
+    /// its own to report, in which case `FileOperationsFFI::metadata`
+    /// falls back to the backing inode's metadata.
+    pub metadata: Option<extern "C" fn(file: usize, out: *mut MetadataFFI) -> isize>,
+    /// Write the next directory entry's name into `buf` (`len` bytes long,
+    /// not NUL-terminated) and return its length, `0` for end-of-stream, or
+    /// a negative `-errno` on failure. Only meaningful when the device was
+    /// registered with `CharDevFFI::is_dir` set.
+    pub read_entry: extern "C" fn(file: usize, buf: *mut u8, len: usize) -> isize,
     pub poll: extern "C" fn(file: usize) -> isize,
     pub io_control: extern "C" fn(file: usize, cmd: u32, data: usize) -> isize,
     pub close: extern "C" fn(file: usize),
@@ -45,15 +59,28 @@ pub struct CharDevFFI {
     parent_module: usize,
     file_operations_ffi: usize,
     major: u32,
+    /// Non-zero if this device should be traversed with `read_entry`
+    /// instead of treated as a flat file.
+    is_dir: u8,
 }
+/// `-ENODEV`, standard POSIX numbering - returned when `config.parent_module`
+/// is already on its way out (`ModuleRef::new` returned `None`) and the
+/// device can't be registered against it.
+const ENODEV: usize = (-19i64) as usize;
+
 #[no_mangle]
 pub extern "C" fn lkm_api_register_device(config: *const CharDevFFI) -> usize {
     let config = unsafe { &*config };
+    let parent_module = match ModuleRef::new(crate::lkm::api::get_module(config.parent_module)) {
+        Some(module_ref) => Arc::new(module_ref),
+        None => return ENODEV,
+    };
     let cdev: CharDev = CharDev {
-        parent_module: Some(crate::lkm::api::get_module(config.parent_module).grab()),
+        parent_module: Some(parent_module),
         file_op: Arc::new(
             unsafe { &*(config.file_operations_ffi as *const FileOperationsFFI) }.clone(),
         ),
+        is_dir: config.is_dir != 0,
     };
     CDevManager::get()
         .write()
@@ -61,25 +88,20 @@ pub extern "C" fn lkm_api_register_device(config: *const CharDevFFI) -> usize {
     0
 }
 
-fn patch_isize_to_usize(s: isize) -> Result<usize, FsError> {
+/// Decode a `poll` callback's return value as a readiness bitmask: bit 0 is
+/// `read`, bit 1 is `write`, bit 2 is `error`. A negative return is a real
+/// failure carrying `-errno` (same convention as the other
+/// `patch_isize_to_*` helpers), not just "nothing ready", so it's mapped
+/// to an error rather than an empty `PollStatus`.
+fn patch_isize_to_pollstatus(s: isize) -> Result<PollStatus, FsError> {
     if s < 0 {
-        Err(FsError::NotSupported)
-    } else {
-        Ok(s as usize)
-    }
-}
-fn patch_i64_to_u64(s: i64) -> Result<u64, FsError> {
-    if s < 0 {
-        Err(FsError::NotSupported)
-    } else {
-        Ok(s as u64)
-    }
-}
-fn patch_isize_to_empty(s: isize) -> Result<(), FsError> {
-    if s == 0 {
-        Ok(())
+        Err(fserror_from_neg_errno(s))
     } else {
-        Err(FsError::NotSupported)
+        Ok(PollStatus {
+            read: s & 0b001 != 0,
+            write: s & 0b010 != 0,
+            error: s & 0b100 != 0,
+        })
     }
 }
 impl FileOperations for FileOperationsFFI {
@@ -140,15 +162,44 @@ impl FileOperations for FileOperationsFFI {
     }
 
     fn metadata(&self, fh: &FileHandle) -> Result<Metadata, FsError> {
-        fh.inode_container.inode.metadata()
+        let callback = match self.metadata {
+            Some(f) => f,
+            None => return fh.inode_container.inode.metadata(),
+        };
+        let mut raw = MetadataFFI {
+            dev: 0,
+            inode: 0,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: TimespecFFI { sec: 0, nsec: 0 },
+            mtime: TimespecFFI { sec: 0, nsec: 0 },
+            ctime: TimespecFFI { sec: 0, nsec: 0 },
+            type_: 0,
+            mode: 0,
+            nlinks: 0,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        };
+        match callback(fh.user_data, &mut raw) {
+            s if s < 0 => Err(fserror_from_neg_errno(s)),
+            _ => Ok(Metadata::fromPrimitive(&raw)),
+        }
     }
 
     fn read_entry(&self, fh: &mut FileHandle) -> Result<String, FsError> {
-        Err(FsError::NotDir)
+        let mut buf = [0u8; 256];
+        match (self.read_entry)(fh.user_data, buf.as_mut_ptr(), buf.len()) {
+            len if len < 0 => Err(fserror_from_neg_errno(len)),
+            0 => Err(FsError::EntryNotFound),
+            len => String::from_utf8(buf[..len as usize].into())
+                .map_err(|_| FsError::InvalidParam),
+        }
     }
 
     fn poll(&self, fh: &FileHandle) -> Result<PollStatus, FsError> {
-        Err(FsError::NotSupported) //TODO: Important!
+        patch_isize_to_pollstatus((self.poll)(fh.user_data))
     }
 
     fn io_control(&self, fh: &FileHandle, cmd: u32, arg: usize) -> Result<(), FsError> {
@@ -0,0 +1,243 @@
+// Filesystem-driver ABI: lets a loaded .ko register a named filesystem
+// driver built entirely from C function pointers and opaque handles, the
+// same way `file_operations::lkm_api_register_device` registers a char
+// device. This is what would let an out-of-tree ext2 implementation (e.g.
+// built on the ext2-rs crate) be compiled as a module and `mount`ed at
+// runtime instead of statically linked in.
+
+use super::{patch_isize_to_empty, patch_isize_to_usize, FsInfoFFI, MetadataFFI, PollStatusFFI, PrimitiveCast};
+use crate::lkm::api::cstr_to_str;
+use crate::rcore_fs::vfs::{FileSystem, FileType, FsError, FsInfo, INode, Metadata, PollStatus, Result};
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::any::Any;
+use spin::RwLock;
+
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct FileSystemOperationsFFI {
+    /// Open/mount the device named by `source` (a NUL-terminated string,
+    /// `source_len` bytes not counting the terminator), returning an opaque
+    /// filesystem handle, or 0 on failure.
+    pub mount: extern "C" fn(source: *const u8, source_len: usize) -> usize,
+    /// Return an opaque inode handle for `fs`'s root directory.
+    pub root_inode: extern "C" fn(fs: usize) -> usize,
+    pub sync: extern "C" fn(fs: usize) -> isize,
+    pub statfs: extern "C" fn(fs: usize, out: *mut FsInfoFFI) -> isize,
+    pub read_at: extern "C" fn(inode: usize, offset: usize, buf: *mut u8, len: usize) -> isize,
+    pub write_at: extern "C" fn(inode: usize, offset: usize, buf: *const u8, len: usize) -> isize,
+    pub metadata: extern "C" fn(inode: usize, out: *mut MetadataFFI) -> isize,
+    pub poll: extern "C" fn(inode: usize, out: *mut PollStatusFFI) -> isize,
+}
+
+/// A `FileSystem` whose behavior is entirely forwarded through a
+/// `FileSystemOperationsFFI` table to a loaded module.
+pub struct FfiFileSystem {
+    handle: usize,
+    ops: FileSystemOperationsFFI,
+    self_ref: RwLock<Option<Arc<FfiFileSystem>>>,
+}
+
+// The vtable is a plain C function pointer table and `handle` is an opaque
+// module-owned id; the module is responsible for its own interior locking,
+// same as `FileOperationsFFI` assumes for `CharDev`.
+unsafe impl Send for FfiFileSystem {}
+unsafe impl Sync for FfiFileSystem {}
+
+impl FfiFileSystem {
+    fn new(handle: usize, ops: FileSystemOperationsFFI) -> Arc<FfiFileSystem> {
+        let fs = Arc::new(FfiFileSystem {
+            handle,
+            ops,
+            self_ref: RwLock::new(None),
+        });
+        *fs.self_ref.write() = Some(fs.clone());
+        fs
+    }
+}
+
+impl FileSystem for FfiFileSystem {
+    fn sync(&self) -> Result<()> {
+        patch_isize_to_empty((self.ops.sync)(self.handle))
+    }
+
+    fn root_inode(&self) -> Arc<INode> {
+        Arc::new(FfiINode {
+            handle: (self.ops.root_inode)(self.handle),
+            ops: self.ops.clone(),
+            fs: self.self_ref.read().clone().unwrap(),
+        })
+    }
+
+    fn info(&self) -> FsInfo {
+        let mut raw = FsInfoFFI {
+            bsize: 0,
+            frsize: 0,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 0,
+        };
+        (self.ops.statfs)(self.handle, &mut raw);
+        FsInfo::fromPrimitive(&raw)
+    }
+}
+
+/// An `INode` whose data operations are forwarded through the same
+/// `FileSystemOperationsFFI` table as its owning `FfiFileSystem`. Only
+/// `read_at`, `write_at`, `metadata`, `poll` and the syncs are backed by C
+/// calls; `find` and `get_entry` fail, so the root inode is the only one a
+/// mount exposes, and directory mutation is not part of this ABI.
+pub struct FfiINode {
+    handle: usize,
+    ops: FileSystemOperationsFFI,
+    fs: Arc<FfiFileSystem>,
+}
+
+unsafe impl Send for FfiINode {}
+unsafe impl Sync for FfiINode {}
+
+impl INode for FfiINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        patch_isize_to_usize((self.ops.read_at)(self.handle, offset, buf.as_mut_ptr(), buf.len()))
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        patch_isize_to_usize((self.ops.write_at)(self.handle, offset, buf.as_ptr(), buf.len()))
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        let mut raw = PollStatusFFI { tag_errorwriteread: 0 };
+        patch_isize_to_empty((self.ops.poll)(self.handle, &mut raw))?;
+        Ok(PollStatus::fromPrimitive(&raw))
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let mut raw = MetadataFFI {
+            dev: 0,
+            inode: self.handle,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: super::TimespecFFI { sec: 0, nsec: 0 },
+            mtime: super::TimespecFFI { sec: 0, nsec: 0 },
+            ctime: super::TimespecFFI { sec: 0, nsec: 0 },
+            type_: 0,
+            mode: 0,
+            nlinks: 0,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        };
+        patch_isize_to_empty((self.ops.metadata)(self.handle, &mut raw))?;
+        Ok(Metadata::fromPrimitive(&raw))
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        patch_isize_to_empty((self.ops.sync)(self.fs.handle))
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        patch_isize_to_empty((self.ops.sync)(self.fs.handle))
+    }
+
+    fn resize(&self, _len: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn create(&self, _name: &str, _type_: FileType, _mode: u32) -> Result<Arc<INode>> {
+        Err(FsError::NotSupported)
+    }
+
+    fn link(&self, _name: &str, _other: &Arc<INode>) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn move_(&self, _old_name: &str, _target: &Arc<INode>, _new_name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn find(&self, _name: &str) -> Result<Arc<INode>> {
+        Err(FsError::NotSupported)
+    }
+
+    fn get_entry(&self, _id: usize) -> Result<String> {
+        Err(FsError::NotDir)
+    }
+
+    fn io_control(&self, _cmd: u32, _data: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn fs(&self) -> Arc<FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &Any {
+        self
+    }
+}
+
+/// Registry of filesystem drivers registered by loaded modules, keyed by the
+/// name passed to `lkm_api_register_filesystem` (e.g. `"ext2"`).
+pub struct FsDriverManager {
+    drivers: BTreeMap<String, FileSystemOperationsFFI>,
+}
+
+pub static mut FS_DRIVER_MANAGER: Option<RwLock<FsDriverManager>> = None;
+
+impl FsDriverManager {
+    pub fn new() -> FsDriverManager {
+        FsDriverManager {
+            drivers: BTreeMap::new(),
+        }
+    }
+    pub fn init() {
+        unsafe {
+            FS_DRIVER_MANAGER = Some(RwLock::new(FsDriverManager::new()));
+        }
+    }
+    pub fn get() -> &'static RwLock<FsDriverManager> {
+        unsafe { FS_DRIVER_MANAGER.as_ref().unwrap() }
+    }
+    pub fn register(&mut self, name: &str, ops: FileSystemOperationsFFI) {
+        self.drivers.insert(String::from(name), ops);
+    }
+    /// Mount `source` through the driver named `name`, e.g. to back a
+    /// `sys_mount` call once the in-tree mount path understands this ABI.
+    pub fn mount(&self, name: &str, source: &str) -> Result<Arc<FileSystem>> {
+        let ops = self.drivers.get(name).ok_or(FsError::NotSupported)?;
+        let mut c_source = String::from(source);
+        c_source.push('\0');
+        let handle = (ops.mount)(c_source.as_ptr(), source.len());
+        if handle == 0 {
+            return Err(FsError::WrongFs);
+        }
+        Ok(FfiFileSystem::new(handle, ops.clone()))
+    }
+}
+
+#[repr(C)]
+pub struct FileSystemDriverFFI {
+    name: *const u8,
+    ops: FileSystemOperationsFFI,
+}
+
+#[no_mangle]
+pub extern "C" fn lkm_api_register_filesystem(config: *const FileSystemDriverFFI) -> usize {
+    let config = unsafe { &*config };
+    let name = unsafe { cstr_to_str(config.name, 256) };
+    FsDriverManager::get().write().register(&name, config.ops.clone());
+    0
+}
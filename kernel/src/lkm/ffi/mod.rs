@@ -1,6 +1,9 @@
 use crate::rcore_fs::vfs::{Metadata, Timespec, FileType, PollStatus, FsInfo};
 use crate::rcore_fs::vfs::{FsError};
 pub mod file_operations;
+pub mod filesystem;
+pub mod module_scheme;
+pub mod p9;
 
 pub trait PrimitiveCast<P>{
     fn fromPrimitive(p: &P)->Self;
@@ -206,36 +209,59 @@ impl PrimitiveCast<PollStatusFFI> for PollStatus{
         }
     }
 }
-fn patch_isize_to_error(s:isize)->FsError{
-    match s {
-        -1=>FsError::NotSupported,  //E_UNIMP, or E_INVAL
-        -2=>FsError::NotFile,       //E_ISDIR
-        -3=>FsError::IsDir,         //E_ISDIR, used only in link
-        -4=>FsError::NotDir,        //E_NOTDIR
-        -5=>FsError::EntryNotFound, //E_NOENT
-        -6=>FsError::EntryExist,    //E_EXIST
-        -7=>FsError::NotSameFs,     //E_XDEV
-        -8=>FsError::InvalidParam,  //E_INVAL
-        -9=>FsError::NoDeviceSpace, //E_NOSPC, but is defined and not used in the original ucore, which uses E_NO_MEM
-        -10=>FsError::DirRemoved,    //E_NOENT, when the current dir was remove by a previous unlink
-        -11=>FsError::DirNotEmpty,   //E_NOTEMPTY
-        -12=>FsError::WrongFs,       //E_INVAL, when we find the content on disk is wrong when opening the device
-        -13=>FsError::DeviceError,
-        -14=>FsError::SymLoop,        //E_LOOP, too many symlink follows.
-        -15=>FsError::NoDevice, //E_NXIO
-        _=>FsError::NotSupported
+/// A loaded module's FFI return value, when negative, carries `-errno`
+/// using the standard Unix/POSIX numbering (the same numbers the std sys
+/// layers use), not the ad hoc small negative codes older in-tree code
+/// used. This maps the ones a `FileSystem`/`INode`/`FileOperations` impl
+/// can plausibly hit onto the matching `FsError` variant; anything else
+/// (including `-errno` values this filesystem layer has no variant for)
+/// falls back to `FsError::NotSupported`, same as an unrecognized code
+/// always has.
+pub fn fserror_from_neg_errno(code: isize) -> FsError {
+    const EPERM: isize = 1;
+    const ENOENT: isize = 2;
+    const EIO: isize = 5;
+    const ENXIO: isize = 6;
+    const EAGAIN: isize = 11;
+    const EACCES: isize = 13;
+    const EBUSY: isize = 16;
+    const EEXIST: isize = 17;
+    const EXDEV: isize = 18;
+    const ENOTDIR: isize = 20;
+    const EISDIR: isize = 21;
+    const EINVAL: isize = 22;
+    const ENOSPC: isize = 28;
+    const ENOTEMPTY: isize = 39;
+    const ELOOP: isize = 40;
+    const ENOSYS: isize = 38;
+    match -code {
+        ENOENT => FsError::EntryNotFound,
+        EISDIR => FsError::IsDir,
+        ENOTDIR => FsError::NotDir,
+        EEXIST => FsError::EntryExist,
+        EXDEV => FsError::NotSameFs,
+        EINVAL => FsError::InvalidParam,
+        ENOSPC => FsError::NoDeviceSpace,
+        ENOTEMPTY => FsError::DirNotEmpty,
+        EIO => FsError::DeviceError,
+        EBUSY => FsError::Busy,
+        ELOOP => FsError::SymLoop,
+        ENXIO => FsError::NoDevice,
+        EAGAIN => FsError::Again,
+        EPERM | EACCES | ENOSYS => FsError::NotSupported,
+        _ => FsError::NotSupported,
     }
 }
 pub fn patch_isize_to_usize(s: isize)->Result<usize, FsError>{
     if s<0{
-        Err(patch_isize_to_error(s))
+        Err(fserror_from_neg_errno(s))
     }else{
         Ok(s as usize)
     }
 }
 pub fn patch_i64_to_u64(s: i64)->Result<u64, FsError>{
     if s<0{
-        Err(patch_isize_to_error(s as isize))
+        Err(fserror_from_neg_errno(s as isize))
     }else{
         Ok(s as u64)
     }
@@ -244,6 +270,6 @@ pub fn patch_isize_to_empty(s: isize)->Result<(), FsError>{
     if s==0{
         Ok(())
     }else{
-        Err(patch_isize_to_error(s))
+        Err(fserror_from_neg_errno(s))
     }
 }
\ No newline at end of file
@@ -0,0 +1,265 @@
+// Redox-style scheme ABI: lets a loaded .ko back a single pollable,
+// byte-stream device endpoint through one dispatch callback instead of the
+// per-operation table `FileOperationsFFI`/`CharDev` use. Modeled directly on
+// redox's scheme trait - every call is a fixed-width packet carrying an
+// opcode, the fd `open` handed back, an offset, and a buffer pointer/length,
+// answered with a single `isize` (a byte count, a new fd, or a negative
+// `-errno` decoded by `patch_isize_to_*`) - which is a better fit than
+// `FileSystemOperationsFFI`'s per-call table for drivers that are themselves
+// ports of a redox scheme rather than a from-scratch rCore driver.
+
+use super::{patch_isize_to_usize, MetadataFFI, PollStatusFFI, PrimitiveCast, TimespecFFI};
+use crate::lkm::api::cstr_to_str;
+use crate::rcore_fs::vfs::{FileSystem, FileType, FsError, FsInfo, INode, Metadata, PollStatus, Result};
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::any::Any;
+use spin::RwLock;
+
+pub const SCHEME_OP_OPEN: u8 = 0;
+pub const SCHEME_OP_READ: u8 = 1;
+pub const SCHEME_OP_WRITE: u8 = 2;
+pub const SCHEME_OP_SEEK: u8 = 3;
+pub const SCHEME_OP_POLL: u8 = 4;
+pub const SCHEME_OP_CLOSE: u8 = 5;
+
+/// `whence` values for `SCHEME_OP_SEEK`, carried in a packet's otherwise
+/// unused `len` field (POSIX `SEEK_*` numbering).
+const SEEK_END: usize = 2;
+
+/// One call into a module's scheme dispatch callback. `fd` is whatever
+/// `SCHEME_OP_OPEN` returned, echoed back on every later call the same way
+/// a redox scheme's syscalls carry the fd `open` returned; `offset` and
+/// `len` are reused by `SCHEME_OP_SEEK` as the target position and `whence`
+/// since a packet has no field to spare for it.
+#[repr(C)]
+pub struct SchemePacket {
+    pub opcode: u8,
+    pub fd: usize,
+    pub offset: usize,
+    pub buf: *mut u8,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct ModuleSchemeFFI {
+    name: *const u8,
+    dispatch: extern "C" fn(packet: *const SchemePacket) -> isize,
+}
+
+/// The scheme's backing "filesystem" - there's no directory tree behind a
+/// scheme, just the one endpoint `root_inode` hands back, but `INode::fs`
+/// still needs an `Arc<FileSystem>` to point at, same reason
+/// `FfiFileSystem`/`FfiINode` keep a `self_ref` pair in `filesystem.rs`.
+pub struct ModuleScheme {
+    name: String,
+    dispatch: extern "C" fn(packet: *const SchemePacket) -> isize,
+    self_ref: RwLock<Option<Arc<ModuleScheme>>>,
+}
+
+unsafe impl Send for ModuleScheme {}
+unsafe impl Sync for ModuleScheme {}
+
+impl ModuleScheme {
+    fn new(name: String, dispatch: extern "C" fn(packet: *const SchemePacket) -> isize) -> Arc<ModuleScheme> {
+        let scheme = Arc::new(ModuleScheme {
+            name,
+            dispatch,
+            self_ref: RwLock::new(None),
+        });
+        *scheme.self_ref.write() = Some(scheme.clone());
+        scheme
+    }
+
+    fn call(&self, opcode: u8, fd: usize, offset: usize, buf: *mut u8, len: usize) -> isize {
+        let packet = SchemePacket { opcode, fd, offset, buf, len };
+        (self.dispatch)(&packet)
+    }
+}
+
+impl FileSystem for ModuleScheme {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Opens a fresh fd on every call, same as a redox scheme being opened
+    /// again by a new client - there's no single persistent root handle to
+    /// hand back.
+    fn root_inode(&self) -> Arc<INode> {
+        let fd = self.call(SCHEME_OP_OPEN, 0, 0, core::ptr::null_mut(), 0);
+        Arc::new(ModuleSchemeINode {
+            fd: if fd < 0 { 0 } else { fd as usize },
+            fs: self.self_ref.read().clone().unwrap(),
+        })
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            bsize: 0,
+            frsize: 0,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 256,
+        }
+    }
+}
+
+/// An `INode` addressing one fd opened on a `ModuleScheme`. Closes that fd
+/// on drop - the last of the six opcodes the module's dispatch callback handles, with no
+/// other natural call site of its own (unlike `CharDev`, a scheme-backed
+/// `INode` isn't owned by a `FileHandle` that could call `close` for it).
+pub struct ModuleSchemeINode {
+    fd: usize,
+    fs: Arc<ModuleScheme>,
+}
+
+unsafe impl Send for ModuleSchemeINode {}
+unsafe impl Sync for ModuleSchemeINode {}
+
+impl INode for ModuleSchemeINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        patch_isize_to_usize(self.fs.call(SCHEME_OP_READ, self.fd, offset, buf.as_mut_ptr(), buf.len()))
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        patch_isize_to_usize(self.fs.call(SCHEME_OP_WRITE, self.fd, offset, buf.as_ptr() as *mut u8, buf.len()))
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        let status = self.fs.call(SCHEME_OP_POLL, self.fd, 0, core::ptr::null_mut(), 0);
+        // A negative result is a `-errno`, which `patch_isize_to_usize`
+        // decodes into an `FsError`; anything else is the packed
+        // `PollStatusFFI` byte.
+        patch_isize_to_usize(status)?;
+        Ok(PollStatus::fromPrimitive(&PollStatusFFI { tag_errorwriteread: status as u8 }))
+    }
+
+    /// There's no metadata callback in this ABI - `SCHEME_OP_SEEK` to the
+    /// end (whence = `SEEK_END`, offset 0) doubles as the only way to learn
+    /// a scheme's size, the same trick a redox `fstat` over a pipe-like
+    /// scheme falls back to.
+    fn metadata(&self) -> Result<Metadata> {
+        let size = patch_isize_to_usize(self.fs.call(SCHEME_OP_SEEK, self.fd, 0, core::ptr::null_mut(), SEEK_END))?;
+        Ok(Metadata::fromPrimitive(&MetadataFFI {
+            dev: 0,
+            inode: self.fd,
+            size,
+            blk_size: 1,
+            blocks: size,
+            atime: TimespecFFI { sec: 0, nsec: 0 },
+            mtime: TimespecFFI { sec: 0, nsec: 0 },
+            ctime: TimespecFFI { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice.toPrimitive(),
+            mode: 0o666,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        }))
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn resize(&self, _len: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn create(&self, _name: &str, _type_: FileType, _mode: u32) -> Result<Arc<INode>> {
+        Err(FsError::NotSupported)
+    }
+
+    fn link(&self, _name: &str, _other: &Arc<INode>) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn move_(&self, _old_name: &str, _target: &Arc<INode>, _new_name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn find(&self, _name: &str) -> Result<Arc<INode>> {
+        Err(FsError::NotSupported)
+    }
+
+    fn get_entry(&self, _id: usize) -> Result<String> {
+        Err(FsError::NotDir)
+    }
+
+    fn io_control(&self, _cmd: u32, _data: usize) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    fn fs(&self) -> Arc<FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &Any {
+        self
+    }
+}
+
+impl Drop for ModuleSchemeINode {
+    fn drop(&mut self) {
+        self.fs.call(SCHEME_OP_CLOSE, self.fd, 0, core::ptr::null_mut(), 0);
+    }
+}
+
+/// Registry of scheme endpoints registered by loaded modules, keyed by the
+/// name passed to `lkm_api_register_scheme` (e.g. `"null"`, `"zero"`).
+pub struct ModuleSchemeManager {
+    schemes: BTreeMap<String, Arc<ModuleScheme>>,
+}
+
+pub static mut MODULE_SCHEME_MANAGER: Option<RwLock<ModuleSchemeManager>> = None;
+
+impl ModuleSchemeManager {
+    pub fn new() -> ModuleSchemeManager {
+        ModuleSchemeManager { schemes: BTreeMap::new() }
+    }
+
+    pub fn init() {
+        unsafe {
+            MODULE_SCHEME_MANAGER = Some(RwLock::new(ModuleSchemeManager::new()));
+        }
+    }
+
+    pub fn get() -> &'static RwLock<ModuleSchemeManager> {
+        unsafe { MODULE_SCHEME_MANAGER.as_ref().unwrap() }
+    }
+
+    pub fn register(&mut self, name: &str, dispatch: extern "C" fn(packet: *const SchemePacket) -> isize) {
+        self.schemes.insert(String::from(name), ModuleScheme::new(String::from(name), dispatch));
+    }
+
+    /// Open the scheme named `name`, e.g. to back a `sys_mount`/device-node
+    /// lookup once something in the in-tree VFS calls this by name.
+    pub fn open(&self, name: &str) -> Result<Arc<INode>> {
+        let scheme = self.schemes.get(name).ok_or(FsError::NoDevice)?;
+        Ok(scheme.root_inode())
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn lkm_api_register_scheme(config: *const ModuleSchemeFFI) -> usize {
+    let config = unsafe { &*config };
+    let name = unsafe { cstr_to_str(config.name, 256) };
+    ModuleSchemeManager::get().write().register(&name, config.dispatch);
+    0
+}
@@ -0,0 +1,380 @@
+//! A `FileOperations` backend that speaks 9P2000.L to a remote server over
+//! an already-connected channel (virtio-9p, a socket, ...), reusing the
+//! same little-endian wire encode/decode and message-type constants
+//! `fs::ninep`'s server half defines instead of redefining the protocol.
+//! This lets a host or networked directory be mounted in as a device the
+//! same way a local char device backed by `FileOperationsFFI` is.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, AtomicU32, Ordering};
+
+use crate::fs::ninep::{
+    Decoder, Encoder, P9_RDWR, P9_SETATTR_SIZE, QTDIR, QTSYMLINK, RLERROR, TATTACH, TCLUNK,
+    TGETATTR, TLOPEN, TREAD, TREADDIR, TSETATTR, TVERSION, TWALK, TWRITE,
+};
+use crate::fs::{FileHandle, SeekFrom};
+use crate::lkm::cdev::{CDevManager, CharDev, FileOperations};
+use crate::lkm::ffi::fserror_from_neg_errno;
+use crate::rcore_fs::vfs::{FileType, FsError, Metadata, PollStatus, Timespec};
+use crate::sync::SpinNoIrqLock as Mutex;
+
+/// 9P2000.L `Tfsync`/`Rfsync` - not among the message types `fs::ninep`'s
+/// hand-rolled server dispatches on yet, since nothing needed it before
+/// this client. A server that doesn't recognize it answers `Rlerror` with
+/// `ENOTSUP`, same as any other message `Ninep9Server::dispatch` falls
+/// through on.
+const TFSYNC: u8 = 50;
+
+/// The transport a `P9FileOperations` speaks messages over: `send` hands a
+/// fully-framed message (`size[4] type[1] tag[2] body`, little-endian) to
+/// the channel `conn` identifies, and `recv` blocks for the next one and
+/// copies it (same framing) into `buf`. Either returns the byte count or a
+/// negative `-errno` on failure. What `conn` actually is - a virtio-9p
+/// queue index, a socket fd, ... - is entirely up to the module; this side
+/// only ever treats it as an opaque handle, the same way `CharDevFFI`
+/// treats a device's `file_operations_ffi` pointer.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct P9TransportFFI {
+    pub send: extern "C" fn(conn: usize, buf: *const u8, len: usize) -> isize,
+    pub recv: extern "C" fn(conn: usize, buf: *mut u8, len: usize) -> isize,
+}
+
+/// Largest message this client will send or accept in one frame.
+const MAX_MSG: usize = 8192;
+
+fn file_type_of_qid(t: u8) -> FileType {
+    match t {
+        QTDIR => FileType::Dir,
+        QTSYMLINK => FileType::SymLink,
+        _ => FileType::File,
+    }
+}
+
+/// A `FileOperations` backend for one attached, already-walked-to remote
+/// path (`root_fid`). `open()` mints a fresh fid via `Twalk`+`Tlopen` and
+/// hands it back as the opaque handle every other call is addressed with
+/// through `FileHandle::user_data`, the same convention
+/// `FileOperationsFFI` uses for its own `file` handles. Since `read`/
+/// `write`/`seek` have no offset of their own to consult (9P has no
+/// server-side cursor, and `FileHandle`'s own offset isn't visible to a
+/// `FileOperations` impl), the current byte offset - or, for a directory
+/// fid, the next `Treaddir` entry index - is tracked here per fid instead.
+pub struct P9FileOperations {
+    conn: usize,
+    transport: P9TransportFFI,
+    root_fid: u32,
+    next_fid: AtomicU32,
+    next_tag: AtomicU16,
+    /// Serializes the request/response round trip: this transport has no
+    /// multiplexing of its own, so only one message may be in flight at a
+    /// time no matter how many fids are open concurrently.
+    io: Mutex<()>,
+    cursors: Mutex<BTreeMap<u32, u64>>,
+}
+
+impl P9FileOperations {
+    /// Handshake (`Tversion`), attach as `aname`, then walk down `path`
+    /// (already split into components) to arrive at the file or directory
+    /// this instance backs.
+    pub fn new(
+        conn: usize,
+        transport: P9TransportFFI,
+        aname: &str,
+        path: &[&str],
+    ) -> Result<P9FileOperations, FsError> {
+        const ROOT_FID: u32 = 0;
+        const NOFID: u32 = !0;
+        let mut ops = P9FileOperations {
+            conn,
+            transport,
+            root_fid: ROOT_FID,
+            next_fid: AtomicU32::new(ROOT_FID + 1),
+            next_tag: AtomicU16::new(1),
+            io: Mutex::new(()),
+            cursors: Mutex::new(BTreeMap::new()),
+        };
+
+        let mut enc = Encoder::default();
+        enc.u32(MAX_MSG as u32).str("9P2000.L");
+        ops.request(TVERSION, &enc.buf)?;
+
+        let mut enc = Encoder::default();
+        enc.u32(ROOT_FID).u32(NOFID).str("").str(aname).u32(NOFID);
+        ops.request(TATTACH, &enc.buf)?;
+
+        let mut cur = ROOT_FID;
+        for name in path {
+            let next = ops.next_fid.fetch_add(1, Ordering::SeqCst);
+            let mut enc = Encoder::default();
+            enc.u32(cur).u32(next).u16(1).str(name);
+            ops.request(TWALK, &enc.buf)?;
+            cur = next;
+        }
+        ops.root_fid = cur;
+        Ok(ops)
+    }
+
+    /// Frame `body` as message `msg_type`, send it, and block for the
+    /// matching reply, turning an `Rlerror` into the `FsError` its `-errno`
+    /// maps to via the same mapping `lkm::ffi::fserror_from_neg_errno`
+    /// gives every other FFI boundary.
+    fn request(&self, msg_type: u8, body: &[u8]) -> Result<(u8, Vec<u8>), FsError> {
+        let _guard = self.io.lock();
+        let tag = self.next_tag.fetch_add(1, Ordering::SeqCst);
+        let mut framed = Vec::with_capacity(7 + body.len());
+        framed.extend_from_slice(&((7 + body.len()) as u32).to_le_bytes());
+        framed.push(msg_type);
+        framed.extend_from_slice(&tag.to_le_bytes());
+        framed.extend_from_slice(body);
+        let sent = (self.transport.send)(self.conn, framed.as_ptr(), framed.len());
+        if sent < 0 {
+            return Err(fserror_from_neg_errno(sent));
+        }
+
+        let mut buf = vec![0u8; MAX_MSG];
+        let n = match (self.transport.recv)(self.conn, buf.as_mut_ptr(), buf.len()) {
+            n if n < 0 => return Err(fserror_from_neg_errno(n)),
+            n => n as usize,
+        };
+        let mut d = Decoder::new(&buf[..n]);
+        let _size = d.u32();
+        let rtype = d.u8();
+        let _tag = d.u16();
+        let rbody = d.bytes(n - 7).to_vec();
+        if rtype == RLERROR {
+            let mut ed = Decoder::new(&rbody);
+            let ecode = ed.u32();
+            return Err(fserror_from_neg_errno(-(ecode as isize)));
+        }
+        Ok((rtype, rbody))
+    }
+
+    fn cursor(&self, fid: u32) -> u64 {
+        *self.cursors.lock().get(&fid).unwrap_or(&0)
+    }
+}
+
+impl FileOperations for P9FileOperations {
+    fn open(&self) -> usize {
+        let fid = self.next_fid.fetch_add(1, Ordering::SeqCst);
+        let mut enc = Encoder::default();
+        enc.u32(self.root_fid).u32(fid).u16(0);
+        if self.request(TWALK, &enc.buf).is_err() {
+            return 0;
+        }
+        let mut enc = Encoder::default();
+        enc.u32(fid).u32(P9_RDWR);
+        if self.request(TLOPEN, &enc.buf).is_err() {
+            return 0;
+        }
+        fid as usize
+    }
+
+    fn read(&self, fh: &mut FileHandle, buf: &mut [u8]) -> Result<usize, FsError> {
+        let fid = fh.user_data as u32;
+        let n = self.read_at(fh, self.cursor(fid) as usize, buf)?;
+        *self.cursors.lock().entry(fid).or_insert(0) += n as u64;
+        Ok(n)
+    }
+
+    fn read_at(&self, fh: &mut FileHandle, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        let fid = fh.user_data as u32;
+        let mut enc = Encoder::default();
+        enc.u32(fid).u64(offset as u64).u32(buf.len() as u32);
+        let (_, body) = self.request(TREAD, &enc.buf)?;
+        let mut d = Decoder::new(&body);
+        let count = d.u32() as usize;
+        buf[..count].copy_from_slice(d.bytes(count));
+        Ok(count)
+    }
+
+    fn write(&self, fh: &mut FileHandle, buf: &[u8]) -> Result<usize, FsError> {
+        let fid = fh.user_data as u32;
+        let n = self.write_at(fh, self.cursor(fid) as usize, buf)?;
+        *self.cursors.lock().entry(fid).or_insert(0) += n as u64;
+        Ok(n)
+    }
+
+    fn write_at(&self, fh: &mut FileHandle, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        let fid = fh.user_data as u32;
+        let mut enc = Encoder::default();
+        enc.u32(fid).u64(offset as u64).u32(buf.len() as u32).bytes(buf);
+        let (_, body) = self.request(TWRITE, &enc.buf)?;
+        let mut d = Decoder::new(&body);
+        Ok(d.u32() as usize)
+    }
+
+    fn seek(&self, fh: &mut FileHandle, pos: SeekFrom) -> Result<u64, FsError> {
+        let fid = fh.user_data as u32;
+        let cur = self.cursor(fid);
+        let new_offset = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => (cur as i64 + offset) as u64,
+            SeekFrom::End(offset) => (self.metadata(fh)?.size as i64 + offset) as u64,
+        };
+        self.cursors.lock().insert(fid, new_offset);
+        Ok(new_offset)
+    }
+
+    fn set_len(&self, fh: &mut FileHandle, len: u64) -> Result<(), FsError> {
+        let fid = fh.user_data as u32;
+        let mut enc = Encoder::default();
+        enc.u32(fid)
+            .u32(P9_SETATTR_SIZE)
+            .u32(0) // mode
+            .u32(0) // uid
+            .u32(0) // gid
+            .u64(len)
+            .u64(0)
+            .u64(0) // atime
+            .u64(0)
+            .u64(0); // mtime
+        self.request(TSETATTR, &enc.buf)?;
+        Ok(())
+    }
+
+    fn sync_all(&self, fh: &mut FileHandle) -> Result<(), FsError> {
+        let fid = fh.user_data as u32;
+        let mut enc = Encoder::default();
+        enc.u32(fid).u32(0); // datasync = 0: flush data and metadata
+        self.request(TFSYNC, &enc.buf)?;
+        Ok(())
+    }
+
+    fn sync_data(&self, fh: &mut FileHandle) -> Result<(), FsError> {
+        let fid = fh.user_data as u32;
+        let mut enc = Encoder::default();
+        enc.u32(fid).u32(1); // datasync = 1: data only
+        self.request(TFSYNC, &enc.buf)?;
+        Ok(())
+    }
+
+    fn metadata(&self, fh: &FileHandle) -> Result<Metadata, FsError> {
+        let fid = fh.user_data as u32;
+        let mut enc = Encoder::default();
+        enc.u32(fid).u64(0); // request_mask: this server always reports everything
+        let (_, body) = self.request(TGETATTR, &enc.buf)?;
+        let mut d = Decoder::new(&body);
+        let _valid = d.u64();
+        let qid_type = d.u8();
+        let _qid_version = d.u32();
+        let qid_path = d.u64();
+        let mode = d.u32();
+        let uid = d.u32();
+        let gid = d.u32();
+        let nlinks = d.u64();
+        let rdev = d.u64();
+        let size = d.u64();
+        let blk_size = d.u64();
+        let blocks = d.u64();
+        let atime_sec = d.u64();
+        let atime_nsec = d.u64();
+        let mtime_sec = d.u64();
+        let mtime_nsec = d.u64();
+        let ctime_sec = d.u64();
+        let ctime_nsec = d.u64();
+        Ok(Metadata {
+            dev: 0,
+            inode: qid_path as usize,
+            size: size as usize,
+            blk_size: blk_size as usize,
+            blocks: blocks as usize,
+            atime: Timespec { sec: atime_sec as i64, nsec: atime_nsec as i32 },
+            mtime: Timespec { sec: mtime_sec as i64, nsec: mtime_nsec as i32 },
+            ctime: Timespec { sec: ctime_sec as i64, nsec: ctime_nsec as i32 },
+            type_: file_type_of_qid(qid_type),
+            mode: mode as u16,
+            nlinks: nlinks as usize,
+            uid: uid as usize,
+            gid: gid as usize,
+            rdev,
+        })
+    }
+
+    fn read_entry(&self, fh: &mut FileHandle) -> Result<String, FsError> {
+        let fid = fh.user_data as u32;
+        let idx = self.cursor(fid);
+        let mut enc = Encoder::default();
+        enc.u32(fid).u64(idx).u32(MAX_MSG as u32);
+        let (_, body) = self.request(TREADDIR, &enc.buf)?;
+        let mut d = Decoder::new(&body);
+        let total_len = d.u32() as usize;
+        if total_len == 0 {
+            return Err(FsError::EntryNotFound);
+        }
+        let _qid_type = d.u8();
+        let _qid_version = d.u32();
+        let _qid_path = d.u64();
+        let next_offset = d.u64();
+        let _dirent_type = d.u8();
+        let name = d.str();
+        self.cursors.lock().insert(fid, next_offset);
+        Ok(name)
+    }
+
+    fn poll(&self, _fh: &FileHandle) -> Result<PollStatus, FsError> {
+        // Every call is a blocking round trip over `io`, so by the time one
+        // returns at all the fid is always ready for the next - there's no
+        // separate readiness state to report, same as `ext2`/`schemefs`.
+        Ok(PollStatus {
+            read: true,
+            write: true,
+            error: false,
+        })
+    }
+
+    fn io_control(&self, _fh: &FileHandle, _cmd: u32, _arg: usize) -> Result<(), FsError> {
+        Err(FsError::NotSupported)
+    }
+
+    fn close(&self, data: usize) {
+        let fid = data as u32;
+        self.cursors.lock().remove(&fid);
+        let mut enc = Encoder::default();
+        enc.u32(fid);
+        let _ = self.request(TCLUNK, &enc.buf);
+    }
+}
+
+/// Wire format for `lkm_api_register_p9_device`'s `path` field: a single
+/// `/`-separated string naming the path to walk from the attach point,
+/// e.g. `b"export/logs"` to mount the remote `export/logs` directory.
+#[repr(C)]
+pub struct P9DeviceFFI {
+    major: u32,
+    conn: usize,
+    transport: P9TransportFFI,
+    aname: *const u8,
+    aname_len: usize,
+    path: *const u8,
+    path_len: usize,
+}
+
+#[no_mangle]
+pub extern "C" fn lkm_api_register_p9_device(config: *const P9DeviceFFI) -> usize {
+    let config = unsafe { &*config };
+    let aname = unsafe { core::slice::from_raw_parts(config.aname, config.aname_len) };
+    let aname = core::str::from_utf8(aname).unwrap_or("");
+    let path = unsafe { core::slice::from_raw_parts(config.path, config.path_len) };
+    let path = core::str::from_utf8(path).unwrap_or("");
+    let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    let ops = match P9FileOperations::new(config.conn, config.transport.clone(), aname, &components) {
+        Ok(ops) => ops,
+        Err(_) => return 0,
+    };
+    CDevManager::get().write().registerDevice(
+        config.major,
+        CharDev {
+            parent_module: None,
+            file_op: Arc::new(ops),
+            is_dir: false,
+        },
+    );
+    0
+}
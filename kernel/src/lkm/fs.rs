@@ -7,7 +7,8 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::any::Any;
 use core::mem::uninitialized;
-use rcore_fs::vfs::{FileSystem, FsError, Result};
+use core::str;
+use rcore_fs::vfs::{FileSystem, FsError, INode, Result};
 use spin::RwLock;
 
 pub struct FileSystemManager {
@@ -27,7 +28,12 @@ impl FileSystemManager {
         }
         let mut fsm = Self::get().write();
         //fsm.registerFileSystem("sfs", crate::rcore_fs_sfs::SimpleFileSystemType{});
-        //RamFSBehav::registerRamFS();
+        drop(fsm);
+        RamFSBehav::registerRamFS();
+        Ext2FsType::registerExt2();
+        Iso9660FsType::registerIso9660();
+        SchemeFsType::registerSchemeFs();
+        OverlayFsType::registerOverlayFs();
     }
     pub fn get() -> &'static RwLock<FileSystemManager> {
         unsafe { FS_MANAGER.as_ref().unwrap() }
@@ -60,3 +66,214 @@ pub trait FileSystemType {
         data: usize,
     ) -> Result<Arc<FileSystem>>;
 }
+
+/// `mount`'s `data` argument, same as `sys_mount`'s raw `void *data` on
+/// Linux, read as a pointer to this struct when `"ramfs"` is given an
+/// archive to unpack rather than starting out empty.
+#[repr(C)]
+pub struct RamFSMountData {
+    pub archive: *const u8,
+    pub len: usize,
+}
+
+/// `"ramfs"` filesystem type: an in-memory tree with no backing device,
+/// same shape as `TmpFS` already builds `fs::initramfs` out of for the
+/// Limine-supplied boot initramfs - this just exposes that through
+/// `sys_mount` instead of only at boot, and lets the caller hand it an
+/// archive (a `RamFSMountData` pointer in `data`) to materialize instead of
+/// mounting an empty tree.
+pub struct RamFSBehav;
+
+impl RamFSBehav {
+    pub fn registerRamFS() {
+        FileSystemManager::get()
+            .write()
+            .registerFileSystem("ramfs", RamFSBehav);
+    }
+}
+
+impl FileSystemType for RamFSBehav {
+    fn mount(
+        &self,
+        _syscall: &mut Syscall,
+        _source: &str,
+        _flags: u64,
+        data: usize,
+    ) -> Result<Arc<FileSystem>> {
+        if data == 0 {
+            return Ok(crate::fs::tmpfs::TmpFS::new());
+        }
+        let desc = unsafe { &*(data as *const RamFSMountData) };
+        let archive = unsafe { core::slice::from_raw_parts(desc.archive, desc.len) };
+        Ok(crate::fs::initramfs::load(archive)?)
+    }
+}
+
+/// `"ext2"` filesystem type: resolves `source` (an `sda<N>` name, same
+/// numbering `fs::devtmpfs` hands out) to its registered block driver and
+/// opens it as an `fs::ext2::Ext2FileSystem`, the same adapter `fs::mod`'s
+/// `VIRTUAL_FS` would use if it weren't hardcoded to the first block
+/// device - this is what lets ext2 be mounted anywhere, by name, through
+/// `sys_mount` instead.
+pub struct Ext2FsType;
+
+impl Ext2FsType {
+    pub fn registerExt2() {
+        FileSystemManager::get()
+            .write()
+            .registerFileSystem("ext2", Ext2FsType);
+    }
+}
+
+impl FileSystemType for Ext2FsType {
+    fn mount(
+        &self,
+        _syscall: &mut Syscall,
+        source: &str,
+        _flags: u64,
+        _data: usize,
+    ) -> Result<Arc<FileSystem>> {
+        let name = if source.starts_with("/dev/") { &source[5..] } else { source };
+        if !name.starts_with("sda") {
+            return Err(FsError::InvalidParam);
+        }
+        let index: usize = name[3..].parse().map_err(|_| FsError::InvalidParam)?;
+        let driver = crate::drivers::BLK_DRIVERS
+            .read()
+            .get(index)
+            .ok_or(FsError::NoDevice)?
+            .clone();
+        let device = Arc::new(crate::drivers::BlockDriver(driver));
+        Ok(crate::fs::ext2::Ext2FileSystem::open(device)?)
+    }
+}
+
+/// `"iso9660"` filesystem type: resolves `source` the same way `Ext2FsType`
+/// does and opens it as an `fs::iso9660::Iso9660FileSystem` - the read-only
+/// counterpart for mounting optical-media images (or their `.iso` block
+/// devices) alongside ext2.
+pub struct Iso9660FsType;
+
+impl Iso9660FsType {
+    pub fn registerIso9660() {
+        FileSystemManager::get()
+            .write()
+            .registerFileSystem("iso9660", Iso9660FsType);
+    }
+}
+
+impl FileSystemType for Iso9660FsType {
+    fn mount(
+        &self,
+        _syscall: &mut Syscall,
+        source: &str,
+        _flags: u64,
+        _data: usize,
+    ) -> Result<Arc<FileSystem>> {
+        let name = if source.starts_with("/dev/") { &source[5..] } else { source };
+        if !name.starts_with("sda") {
+            return Err(FsError::InvalidParam);
+        }
+        let index: usize = name[3..].parse().map_err(|_| FsError::InvalidParam)?;
+        let driver = crate::drivers::BLK_DRIVERS
+            .read()
+            .get(index)
+            .ok_or(FsError::NoDevice)?
+            .clone();
+        let device = Arc::new(crate::drivers::BlockDriver(driver));
+        Ok(crate::fs::iso9660::Iso9660FileSystem::open(device)?)
+    }
+}
+
+/// `"schemefs"` filesystem type: resolves `source` to a userspace process
+/// registered through `sys_fsscheme_create` and opens it as a
+/// `fs::schemefs::SchemeFS`, the same way `Ext2FsType` resolves `source` to
+/// a block driver - except what's being mounted is a live IPC channel
+/// instead of a disk.
+pub struct SchemeFsType;
+
+impl SchemeFsType {
+    pub fn registerSchemeFs() {
+        FileSystemManager::get()
+            .write()
+            .registerFileSystem("schemefs", SchemeFsType);
+    }
+}
+
+impl FileSystemType for SchemeFsType {
+    fn mount(
+        &self,
+        _syscall: &mut Syscall,
+        source: &str,
+        _flags: u64,
+        _data: usize,
+    ) -> Result<Arc<FileSystem>> {
+        let server = crate::lkm::fsscheme::FsSchemeManager::get()
+            .read()
+            .get_named(source)
+            .ok_or(FsError::NoDevice)?;
+        Ok(crate::fs::schemefs::SchemeFS::open(server)?)
+    }
+}
+
+/// `mount`'s `data` argument when mounting `"overlayfs"`: a pair of
+/// length-prefixed path strings, read the same way `RamFSMountData` reads
+/// its archive, each resolved to an inode through the mounting process's
+/// own path tree (exactly what `sys_mount` does with `target` itself)
+/// before being handed to `fs::unionfs::OverlayFS::new`.
+#[repr(C)]
+pub struct OverlayMountData {
+    pub lower: *const u8,
+    pub lower_len: usize,
+    pub upper: *const u8,
+    pub upper_len: usize,
+}
+
+/// `"overlayfs"` filesystem type: stacks the writable directory named by
+/// `data.upper` over the read-only directory named by `data.lower` into a
+/// `fs::unionfs::OverlayFS`, the way a container runtime layers an image's
+/// read-only layers under a throwaway writable one.
+pub struct OverlayFsType;
+
+impl OverlayFsType {
+    pub fn registerOverlayFs() {
+        FileSystemManager::get()
+            .write()
+            .registerFileSystem("overlayfs", OverlayFsType);
+    }
+
+    fn resolve(syscall: &mut Syscall, path: &str) -> Result<Arc<INode>> {
+        let proc = syscall.process();
+        match proc.cwd.path_resolve(&proc.cwd.cwd, path, true)? {
+            PathResolveResult::IsDir { dir } => Ok(dir.inode.clone()),
+            PathResolveResult::IsFile { file, .. } => Ok(file.inode.clone()),
+            PathResolveResult::NotExist { .. } => Err(FsError::NoDevice),
+        }
+    }
+}
+
+impl FileSystemType for OverlayFsType {
+    fn mount(
+        &self,
+        syscall: &mut Syscall,
+        _source: &str,
+        _flags: u64,
+        data: usize,
+    ) -> Result<Arc<FileSystem>> {
+        if data == 0 {
+            return Err(FsError::InvalidParam);
+        }
+        let desc = unsafe { &*(data as *const OverlayMountData) };
+        let lower_path = unsafe {
+            str::from_utf8(core::slice::from_raw_parts(desc.lower, desc.lower_len))
+                .map_err(|_| FsError::InvalidParam)?
+        };
+        let upper_path = unsafe {
+            str::from_utf8(core::slice::from_raw_parts(desc.upper, desc.upper_len))
+                .map_err(|_| FsError::InvalidParam)?
+        };
+        let lower = Self::resolve(syscall, lower_path)?;
+        let upper = Self::resolve(syscall, upper_path)?;
+        Ok(crate::fs::unionfs::OverlayFS::new(lower, upper))
+    }
+}
@@ -0,0 +1,405 @@
+//! Userspace "scheme" filesystem backend.
+//!
+//! `fs::schemefs::SchemeFS`/`SchemeFSInode` let a userspace process stand in
+//! for a real `FileSystem`/`INode` pair the same way `SchemeFileOperations`
+//! (see `scheme.rs`) lets one stand in for a char device's `FileOperations`:
+//! every call is packaged into an `FsSchemeRequest`, pushed onto the
+//! registering process's `FsSchemeServer` queue, and answered through
+//! `sys_fsscheme_read_request`/`sys_fsscheme_write_reply`. Unlike
+//! `SchemeServer::call` (which parks the caller on a `Condvar`), a pending
+//! reply here is awaited the same way `FileHandle::read_at` already waits
+//! out `FsError::Again` - a plain loop that yields the CPU each time round -
+//! since an `INode` method has no natural wakeup source of its own to park
+//! on.
+//!
+//! A filesystem has no single open-by-address call site the way a device
+//! file's `FileHandle` does, so every open inode is addressed by a handle id
+//! the provider mints itself (`FsSchemeOp::OpenRoot`/`Create`/`Find` all
+//! reply with one) and hands back on every later call, instead of reusing
+//! the caller's own address the way `SchemeFileOperations` does.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::sync::SpinNoIrqLock as Mutex;
+use crate::thread;
+use rcore_fs::vfs::{FileType, FsError, Metadata, Timespec};
+use spin::RwLock;
+
+/// One operation forwarded to the server process, addressed to the handle
+/// id the provider minted for the inode it targets (`0` for the root,
+/// before it's been opened).
+#[derive(Debug, Clone)]
+pub enum FsSchemeOp {
+    OpenRoot,
+    ReadAt { handle: u64, offset: usize, len: usize },
+    WriteAt { handle: u64, offset: usize, data: Vec<u8> },
+    Create { handle: u64, name: String, type_: FileType, mode: u32 },
+    Find { handle: u64, name: String },
+    GetEntry { handle: u64, id: usize },
+    Metadata { handle: u64 },
+    Unlink { handle: u64, name: String },
+    Move { handle: u64, old_name: String, target: u64, new_name: String },
+    IoControl { handle: u64, cmd: u32, data: usize },
+    Close { handle: u64 },
+}
+
+pub struct FsSchemeRequest {
+    pub id: u64,
+    pub op: FsSchemeOp,
+}
+
+/// The server process's answer to an `FsSchemeRequest`: either the call's
+/// result, serialized per-opcode the same way the request's own payload is,
+/// or the error it failed with.
+pub enum FsSchemeReply {
+    Ok(Vec<u8>),
+    Err(FsError),
+}
+
+#[derive(Default)]
+struct FsSchemeState {
+    queue: VecDeque<FsSchemeRequest>,
+    replies: BTreeMap<u64, FsSchemeReply>,
+}
+
+/// Shared between every `SchemeFSInode` call forwarded to this filesystem
+/// and the server fd the registering process reads requests from / writes
+/// replies to.
+pub struct FsSchemeServer {
+    state: Mutex<FsSchemeState>,
+    next_id: AtomicU64,
+}
+
+impl FsSchemeServer {
+    pub fn new() -> Arc<FsSchemeServer> {
+        Arc::new(FsSchemeServer {
+            state: Mutex::new(FsSchemeState::default()),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Enqueue `op` and block until the server process has answered it,
+    /// polling the same way `FileHandle::read_at` rides out `FsError::Again`
+    /// rather than parking on a `Condvar`.
+    pub fn call(&self, op: FsSchemeOp) -> FsSchemeReply {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.state.lock().queue.push_back(FsSchemeRequest { id, op });
+        loop {
+            if let Some(reply) = self.state.lock().replies.remove(&id) {
+                return reply;
+            }
+            thread::yield_now();
+        }
+    }
+
+    /// Block until a request is available, then hand it to the server
+    /// process (`sys_fsscheme_read_request`) to decode and act on.
+    pub fn next_request(&self) -> FsSchemeRequest {
+        loop {
+            if let Some(req) = self.state.lock().queue.pop_front() {
+                return req;
+            }
+            thread::yield_now();
+        }
+    }
+
+    /// Post the server process's answer (`sys_fsscheme_write_reply`).
+    pub fn reply(&self, id: u64, reply: FsSchemeReply) {
+        self.state.lock().replies.insert(id, reply);
+    }
+}
+
+fn decode_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    let n = bytes.len().min(8);
+    buf[..n].copy_from_slice(&bytes[..n]);
+    u64::from_le_bytes(buf)
+}
+
+fn file_type_byte(t: FileType) -> u8 {
+    use FileType::*;
+    match t {
+        File => 0,
+        Dir => 1,
+        SymLink => 2,
+        CharDevice => 3,
+        BlockDevice => 4,
+        NamedPipe => 5,
+        Socket => 6,
+    }
+}
+
+fn file_type_from_byte(b: u8) -> FileType {
+    use FileType::*;
+    match b {
+        1 => Dir,
+        2 => SymLink,
+        3 => CharDevice,
+        4 => BlockDevice,
+        5 => NamedPipe,
+        6 => Socket,
+        _ => File,
+    }
+}
+
+/// `FsError` has no `Copy`/wire form of its own, so replies encode it as
+/// this variant's position in the enum as declared in `rcore_fs::vfs`.
+pub fn error_byte(e: &FsError) -> u8 {
+    use FsError::*;
+    match e {
+        NotSupported => 0,
+        NotFile => 1,
+        IsDir => 2,
+        NotDir => 3,
+        EntryNotFound => 4,
+        EntryExist => 5,
+        NotSameFs => 6,
+        InvalidParam => 7,
+        NoDeviceSpace => 8,
+        DirRemoved => 9,
+        DirNotEmpty => 10,
+        WrongFs => 11,
+        DeviceError => 12,
+        Busy => 13,
+        Again => 14,
+        SymLoop => 15,
+        NoDevice => 16,
+    }
+}
+
+pub fn error_from_byte(b: u8) -> FsError {
+    use FsError::*;
+    match b {
+        0 => NotSupported,
+        1 => NotFile,
+        2 => IsDir,
+        3 => NotDir,
+        4 => EntryNotFound,
+        5 => EntryExist,
+        6 => NotSameFs,
+        7 => InvalidParam,
+        8 => NoDeviceSpace,
+        9 => DirRemoved,
+        10 => DirNotEmpty,
+        11 => WrongFs,
+        12 => DeviceError,
+        13 => Busy,
+        14 => Again,
+        15 => SymLoop,
+        _ => NoDevice,
+    }
+}
+
+/// Wire format for a `Metadata`: every field in declaration order,
+/// fixed-width little-endian, for `FsSchemeOp::Metadata`'s reply.
+pub fn encode_metadata(m: &Metadata) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(8 * 11 + 1 + 2);
+    buf.extend_from_slice(&(m.dev as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.inode as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.size as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.blk_size as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.blocks as u64).to_le_bytes());
+    buf.extend_from_slice(&m.atime.sec.to_le_bytes());
+    buf.extend_from_slice(&m.atime.nsec.to_le_bytes());
+    buf.extend_from_slice(&m.mtime.sec.to_le_bytes());
+    buf.extend_from_slice(&m.mtime.nsec.to_le_bytes());
+    buf.extend_from_slice(&m.ctime.sec.to_le_bytes());
+    buf.extend_from_slice(&m.ctime.nsec.to_le_bytes());
+    buf.push(file_type_byte(m.type_));
+    buf.extend_from_slice(&m.mode.to_le_bytes());
+    buf.extend_from_slice(&(m.nlinks as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.uid as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.gid as u64).to_le_bytes());
+    buf.extend_from_slice(&m.rdev.to_le_bytes());
+    buf
+}
+
+pub fn decode_metadata(data: &[u8]) -> Option<Metadata> {
+    if data.len() < 8 * 11 + 1 + 2 {
+        return None;
+    }
+    let mut p = 0usize;
+    let mut take64 = |p: &mut usize| {
+        let v = decode_u64(&data[*p..*p + 8]);
+        *p += 8;
+        v
+    };
+    let dev = take64(&mut p) as usize;
+    let inode = take64(&mut p) as usize;
+    let size = take64(&mut p) as usize;
+    let blk_size = take64(&mut p) as usize;
+    let blocks = take64(&mut p) as usize;
+    let atime = Timespec {
+        sec: take64(&mut p) as i64,
+        nsec: take64(&mut p) as i32,
+    };
+    let mtime = Timespec {
+        sec: take64(&mut p) as i64,
+        nsec: take64(&mut p) as i32,
+    };
+    let ctime = Timespec {
+        sec: take64(&mut p) as i64,
+        nsec: take64(&mut p) as i32,
+    };
+    let type_ = file_type_from_byte(data[p]);
+    p += 1;
+    let mode = u16::from_le_bytes([data[p], data[p + 1]]);
+    p += 2;
+    let nlinks = take64(&mut p) as usize;
+    let uid = take64(&mut p) as usize;
+    let gid = take64(&mut p) as usize;
+    let rdev = take64(&mut p);
+    Some(Metadata {
+        dev,
+        inode,
+        size,
+        blk_size,
+        blocks,
+        atime,
+        mtime,
+        ctime,
+        type_,
+        mode,
+        nlinks,
+        uid,
+        gid,
+        rdev,
+    })
+}
+
+fn opcode_byte(op: &FsSchemeOp) -> u8 {
+    match op {
+        FsSchemeOp::OpenRoot => 0,
+        FsSchemeOp::ReadAt { .. } => 1,
+        FsSchemeOp::WriteAt { .. } => 2,
+        FsSchemeOp::Create { .. } => 3,
+        FsSchemeOp::Find { .. } => 4,
+        FsSchemeOp::GetEntry { .. } => 5,
+        FsSchemeOp::Metadata { .. } => 6,
+        FsSchemeOp::Unlink { .. } => 7,
+        FsSchemeOp::Move { .. } => 8,
+        FsSchemeOp::IoControl { .. } => 9,
+        FsSchemeOp::Close { .. } => 10,
+    }
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u64).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Wire format for an `FsSchemeRequest`, read back out by
+/// `sys_fsscheme_read_request`: `[opcode:u8][id:u64][handle:u64][payload]`,
+/// with the payload shaped per-opcode the same way `FsSchemeOp` is (strings
+/// are length-prefixed since, unlike `scheme.rs`'s byte buffers, a name
+/// isn't the whole payload).
+pub fn encode_request(req: &FsSchemeRequest) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.push(opcode_byte(&req.op));
+    buf.extend_from_slice(&req.id.to_le_bytes());
+    match &req.op {
+        FsSchemeOp::OpenRoot => {
+            buf.extend_from_slice(&0u64.to_le_bytes());
+        }
+        FsSchemeOp::ReadAt { handle, offset, len } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+            buf.extend_from_slice(&(*offset as u64).to_le_bytes());
+            buf.extend_from_slice(&(*len as u64).to_le_bytes());
+        }
+        FsSchemeOp::WriteAt { handle, offset, data } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+            buf.extend_from_slice(&(*offset as u64).to_le_bytes());
+            buf.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            buf.extend_from_slice(data);
+        }
+        FsSchemeOp::Create { handle, name, type_, mode } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+            push_str(&mut buf, name);
+            buf.push(file_type_byte(*type_));
+            buf.extend_from_slice(&mode.to_le_bytes());
+        }
+        FsSchemeOp::Find { handle, name } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+            push_str(&mut buf, name);
+        }
+        FsSchemeOp::GetEntry { handle, id } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+            buf.extend_from_slice(&(*id as u64).to_le_bytes());
+        }
+        FsSchemeOp::Metadata { handle } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+        }
+        FsSchemeOp::Unlink { handle, name } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+            push_str(&mut buf, name);
+        }
+        FsSchemeOp::Move { handle, old_name, target, new_name } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+            push_str(&mut buf, old_name);
+            buf.extend_from_slice(&target.to_le_bytes());
+            push_str(&mut buf, new_name);
+        }
+        FsSchemeOp::IoControl { handle, cmd, data } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+            buf.extend_from_slice(&cmd.to_le_bytes());
+            buf.extend_from_slice(&(*data as u64).to_le_bytes());
+        }
+        FsSchemeOp::Close { handle } => {
+            buf.extend_from_slice(&handle.to_le_bytes());
+        }
+    }
+    buf
+}
+
+/// Registry of live filesystem scheme servers. Kept under two keys:
+/// `by_name`, looked up by `FileSystemType::mount`'s `source` the way
+/// `ModuleSchemeManager` keys its device endpoints by the name a module
+/// registered; and `by_fd`, looked up by the `sys_fsscheme_read_request`/
+/// `sys_fsscheme_write_reply` caller the way `SchemeManager` keys its
+/// servers by the fd `sys_scheme_create` returned - `mount` has no fd to
+/// look one up with, and the provider process has no mount to name one by.
+#[derive(Default)]
+pub struct FsSchemeManager {
+    by_name: BTreeMap<String, Arc<FsSchemeServer>>,
+    by_fd: BTreeMap<usize, Arc<FsSchemeServer>>,
+    next_fd: usize,
+}
+
+pub static mut FS_SCHEME_MANAGER: Option<RwLock<FsSchemeManager>> = None;
+
+impl FsSchemeManager {
+    pub fn init() {
+        unsafe {
+            FS_SCHEME_MANAGER = Some(RwLock::new(FsSchemeManager::default()));
+        }
+    }
+
+    pub fn get() -> &'static RwLock<FsSchemeManager> {
+        unsafe { FS_SCHEME_MANAGER.as_ref().unwrap() }
+    }
+
+    /// Register a newly-created server under `name`, overwriting whatever
+    /// was previously registered under it, and hand back the fd its
+    /// provider process polls it with.
+    pub fn register(&mut self, name: &str) -> usize {
+        let server = FsSchemeServer::new();
+        self.by_name.insert(String::from(name), server.clone());
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        self.by_fd.insert(fd, server);
+        fd
+    }
+
+    pub fn get_named(&self, name: &str) -> Option<Arc<FsSchemeServer>> {
+        self.by_name.get(name).cloned()
+    }
+
+    pub fn get_fd(&self, fd: usize) -> Option<Arc<FsSchemeServer>> {
+        self.by_fd.get(&fd).cloned()
+    }
+}
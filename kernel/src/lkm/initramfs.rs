@@ -0,0 +1,66 @@
+//! Minimal cpio ("newc") archive walker for pulling module images straight
+//! out of an initramfs's bytes, without mounting it as a filesystem first -
+//! all `ModuleManager::load_initramfs` needs is each entry's name and data.
+//! Mirrors the header layout `fs::initramfs` parses to build a `TmpFS`, but
+//! that one hands back `INode`s built on the external `rcore_fs` crate,
+//! which isn't the vocabulary `ModuleManager` and `LoadedModule` speak.
+
+use alloc::vec::Vec;
+
+const MAGIC: &[u8] = b"070701";
+/// 6-byte magic + 13 fixed 8-hex-digit fields.
+const HEADER_LEN: usize = 6 + 13 * 8;
+const TRAILER_NAME: &str = "TRAILER!!!";
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn hex_field(bytes: &[u8]) -> Option<u32> {
+    core::str::from_utf8(bytes).ok().and_then(|s| u32::from_str_radix(s, 16).ok())
+}
+
+/// Walk a "newc" cpio archive, returning each entry's name and data slice in
+/// archive order (directory entries included, with empty data). Stops at
+/// the first malformed or truncated header instead of erroring out, since a
+/// caller only scanning for `.ko` files would rather see whatever entries
+/// parsed cleanly than lose the whole archive to one bad trailing record.
+pub fn entries(archive: &[u8]) -> Vec<(&str, &[u8])> {
+    let mut out = Vec::new();
+    let mut off = 0;
+    loop {
+        if off + HEADER_LEN > archive.len() || &archive[off..off + 6] != MAGIC {
+            break;
+        }
+        let field = |i: usize| hex_field(&archive[off + 6 + i * 8..off + 6 + (i + 1) * 8]);
+        let (filesize, namesize) = match (field(6), field(11)) {
+            (Some(filesize), Some(namesize)) => (filesize as usize, namesize as usize),
+            _ => break,
+        };
+
+        let name_start = off + HEADER_LEN;
+        if namesize == 0 {
+            break;
+        }
+        let name_end = name_start + namesize - 1; // drop the trailing NUL
+        if name_end > archive.len() {
+            break;
+        }
+        let name = match core::str::from_utf8(&archive[name_start..name_end]) {
+            Ok(name) => name,
+            Err(_) => break,
+        };
+
+        let data_start = align4(name_start + namesize);
+        let data_end = data_start + filesize;
+        if data_end > archive.len() {
+            break;
+        }
+        if name == TRAILER_NAME {
+            break;
+        }
+        out.push((name, &archive[data_start..data_end]));
+        off = align4(data_end);
+    }
+    out
+}
@@ -4,6 +4,8 @@ use crate::arch::paging::ActivePageTable;
 use crate::consts::*;
 use crate::memory::{active_table, GlobalFrameAlloc};
 use crate::sync::SpinLock as Mutex;
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
 use alloc::vec::*;
 use buddy_system_allocator::*;
 use core::alloc::Layout;
@@ -12,6 +14,11 @@ use lazy_static::lazy_static;
 use rcore_memory::memory_set::handler::{ByFrame, MemoryHandler};
 use rcore_memory::memory_set::MemoryAttr;
 use rcore_memory::{Page, PAGE_SIZE};
+
+/// Unmapped pages kept on each side of a lazy `VirtualArea`'s usable range,
+/// so a kernel stack/module overflowing past either end faults instead of
+/// silently corrupting whatever the buddy manager handed out next door.
+pub const GUARD_PAGES: usize = 1;
 //Allocated virtual memory space by pages. returns some vaddr.
 pub trait MemorySpaceManager {
     fn new() -> Self;
@@ -20,35 +27,10 @@ pub trait MemorySpaceManager {
     fn active_table(&self) -> ActivePageTable;
 }
 
-//The most simple strategy: no free and allocate ahead.
-pub struct LinearManager {
-    last_page: usize,
-}
-pub const KSEG2_START: usize = 0xffff_fe80_0000_0000;
-
-impl MemorySpaceManager for LinearManager {
-    fn new() -> LinearManager {
-        LinearManager { last_page: 0 }
-    }
-    fn alloc(&mut self, size: usize) -> Option<(usize, usize)> {
-        let mut required_pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
-
-        let current = self.last_page * PAGE_SIZE + KSEG2_START;
-        self.last_page += required_pages;
-        Some((current, required_pages * PAGE_SIZE))
-    }
-
-    fn free(&mut self, (addr, size): (usize, usize)) {
-        //Do nothing.
-    }
-    fn active_table(&self) -> ActivePageTable {
-        active_table()
-    }
-}
-
-// 512 GiB is a large space, and we don't need to worry about internal fragmentation.
-// What kind of kernel program will try to allocate 256 GiB memory?
-// 27 layers is enough, since the minimal unit is a block.
+// KSEG2_START/KSEG2_SIZE live in `crate::consts` (imported above) since
+// riscv64's Sv39/Sv48 high-half layout needs different values than x86_64's.
+// We don't need to worry about internal fragmentation at this scale: what
+// kind of kernel program will try to allocate hundreds of GiB of memory?
 
 pub struct BuddyManager(pub Heap);
 
@@ -56,20 +38,25 @@ impl MemorySpaceManager for BuddyManager {
     fn new() -> Self {
         let mut vmm = BuddyManager(Heap::empty());
         unsafe {
-            vmm.0.init(KSEG2_START, 0x8000000000);
-            //vmm.0.add_to_heap(KSEG2_START, KSEG2_START+0x8000000000);
+            vmm.0.init(KSEG2_START, KSEG2_SIZE);
         }
         vmm
     }
 
     fn alloc(&mut self, size: usize) -> Option<(usize, usize)> {
-        let mut required_pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
-        let ret = self
-            .0
-            .alloc(Layout::from_size_align(required_pages * PAGE_SIZE, 1).ok()?);
+        let required_pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let required_size = required_pages * PAGE_SIZE;
+        // Page-aligned so the returned address can be mapped directly, and
+        // using the exact same layout (size, align) on `free` is what lets
+        // the buddy heap coalesce a freed block back with its sibling.
+        let layout = Layout::from_size_align(required_size, PAGE_SIZE).ok()?;
+        let ret = self.0.alloc(layout);
         match ret {
-            Ok(start) => Some((start.as_ptr() as usize, required_pages * PAGE_SIZE)),
+            Ok(start) => Some((start.as_ptr() as usize, required_size)),
             Err(err) => {
+                // The `KSEG2_SIZE`-byte KSEG2 arena is exhausted: report failure
+                // instead of the overlapping addresses `LinearManager` used
+                // to silently hand out once it ran off the end.
                 error!("[KVMM] allocation failed!");
                 None
             }
@@ -79,7 +66,7 @@ impl MemorySpaceManager for BuddyManager {
     fn free(&mut self, target: (usize, usize)) {
         self.0.dealloc(
             unsafe { NonNull::new_unchecked(target.0 as *mut u8) },
-            Layout::from_size_align(target.1, 1).unwrap(),
+            Layout::from_size_align(target.1, PAGE_SIZE).unwrap(),
         )
     }
 
@@ -139,10 +126,243 @@ impl<T: MemorySpaceManager> VKMemManager<T>{
     }
 }
 */
-type VirtualMemorySpaceManager = LinearManager;
-type LockedVMM = Mutex<VirtualMemorySpaceManager>;
+/// In-page header a `SlabManager` slab page starts with: the size class it
+/// serves, how many objects fit after the header, how many are currently
+/// free, and the head of an intrusive singly-linked free list threaded
+/// through the free objects themselves (each free object's first word is
+/// the address of the next free object, or 0 for "none" - a kernel virtual
+/// address is never actually zero).
+#[repr(C)]
+struct SlabPageHeader {
+    class: usize,
+    capacity: usize,
+    free_count: usize,
+    free_list: usize,
+}
+
+/// Size classes a `SlabManager` slab page is carved into, in bytes.
+/// Anything bigger than the largest class (or a multi-page request) skips
+/// slabbing entirely and goes straight to the backing `BuddyManager`, same
+/// as it always could.
+const SLAB_CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// Finds the smallest size class that fits `size`, if any.
+fn slab_class_index(size: usize) -> Option<usize> {
+    SLAB_CLASSES.iter().position(|&class| size <= class)
+}
+
+/// Wraps `BuddyManager` with per-size-class free lists for small kernel
+/// objects, so allocating one doesn't waste a whole page and thrash the
+/// buddy free-lists the way routing every request straight to `BuddyManager`
+/// would. A class's free objects are carved out of whole pages borrowed
+/// from the buddy manager on demand ("slab pages"); `free` walks back to the
+/// owning slab page from the pointer's page-aligned base, pushes the object
+/// onto that page's free list, and once a page's objects are all free again
+/// it's unmapped and handed back to `BuddyManager` rather than held onto.
+pub struct SlabManager {
+    buddy: BuddyManager,
+    /// `partial_pages[i]`: slab pages of class `SLAB_CLASSES[i]` that still
+    /// have at least one free object. A page leaves this list the moment it
+    /// fills up, and rejoins it the moment something on it is freed.
+    partial_pages: [Vec<usize>; SLAB_CLASSES.len()],
+}
+
+impl SlabManager {
+    /// Borrows a fresh page from `BuddyManager`, maps it, and carves it into
+    /// `SLAB_CLASSES[ci]`-sized objects threaded onto a free list following
+    /// the header.
+    fn grow_class(&mut self, ci: usize) -> Option<()> {
+        let (page_base, _) = self.buddy.alloc(PAGE_SIZE)?;
+        let attr = MemoryAttr::default().writable();
+        let mut active_pt = active_table();
+        ByFrame::new(GlobalFrameAlloc).map(&mut active_pt, page_base, &attr);
+
+        let class = SLAB_CLASSES[ci];
+        let header_size = core::mem::size_of::<SlabPageHeader>();
+        let first_obj = page_base + header_size;
+        let capacity = (PAGE_SIZE - header_size) / class;
+
+        let mut free_list = 0usize;
+        for i in (0..capacity).rev() {
+            let obj_addr = first_obj + i * class;
+            unsafe {
+                *(obj_addr as *mut usize) = free_list;
+            }
+            free_list = obj_addr;
+        }
+        unsafe {
+            *(page_base as *mut SlabPageHeader) = SlabPageHeader {
+                class,
+                capacity,
+                free_count: capacity,
+                free_list,
+            };
+        }
+        self.partial_pages[ci].push(page_base);
+        Some(())
+    }
+}
+
+impl MemorySpaceManager for SlabManager {
+    fn new() -> Self {
+        SlabManager {
+            buddy: BuddyManager::new(),
+            partial_pages: [
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+            ],
+        }
+    }
+
+    fn alloc(&mut self, size: usize) -> Option<(usize, usize)> {
+        let ci = match slab_class_index(size) {
+            Some(ci) => ci,
+            // Bigger than our largest class (or a multi-page request):
+            // slabbing wouldn't save anything, hand it straight to the buddy
+            // arena like `BuddyManager` alone would.
+            None => return self.buddy.alloc(size),
+        };
+        if self.partial_pages[ci].is_empty() {
+            self.grow_class(ci)?;
+        }
+        let page_base = *self.partial_pages[ci].last().unwrap();
+        let header = unsafe { &mut *(page_base as *mut SlabPageHeader) };
+        let obj_addr = header.free_list;
+        header.free_list = unsafe { *(obj_addr as *const usize) };
+        header.free_count -= 1;
+        if header.free_count == 0 {
+            self.partial_pages[ci].pop();
+        }
+        Some((obj_addr, SLAB_CLASSES[ci]))
+    }
+
+    fn free(&mut self, target: (usize, usize)) {
+        let (addr, size) = target;
+        let ci = match slab_class_index(size) {
+            Some(ci) => ci,
+            None => return self.buddy.free(target),
+        };
+        let page_base = addr & !(PAGE_SIZE - 1);
+        let header = unsafe { &mut *(page_base as *mut SlabPageHeader) };
+        let was_full = header.free_count == 0;
+        unsafe {
+            *(addr as *mut usize) = header.free_list;
+        }
+        header.free_list = addr;
+        header.free_count += 1;
+
+        if header.free_count == header.capacity {
+            // Every object on this page is free again: it's cheaper to give
+            // the page back to the buddy arena than to keep it mapped on
+            // the chance another object this size gets allocated.
+            self.partial_pages[ci].retain(|&base| base != page_base);
+            let mut active_pt = active_table();
+            ByFrame::new(GlobalFrameAlloc).unmap(&mut active_pt, page_base);
+            tlb_shootdown((page_base, page_base + PAGE_SIZE));
+            self.buddy.free((page_base, PAGE_SIZE));
+        } else if was_full {
+            self.partial_pages[ci].push(page_base);
+        }
+    }
+
+    fn active_table(&self) -> ActivePageTable {
+        self.buddy.active_table()
+    }
+}
+
+// Selectable `VirtualMemorySpaceManager` strategies: `BuddyManager` maps
+// every request straight onto the buddy arena, one page-aligned block per
+// allocation regardless of size; `SlabManager` adds size-class slab pages
+// in front of it so small kernel objects don't each waste a whole page, and
+// only reaches into the buddy arena for a fresh slab page or a multi-page
+// request. Swap this alias to change the strategy everywhere
+// `KERNELVM_MANAGER` is used.
+type VirtualMemorySpaceManager = SlabManager;
+
+/// A lazy `VirtualArea`'s registration in the fault path: its usable range
+/// (guard pages excluded - a fault there isn't ours to fix), the attribute
+/// every page in the range gets mapped with, and the set of pages actually
+/// faulted in so far. `faulted` is an `Arc` because a `VirtualArea` keeps
+/// its own clone to know exactly which pages it mapped when it's dropped.
+struct LazyAreaEntry {
+    start: usize,
+    end: usize,
+    attr: MemoryAttr,
+    faulted: Arc<Mutex<BTreeSet<usize>>>,
+}
+
+/// Everything `add_area`/`add_lazy_area` and the page-fault handler need to
+/// touch under one lock: the buddy arena itself, and the lazy areas carved
+/// out of it. Sharing the lock is what lets `handle_page_fault` walk the
+/// area list safely while `VirtualSpace::new`/`Drop` are adding or removing
+/// entries on another CPU.
+struct KernelVmState {
+    space: VirtualMemorySpaceManager,
+    lazy_areas: Vec<LazyAreaEntry>,
+}
+
+impl KernelVmState {
+    fn new() -> Self {
+        KernelVmState {
+            space: VirtualMemorySpaceManager::new(),
+            lazy_areas: Vec::new(),
+        }
+    }
+
+    fn register_lazy_area(&mut self, entry: LazyAreaEntry) {
+        self.lazy_areas.push(entry);
+    }
+
+    fn unregister_lazy_area(&mut self, start: usize) {
+        self.lazy_areas.retain(|area| area.start != start);
+    }
+
+    fn find_lazy_area(&self, page_addr: usize) -> Option<&LazyAreaEntry> {
+        self.lazy_areas
+            .iter()
+            .find(|area| page_addr >= area.start && page_addr < area.end)
+    }
+}
+
+type LockedVMM = Mutex<KernelVmState>;
 lazy_static! {
-    pub static ref KERNELVM_MANAGER: LockedVMM = Mutex::new(VirtualMemorySpaceManager::new());
+    pub static ref KERNELVM_MANAGER: LockedVMM = Mutex::new(KernelVmState::new());
+}
+
+/// Services a kernel-space page fault at `fault_addr`: called from
+/// `arch::interrupt`'s page-fault handler once it's wired up for this
+/// target (x86_64's `#PF`, riscv64's store/load/instruction page faults).
+/// Looks `fault_addr` up against every lazy `VirtualArea` registered via
+/// `add_lazy_area`; on a hit, allocates a frame and maps it with the area's
+/// stored `MemoryAttr`, same as `VirtualArea::new` would have done eagerly.
+/// Returns `false` if the address isn't inside any lazy area's usable range
+/// (this includes its guard pages, and genuinely unrelated addresses) or if
+/// it's inside one but was already faulted in - either way that's a real
+/// fault (stack/module overflow into a guard page, or a protection
+/// violation), and the caller should panic/oops rather than retry.
+pub fn handle_page_fault(fault_addr: usize) -> bool {
+    let page_addr = fault_addr & !(PAGE_SIZE - 1);
+    let mut vmm = KERNELVM_MANAGER.lock();
+    let (attr, faulted) = match vmm.find_lazy_area(page_addr) {
+        Some(area) => (area.attr.clone(), area.faulted.clone()),
+        None => return false,
+    };
+    {
+        let mut faulted = faulted.lock();
+        if faulted.contains(&page_addr) {
+            return false;
+        }
+        faulted.insert(page_addr);
+    }
+    let mut active_pt = vmm.space.active_table();
+    ByFrame::new(GlobalFrameAlloc).map(&mut active_pt, page_addr, &attr);
+    true
 }
 
 // Represents a contiguous virtual area: like the ancient loader.
@@ -158,7 +378,7 @@ pub struct VirtualSpace {
 impl VirtualSpace {
     pub fn new(allocator: &'static LockedVMM, size: usize) -> Option<VirtualSpace> {
         let mut vmm = allocator.lock();
-        let (start, rsize) = vmm.alloc(size)?;
+        let (start, rsize) = vmm.space.alloc(size)?;
         Some(VirtualSpace {
             start: start,
             size: rsize,
@@ -197,6 +417,24 @@ impl VirtualSpace {
         self.areas.push(area);
         self.areas.last().unwrap()
     }
+
+    /// Like `add_area`, but doesn't map a single page up front. The range is
+    /// only reserved, with `GUARD_PAGES` of unmapped guard pages kept at
+    /// each end, and pages are mapped lazily by `handle_page_fault` on first
+    /// access with `attr`. Meant for the large, sparsely-touched ranges the
+    /// LKM loader (`crate::lkm::manager`) reserves for a module's image,
+    /// where eagerly `ByFrame`-mapping every page would waste frames that
+    /// the module never touches.
+    pub fn add_lazy_area(
+        &mut self,
+        start_addr: usize,
+        end_addr: usize,
+        attr: &MemoryAttr,
+    ) -> &VirtualArea {
+        let area = VirtualArea::new_lazy(start_addr, end_addr - start_addr, attr, self);
+        self.areas.push(area);
+        self.areas.last().unwrap()
+    }
 }
 
 impl Drop for VirtualSpace {
@@ -207,10 +445,21 @@ impl Drop for VirtualSpace {
     }
 }
 
+/// Present only for areas created via `add_lazy_area`: the usable range
+/// (guard pages excluded) registered in `KernelVmState::lazy_areas`, and the
+/// set of pages actually faulted in, so `unmap` only tears down what's
+/// really mapped.
+struct LazyState {
+    usable_start: usize,
+    usable_end: usize,
+    faulted: Arc<Mutex<BTreeSet<usize>>>,
+}
+
 pub struct VirtualArea {
     start: usize,
     end: usize,
     attr: MemoryAttr,
+    lazy: Option<LazyState>,
 }
 impl VirtualArea {
     pub fn new(
@@ -222,26 +471,92 @@ impl VirtualArea {
         let aligned_start_addr = page_addr - page_addr % PAGE_SIZE;
         let mut aligned_end = (page_addr + size + PAGE_SIZE - 1);
         aligned_end = aligned_end - aligned_end % PAGE_SIZE;
-        let mut active_pt = parent.allocator.lock().active_table();
+        let mut active_pt = parent.allocator.lock().space.active_table();
         for p in Page::range_of(aligned_start_addr, aligned_end) {
             parent
                 .page_allocator
                 .map(&mut active_pt, p.start_address(), attr);
         }
         debug!("[VMM] Allocating");
-        //invoke_on_allcpu(tlb_shootdown, (aligned_start_addr, aligned_end),true);
+        // The kernel page table is shared by every CPU, so a core that
+        // mapped this range with stricter permissions before (or another
+        // module loaded here and was unloaded) can still be holding a stale,
+        // now-wrong translation in its TLB; shoot it down everywhere before
+        // handing the range out.
+        tlb_shootdown((aligned_start_addr, aligned_end));
         debug!("[VMM] Allocated!");
         VirtualArea {
             start: aligned_start_addr,
             end: aligned_end,
             attr: attr.clone(),
+            lazy: None,
+        }
+    }
+
+    /// Reserves `[page_addr, page_addr + size)` (page-aligned) without
+    /// mapping anything: `GUARD_PAGES` pages at each end stay permanently
+    /// unmapped, and the range in between is registered with
+    /// `KernelVmState` so `handle_page_fault` can map pages into it one at a
+    /// time as the module actually touches them.
+    pub fn new_lazy(page_addr: usize, size: usize, attr: &MemoryAttr, parent: &mut VirtualSpace) -> VirtualArea {
+        let aligned_start_addr = page_addr - page_addr % PAGE_SIZE;
+        let mut aligned_end = page_addr + size + PAGE_SIZE - 1;
+        aligned_end = aligned_end - aligned_end % PAGE_SIZE;
+        let guard_size = GUARD_PAGES * PAGE_SIZE;
+        let usable_start = aligned_start_addr + guard_size;
+        let usable_end = if aligned_end > aligned_start_addr + 2 * guard_size {
+            aligned_end - guard_size
+        } else {
+            // Area too small to fit guard pages on both sides: no usable
+            // range rather than letting the guards overlap each other.
+            usable_start
+        };
+        let faulted = Arc::new(Mutex::new(BTreeSet::new()));
+        parent.allocator.lock().register_lazy_area(LazyAreaEntry {
+            start: usable_start,
+            end: usable_end,
+            attr: attr.clone(),
+            faulted: faulted.clone(),
+        });
+        debug!(
+            "[VMM] Reserved lazy area [{:#x}, {:#x}), usable [{:#x}, {:#x})",
+            aligned_start_addr, aligned_end, usable_start, usable_end
+        );
+        VirtualArea {
+            start: aligned_start_addr,
+            end: aligned_end,
+            attr: attr.clone(),
+            lazy: Some(LazyState {
+                usable_start,
+                usable_end,
+                faulted,
+            }),
         }
     }
+
     pub fn unmap(&mut self, allocator: &LockedVMM, parent: &mut ByFrame<GlobalFrameAlloc>) {
-        let mut active_pt = allocator.lock().active_table();
-        for p in Page::range_of(self.start, self.end) {
-            parent.unmap(&mut active_pt, p.start_address());
+        match &self.lazy {
+            None => {
+                let mut active_pt = allocator.lock().space.active_table();
+                for p in Page::range_of(self.start, self.end) {
+                    parent.unmap(&mut active_pt, p.start_address());
+                }
+                tlb_shootdown((self.start, self.end));
+            }
+            Some(lazy) => {
+                let mut vmm = allocator.lock();
+                vmm.unregister_lazy_area(lazy.usable_start);
+                let faulted = lazy.faulted.lock();
+                if !faulted.is_empty() {
+                    let mut active_pt = vmm.space.active_table();
+                    for &page_addr in faulted.iter() {
+                        parent.unmap(&mut active_pt, page_addr);
+                    }
+                    // Only pages we actually faulted in were ever mapped, so
+                    // that's all that needs flushing - not the whole range.
+                    tlb_shootdown((lazy.usable_start, lazy.usable_end));
+                }
+            }
         }
-        invoke_on_allcpu(tlb_shootdown, (self.start, self.end), true);
     }
 }
@@ -30,60 +30,97 @@ impl FreeList {
         });
         FreeList { node: Some(root) }
     }
+    /// Best-fit: scan the whole list and take the smallest node that's still
+    /// big enough, instead of the first one that fits. Keeps a first-fit
+    /// list's habit of shredding one big region into slivers for early
+    /// requests from starving a later large request.
     pub fn alloc(&mut self, size: usize) -> Option<usize> {
         unsafe {
             let mut iterator = &mut (self.node) as Slot;
             let mut pos: Option<PtrNode> = next_addr(iterator);
+            let mut best: Option<Slot> = None;
+            let mut best_size = usize::max_value();
             while pos.is_some() {
-                let mut ptr = pos.clone().unwrap();
-                if (*ptr).size >= size {
-                    if (*ptr).size > size {
-                        (*ptr).size -= size;
-                        let ret = (*ptr).start;
-                        (*ptr).start += size;
-                        return Some(ret);
-                    } else {
-                        let ret = (*ptr).start;
-                        let mut placeholder = None;
-                        ::core::mem::swap(&mut placeholder, &mut ((*ptr).next));
-                        ::core::mem::swap(
-                            &mut placeholder,
-                            &mut (*iterator) as &mut Option<Box<Node>>,
-                        );
-                        drop(placeholder); //This is unnecessary, but we do this.
-                        return Some(ret);
-                    }
-                } else {
-                    iterator = &mut ((*pos.unwrap()).next) as Slot;
-                    pos = next_addr(iterator);
+                let ptr = pos.clone().unwrap();
+                if (*ptr).size >= size && (*ptr).size < best_size {
+                    best_size = (*ptr).size;
+                    best = Some(iterator);
                 }
+                iterator = &mut ((*pos.unwrap()).next) as Slot;
+                pos = next_addr(iterator);
+            }
+            let iterator = best?;
+            let ptr = next_addr(iterator).unwrap();
+            if (*ptr).size > size {
+                (*ptr).size -= size;
+                let ret = (*ptr).start;
+                (*ptr).start += size;
+                Some(ret)
+            } else {
+                let ret = (*ptr).start;
+                let mut placeholder = None;
+                ::core::mem::swap(&mut placeholder, &mut ((*ptr).next));
+                ::core::mem::swap(&mut placeholder, &mut (*iterator) as &mut Option<Box<Node>>);
+                drop(placeholder); //This is unnecessary, but we do this.
+                Some(ret)
             }
-            None
         }
     }
-    /*
-    pub fn free(&mut self, start: usize, size: usize){
-        unsafe{
-            let mut iterator = &mut(self.node) as Slot;
-            let mut pos: Option<PtrNode>=next_addr(iterator);
-            while pos.is_some(){
-                let mut ptr=pos.clone().unwrap();
-                if (*ptr).start>start{
-                    if start+size==(*ptr).start{
-                        (*ptr).start=start;
-                        (*ptr).size+=size;
-                        return;
-                    }else {
-                        let mut placeholder = Box::new(Node { start: start, size: size, next: None });
-                        ::core::mem::swap(&mut (placeholder.next), &mut (*iterator));
-                        *iterator = Some(placeholder); //releasing a None.
+
+    /// Return `[start, start+size)` to the list, merging it with a
+    /// physically adjacent free node on either side so free space doesn't
+    /// fragment into a string of neighbouring slivers that `alloc` can never
+    /// see as one region.
+    pub fn free(&mut self, start: usize, size: usize) {
+        unsafe {
+            let mut iterator = &mut (self.node) as Slot;
+            let mut prev: Option<PtrNode> = None;
+            let mut pos: Option<PtrNode> = next_addr(iterator);
+            // The list is kept sorted by `start`, so walk it until we reach
+            // the first node that starts after the freed range: `prev` (if
+            // any) is the node immediately before it, `pos` the node
+            // immediately after.
+            while let Some(ptr) = pos {
+                if (*ptr).start > start {
+                    break;
+                }
+                prev = pos;
+                iterator = &mut ((*ptr).next) as Slot;
+                pos = next_addr(iterator);
+            }
+            if let Some(p) = prev {
+                if (*p).start + (*p).size == start {
+                    // Merges into the end of the previous node.
+                    (*p).size += size;
+                    if let Some(next_ptr) = pos {
+                        if (*p).start + (*p).size == (*next_ptr).start {
+                            // The merged node now also touches the next one: fold it in too.
+                            (*p).size += (*next_ptr).size;
+                            let mut placeholder = None;
+                            ::core::mem::swap(&mut placeholder, &mut ((*next_ptr).next));
+                            ::core::mem::swap(&mut placeholder, &mut ((*p).next));
+                            drop(placeholder);
+                        }
                     }
-                }else{
-                    iterator=&mut ((*pos.unwrap()).next) as Slot;
-                    pos=next_addr(iterator);
+                    return;
+                }
+            }
+            if let Some(next_ptr) = pos {
+                if start + size == (*next_ptr).start {
+                    // Merges into the start of the next node.
+                    (*next_ptr).start = start;
+                    (*next_ptr).size += size;
+                    return;
                 }
             }
+            // No adjacent node to merge with: splice in a new one.
+            let mut placeholder = Box::new(Node {
+                start: start,
+                size: size,
+                next: None,
+            });
+            ::core::mem::swap(&mut (placeholder.next), &mut (*iterator));
+            *iterator = Some(placeholder); //releasing a None.
         }
     }
-    */
 }
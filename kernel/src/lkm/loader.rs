@@ -0,0 +1,33 @@
+//! x86_64 ELF relocation type numbers, as assigned by the System V x86_64
+//! psABI, and the subset `manager::relocate_single_symbol` understands.
+//! Named after the raw `R_X86_64_*` constants rather than the
+//! `REL_SYMBOLIC`/`REL_GOT`-style aliases an earlier version of this file
+//! used, since a module's `.rela.plt`/`.rela.dyn` sections carry these exact
+//! numbers and there's no benefit to renaming them on the way in.
+
+/// No relocation - some `.rela.dyn` slots are padding/reserved and carry
+/// this type.
+pub const R_X86_64_NONE: usize = 0;
+/// Absolute 64-bit: write `S + A` at the relocation offset.
+pub const R_X86_64_64: usize = 1;
+/// PC-relative 32-bit: write the low 32 bits of `S + A - P` (`P` is the
+/// relocated address itself), sign-extended back out on read.
+pub const R_X86_64_PC32: usize = 2;
+/// GOT-slot absolute: write `S` (the addend is conventionally 0 for
+/// compiler-emitted `.got` entries).
+pub const R_X86_64_GLOB_DAT: usize = 6;
+/// PLT-slot absolute, normally resolved lazily on first call rather than
+/// eagerly at load: write `S`.
+pub const R_X86_64_JUMP_SLOT: usize = 7;
+/// Load-bias-relative: write `B + A`, where `B` is the module's own load
+/// base - used for position-independent data that doesn't reference any
+/// external symbol at all.
+pub const R_X86_64_RELATIVE: usize = 8;
+/// Module-local TLS: write the offset of the symbol within its module's
+/// static TLS block. This tree has no real per-thread TLS area (no
+/// `arch::x86_64::cpu`/thread-local segment register setup exists yet), so
+/// `relocate_single_symbol` treats the module's own data segment as a
+/// stand-in TLS block - good enough for a module's own `static`s to resolve
+/// to a stable, distinct address, not a faithful per-thread TLS
+/// implementation.
+pub const R_X86_64_TPOFF64: usize = 18;
@@ -2,6 +2,8 @@ use once::*;
 use alloc::prelude::*;
 use alloc::vec::*;
 use alloc::string::*;
+use alloc::sync::Arc;
+use alloc::collections::btree_map::BTreeMap;
 use super::structs::*;
 use super::api::*;
 use super::kernelvm::*;
@@ -9,6 +11,7 @@ use crate::sync::SpinLock as Mutex;
 use lazy_static::lazy_static;
 use xmas_elf::{ElfFile, header, program::{Flags, Type}};
 use core::borrow::BorrowMut;
+use core::sync::atomic::Ordering;
 use crate::consts::*;
 use rcore_memory::{PAGE_SIZE, Page};
 use rcore_memory::memory_set::handler::{MemoryHandler, ByFrame};
@@ -23,19 +26,36 @@ use xmas_elf::symbol_table::DynEntry64;
 use xmas_elf::symbol_table::Entry;
 use crate::syscall::SysResult;
 use core::slice;
-use core::mem::transmute;
 use crate::syscall::SysError::*;
 
 
+/// `delete_module`'s `flags` bit that bypasses the `used_counts`/
+/// `using_counts` busy checks, same spirit as glibc's `O_NONBLOCK`-style
+/// `delete_module(2)` flags on Linux.
+pub const MODULE_UNLOAD_FORCE: u32 = 0b1;
+
 // Module Manager is the core part of LKM.
 // It does these jobs: Load preset(API) symbols; manage module loading dependency and linking modules; managing kseg2 virtual space.
-pub struct ModuleManager<'a>{
+pub struct ModuleManager{
     stub_symbols: Vec<ModuleSymbol>,
-    loaded_modules: Vec<LoadedModule<'a>>
+    loaded_modules: Vec<Arc<LoadedModule>>,
+    /// Raw images of modules not yet loaded, keyed by `ModuleInfo::name`.
+    /// Populated by whatever brings a module image into the kernel (e.g. an
+    /// initramfs autoloader); consulted by `resolve_dependency` when a
+    /// module being loaded depends on one that isn't loaded yet.
+    available_images: BTreeMap<String, Vec<u8>>,
+    /// Names of providers whose exported symbols the module currently being
+    /// relocated has actually pulled a relocation from, collected by
+    /// `find_symbol_in_deps` as it resolves each undefined symbol and
+    /// drained into the new `LoadedModule::dep_edges` once relocation
+    /// finishes. This is scratch state, valid only while `init_module_internal`
+    /// is on the stack - `ModuleManager` is only ever touched through the
+    /// single global lock, so there's no concurrent load to clobber it.
+    current_load_providers: Vec<String>,
 }
 
 lazy_static!{
-    static ref LKM_MANAGER: Mutex<Option<ModuleManager<'static> >>=Mutex::new(None);
+    static ref LKM_MANAGER: Mutex<Option<ModuleManager>>=Mutex::new(None);
 }
 
 macro_rules! export_stub{
@@ -54,18 +74,88 @@ unsafe fn write_to_addr(base: usize, offset: usize, val:usize){
         *(addr as *mut usize)=val;
     }
 }
-impl<'a> ModuleManager<'a>{
+unsafe fn write_to_addr32(base: usize, offset: usize, val: u32){
+    unsafe {
+        let addr=base+offset;
+        *(addr as *mut u32)=val;
+    }
+}
+
+/// Copies `s` into a freshly leaked, NUL-terminated `'static` buffer and
+/// returns its address - used to give a lazy PLT stub (`write_plt_stub`) a
+/// symbol name that outlives the module image bytes `s` was borrowed from.
+/// Leaked rather than freed on unload, same as the stub pool it's paired
+/// with isn't reclaimed either - acceptable for a module loader that isn't
+/// expected to load/unload the same module thousands of times.
+fn leak_cstr(s: &str) -> usize {
+    let mut buf: Vec<u8> = Vec::with_capacity(s.len() + 1);
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+    Box::leak(buf.into_boxed_slice()).as_ptr() as usize
+}
+
+/// Size in bytes of one generated PLT lazy-binding trampoline - see
+/// `ModuleManager::reloc_plt_lazy`'s doc comment for what it does.
+const PLT_STUB_SIZE: usize = 34;
+
+/// Hand-encodes one `PLT_STUB_SIZE`-byte trampoline at `stub_addr`:
+/// `movabs $name_ptr, %rdi; movabs $got_slot, %rsi; movabs
+/// $lkm_plt_lazy_resolve, %rax; call *%rax; jmp *%rax`.
+/// `lkm_plt_lazy_resolve` patches `*got_slot` and returns the resolved
+/// address in `%rax` per the System V calling convention, which the
+/// trailing `jmp *%rax` then dispatches to - so the very first call
+/// through a lazily-bound PLT slot both resolves and dispatches, and every
+/// call after that goes straight to the now-patched slot without ever
+/// reaching this trampoline again.
+fn write_plt_stub(stub_addr: usize, name_ptr: usize, got_slot: usize) {
+    let stub = unsafe { slice::from_raw_parts_mut(stub_addr as *mut u8, PLT_STUB_SIZE) };
+    stub[0] = 0x48; stub[1] = 0xbf; // movabs $imm64, %rdi
+    stub[2..10].copy_from_slice(&(name_ptr as u64).to_le_bytes());
+    stub[10] = 0x48; stub[11] = 0xbe; // movabs $imm64, %rsi
+    stub[12..20].copy_from_slice(&(got_slot as u64).to_le_bytes());
+    stub[20] = 0x48; stub[21] = 0xb8; // movabs $imm64, %rax
+    stub[22..30].copy_from_slice(&(lkm_plt_lazy_resolve as usize as u64).to_le_bytes());
+    stub[30] = 0xff; stub[31] = 0xd0; // call *%rax
+    stub[32] = 0xff; stub[33] = 0xe0; // jmp *%rax
+}
+
+/// Called by a module's lazily-bound PLT trampoline (`write_plt_stub`) the
+/// first time that slot is reached: looks `name_ptr` up among the stub
+/// symbols and every loaded module's exports, same as any other undefined
+/// symbol, patches the resolved address straight into `*got_slot` so every
+/// later call through this slot skips the trampoline entirely, and hands
+/// the resolved address back in the return value for the trampoline's own
+/// `jmp *%rax` to dispatch to.
+#[no_mangle]
+pub extern "C" fn lkm_plt_lazy_resolve(name_ptr: *const u8, got_slot: usize) -> usize {
+    let name = unsafe { super::api::cstr_to_str(name_ptr, 256) };
+    let resolved = ModuleManager::with(|kmm| kmm.resolve_lazy_plt_symbol(&name, got_slot)).unwrap_or(0);
+    if resolved == 0 {
+        error!("[LKM] lazy PLT resolve failed for symbol {}", name);
+    } else {
+        unsafe { *(got_slot as *mut usize) = resolved; }
+    }
+    resolved
+}
+impl ModuleManager{
 
     fn create_stub_symbol(symbol_name: &str, symbol_loc: usize)->ModuleSymbol{
         ModuleSymbol{name: String::from(symbol_name), loc: symbol_loc}
     }
     fn init_stub_symbols()->Vec<ModuleSymbol>{
         vec! [
-            export_stub!(lkm_api_pong)
+            export_stub!(lkm_api_pong),
+            export_stub!(lkm_api_get_param)
 
         ]
     }
-    fn find_symbol_in_deps(&self, symbol:&str)->Option<usize>{
+    /// Resolve an undefined symbol against the stub API first, then every
+    /// loaded module's exports. A hit on a loaded module's export counts as
+    /// that module being actually depended on: bump its `used_counts` and
+    /// record the edge in `current_load_providers` so the module currently
+    /// being relocated can carry it forward into its own `dep_edges` once
+    /// loading finishes, for `delete_module` to unwind later.
+    fn find_symbol_in_deps(&mut self, symbol:&str)->Option<usize>{
         for sym in self.stub_symbols.iter(){
             if (&sym.name)==symbol{
                 return Some(sym.loc);
@@ -75,13 +165,90 @@ impl<'a> ModuleManager<'a>{
         for km in self.loaded_modules.iter().rev(){
             for sym in km.exported_symbols.iter(){
                 if (&sym.name)==symbol {
+                    km.used_counts.fetch_add(1, Ordering::SeqCst);
+                    self.current_load_providers.push(km.info.name.clone());
                     return Some(sym.loc);
                 }
             }
         }
         None
     }
-    fn get_symbol_loc(&self, symbol_index: usize, elf: &ElfFile, dynsym: &[DynEntry64], base:usize, find_dependency: bool)->Option<usize>{
+    /// `lkm_plt_lazy_resolve`'s own symbol lookup: same stub-then-loaded-modules
+    /// search as `find_symbol_in_deps`, but recording the dependency edge
+    /// straight onto the *dependent* module's `dep_edges` instead of
+    /// `current_load_providers` - by the time a lazy PLT slot is actually hit,
+    /// the module that owns it already finished loading (and `dep_edges` was
+    /// already folded in from `current_load_providers`), possibly long ago, so
+    /// there's no "module currently being loaded" for `current_load_providers`
+    /// to mean anything for. `got_slot` is the address `write_plt_stub` baked
+    /// into this trampoline - it lands inside the dependent module's own
+    /// mapped range, so `faultguard::module_at` recovers which module this
+    /// resolution is actually for.
+    fn resolve_lazy_plt_symbol(&mut self, symbol: &str, got_slot: usize)->Option<usize>{
+        for sym in self.stub_symbols.iter(){
+            if (&sym.name)==symbol{
+                return Some(sym.loc);
+            }
+        }
+        for km in self.loaded_modules.iter().rev(){
+            for sym in km.exported_symbols.iter(){
+                if (&sym.name)==symbol {
+                    km.used_counts.fetch_add(1, Ordering::SeqCst);
+                    if let Some(dependent_name) = super::faultguard::module_at(got_slot){
+                        if let Some(dependent) = self.find_loaded(&dependent_name){
+                            dependent.dep_edges.lock().push(km.info.name.clone());
+                        }
+                    }
+                    return Some(sym.loc);
+                }
+            }
+        }
+        None
+    }
+    fn find_loaded(&self, name: &str)->Option<Arc<LoadedModule>>{
+        self.loaded_modules.iter().find(|km| km.info.name==name).cloned()
+    }
+    /// Register a module image so a later `init_module` that depends on it
+    /// can find and load it automatically, instead of failing with `ENOENT`.
+    /// Intended for whatever brings module images into the kernel in bulk
+    /// (e.g. an initramfs scan); not used by `sys_init_module` itself.
+    pub fn register_module_image(&mut self, name: &str, image: Vec<u8>){
+        self.available_images.insert(String::from(name), image);
+    }
+    /// Make sure `dep` is loaded and API-compatible, loading it from
+    /// `available_images` first if necessary. Doesn't itself touch
+    /// `used_counts` - that's bumped per actual symbol reference by
+    /// `find_symbol_in_deps` once relocation runs, not just because a
+    /// `dependence` line named it. `loading` is the chain of module names
+    /// already being loaded on this call stack, so a cyclic dependency is
+    /// reported as `ELOOP` instead of recursing forever.
+    fn resolve_dependency(&mut self, dep: &ModuleDependence, loading: &[String])->SysResult{
+        if let Some(loaded) = self.find_loaded(&dep.name){
+            if loaded.info.api_version<dep.api_version{
+                error!("[LKM] dependency {} api_version mismatch: need {}, have {}", dep.name, dep.api_version, loaded.info.api_version);
+                return Err(ENOEXEC);
+            }
+            return Ok(0);
+        }
+        if loading.iter().any(|n| n==&dep.name){
+            error!("[LKM] circular dependency on {}!", dep.name);
+            return Err(ELOOP);
+        }
+        let image = self.available_images.remove(&dep.name).ok_or_else(||{
+            error!("[LKM] dependency {} not found!", dep.name);
+            ENOENT
+        })?;
+        let mut next_loading: Vec<String> = Vec::from(loading);
+        next_loading.push(dep.name.clone());
+        self.init_module_internal(&image, "", &next_loading)?;
+        let loaded = self.find_loaded(&dep.name).expect("[LKM] dependency vanished right after loading it");
+        if loaded.info.api_version<dep.api_version{
+            error!("[LKM] dependency {} api_version mismatch: need {}, have {}", dep.name, dep.api_version, loaded.info.api_version);
+            return Err(ENOEXEC);
+        }
+        Ok(0)
+    }
+    fn get_symbol_loc(&mut self, symbol_index: usize, elf: &ElfFile, dynsym: &[DynEntry64], base:usize, find_dependency: bool)->Option<usize>{
         let selected_symbol=&dynsym[symbol_index];
         if selected_symbol.shndx()==0 {
             if find_dependency {
@@ -93,7 +260,10 @@ impl<'a> ModuleManager<'a>{
             Some (base+(selected_symbol.value() as usize))
         }
     }
-    pub fn init_module(&mut self, module_image: &[u8], param_values: &str)->SysResult{
+    /// Load `module_image`. `loading` is the chain of module names already
+    /// being loaded by an enclosing `resolve_dependency` call, used purely
+    /// for cycle detection - top-level callers pass `&[]`.
+    fn init_module_internal(&mut self, module_image: &[u8], param_values: &str, loading: &[String])->SysResult{
         let elf=ElfFile::new(module_image).expect("[LKM] failed to read elf");
         let is32 = match elf.header.pt2 {
             header::HeaderPt2::Header32(_) => true,
@@ -130,6 +300,14 @@ impl<'a> ModuleManager<'a>{
             })?;
             println!("[LKM] loading module {} version {} api_version {}", minfo.name, minfo.version, minfo.api_version);
 
+            if self.find_loaded(&minfo.name).is_some(){
+                error!("[LKM] module {} is already loaded!", minfo.name);
+                return Err(EEXIST);
+            }
+            for dep in minfo.dependent_modules.iter(){
+                self.resolve_dependency(dep, loading)?;
+            }
+
             let mut max_addr:usize;
             let mut min_addr: usize;
             let mut off_start: usize;
@@ -160,6 +338,7 @@ impl<'a> ModuleManager<'a>{
                 ENOMEM
             })?;
             let base=vspace.start();
+            super::faultguard::register_module_range(&minfo.name, base, map_len);
 
 
             //loaded_minfo.mem_start=base as usize;
@@ -182,7 +361,16 @@ impl<'a> ModuleManager<'a>{
                         let mut attr = MemoryAttr::default();
                         if flags.is_write() { attr = attr.writable(); }
                         if flags.is_execute() { attr = attr.execute(); }
-                        let area_ref = vspace_ref.add_area(prog_start_addr, prog_end_addr, &attr);
+                        // Demand-paged: a module image can reserve many MiB
+                        // it barely touches (bss, debug sections padded into
+                        // PT_LOAD), so don't `ByFrame`-map the whole thing up
+                        // front. The writes below fault each page in as they
+                        // go, same as any other first access would.
+                        // `add_lazy_area` keeps `GUARD_PAGES` unmapped at each
+                        // end of the range it's given, so the range is widened
+                        // by that much on both sides to leave the whole segment usable.
+                        let guard = GUARD_PAGES * PAGE_SIZE;
+                        let area_ref = vspace_ref.add_lazy_area(prog_start_addr - guard, prog_end_addr + guard, &attr);
                         //self.vallocator.map_pages(prog_start_addr, prog_end_addr, &attr);
                         //No need to flush TLB.
                         let target = unsafe { ::core::slice::from_raw_parts_mut(prog_start_addr as *mut u8, ph.mem_size() as usize) };
@@ -195,12 +383,7 @@ impl<'a> ModuleManager<'a>{
                     }
                 }
             }
-            let mut loaded_minfo=LoadedModule{
-                info: minfo,
-                exported_symbols: Vec::new(),
-                used_counts:0,
-                vspace: vspace
-            };
+            let mut loaded_minfo=LoadedModule::new(minfo, Vec::new(), vspace, parse_params(param_values));
             println!("[LKM] module load done at {}, now need to do the relocation job.", base);
             // We only search two tables for relocation info: the symbols from itself, and the symbols from the global exported symbols.
             let dynsym_table={
@@ -236,9 +419,11 @@ impl<'a> ModuleManager<'a>{
                     }
                 }
                 println!("[LKM] relocating three sections");
-                self.reloc_symbols(&elf, reloc_jmprel, base, dynsym_table);
-                self.reloc_symbols(&elf, reloc_rel, base,dynsym_table);
-                self.reloc_symbols(&elf, reloc_rela, base,dynsym_table);
+                self.current_load_providers.clear();
+                loaded_minfo.plt_stub_space=self.reloc_plt_lazy(&elf, reloc_jmprel, base, dynsym_table)?;
+                self.reloc_symbols(&elf, reloc_rel, base,dynsym_table)?;
+                self.reloc_symbols(&elf, reloc_rela, base,dynsym_table)?;
+                *loaded_minfo.dep_edges.lock()=self.current_load_providers.drain(..).collect();
                 println!("[LKM] relocation done. adding module to manager and call init_module");
                 let mut lkm_entry:usize=0;
                 for exported in loaded_minfo.info.exported_symbols.iter(){
@@ -252,16 +437,31 @@ impl<'a> ModuleManager<'a>{
                             if exported=="init_module"{
                                 lkm_entry=base+(sym.value() as usize);
                             }
+                            if exported=="cleanup_module"{
+                                loaded_minfo.cleanup_entry=base+(sym.value() as usize);
+                            }
                         }
                     }
                 }
                 if lkm_entry>0 {
                     println!("[LKM] calling init_module at {}", lkm_entry);
-                    unsafe{
-                        let init_module:fn()=transmute(lkm_entry);
-                        (init_module)();
+                    super::api::set_current_params(loaded_minfo.params.clone());
+                    let call_result = super::faultguard::guarded_call(&loaded_minfo.info.name, lkm_entry);
+                    super::api::clear_current_params();
+                    if call_result.is_err() {
+                        error!("[LKM] module {} faulted inside init_module, aborting load", loaded_minfo.info.name);
+                        // `loaded_minfo` never made it into `self.loaded_modules`,
+                        // so nobody else can have bumped its own `used_counts` -
+                        // only undo what loading *it* did to its own providers.
+                        for provider in loaded_minfo.dep_edges.lock().iter(){
+                            if let Some(loaded) = self.find_loaded(provider){
+                                loaded.used_counts.fetch_sub(1, Ordering::SeqCst);
+                            }
+                        }
+                        // Dropping `loaded_minfo` tears its `VirtualSpace` down.
+                        return Err(ENOEXEC);
                     }
-
+                    self.loaded_modules.push(Arc::new(loaded_minfo));
 
                 }else {
                     error!("[LKM] this module does not have init_module()!");
@@ -279,31 +479,156 @@ impl<'a> ModuleManager<'a>{
 
     }
 
-    fn relocate_single_symbol(&mut self, base: usize, reloc_addr: usize, addend: usize, sti: usize, itype: usize, elf: &ElfFile, dynsym: &[DynEntry64]){
-        let sym_val=self.get_symbol_loc(sti, elf, dynsym, base, true).expect("[LKM] resolve symbol failed!");
-        match itype as usize{
-            loader::REL_NONE=>{}
-            loader::REL_OFFSET32=>{
-                panic!("[LKM] REL_OFFSET32 detected!")
-                //    addend-=reloc_addr;
+    pub fn init_module(&mut self, module_image: &[u8], param_values: &str)->SysResult{
+        self.init_module_internal(module_image, param_values, &[])
+    }
+
+    fn peek_module_info(module_image: &[u8])->Option<ModuleInfo>{
+        let elf=ElfFile::new(module_image).ok()?;
+        let lkm_info=elf.find_section_by_name(".rcore-lkm")?;
+        if let Undefined(info_content)=lkm_info.get_data(&elf).ok()?{
+            ModuleInfo::parse(core::str::from_utf8(info_content).ok()?)
+        }else{
+            None
+        }
+    }
+
+    /// Load every `.ko` entry in a "newc" cpio `archive` (as handed to the
+    /// kernel as an initramfs), in dependency order, so that by the time a
+    /// dependent module is loaded, `find_symbol_in_deps` can already see
+    /// its dependencies' exported symbols. Order is derived by repeatedly
+    /// loading whatever's left whose declared `dependence` modules are
+    /// already loaded, same idea as a Kahn's-algorithm topological sort;
+    /// any module whose dependencies never become satisfied (missing or
+    /// circular) is reported and left unloaded rather than blocking the rest.
+    pub fn load_initramfs(&mut self, archive: &[u8])->SysResult{
+        let mut pending: Vec<(String, &[u8], Vec<String>)>=Vec::new();
+        for (name, data) in super::initramfs::entries(archive){
+            if !name.ends_with(".ko"){
+                continue;
+            }
+            match Self::peek_module_info(data){
+                Some(info)=>{
+                    let deps=info.dependent_modules.iter().map(|d| d.name.clone()).collect();
+                    pending.push((info.name, data, deps));
+                }
+                None=>{
+                    error!("[LKM] initramfs entry {} has no rcore-lkm metadata, skipping", name);
+                }
+            }
+        }
+        loop{
+            let mut progressed=false;
+            let mut i=0;
+            while i<pending.len(){
+                let ready=pending[i].2.iter().all(|dep| self.find_loaded(dep).is_some());
+                if ready{
+                    let (name, data, _)=pending.remove(i);
+                    if self.init_module(data, "").is_err(){
+                        error!("[LKM] failed to autoload module {} from initramfs", name);
+                    }
+                    progressed=true;
+                }else{
+                    i+=1;
+                }
+            }
+            if pending.is_empty() || !progressed{
+                break;
+            }
+        }
+        for (name, _, _) in pending.iter(){
+            error!("[LKM] module {} from initramfs has unresolved or circular dependencies, not loaded", name);
+        }
+        Ok(0)
+    }
+
+    /// Applies one relocation entry per the x86_64 psABI's `R_X86_64_*`
+    /// semantics (`loader`'s constants). Returns `Err(ENOENT)` rather than
+    /// panicking when the referenced symbol can't be resolved against any
+    /// loaded module, so a module with a genuinely-missing dependency fails
+    /// its own load cleanly instead of taking the kernel down with it.
+    fn relocate_single_symbol(&mut self, base: usize, reloc_addr: usize, addend: usize, sti: usize, itype: usize, elf: &ElfFile, dynsym: &[DynEntry64])->SysResult{
+        match itype{
+            loader::R_X86_64_NONE=>{}
+            loader::R_X86_64_64=>{
+                let sym_val=self.get_symbol_loc(sti, elf, dynsym, base, true).ok_or(ENOENT)?;
+                unsafe {write_to_addr(base, reloc_addr, sym_val.wrapping_add(addend));}
+            }
+            loader::R_X86_64_PC32=>{
+                let sym_val=self.get_symbol_loc(sti, elf, dynsym, base, true).ok_or(ENOENT)?;
+                let p=base+reloc_addr;
+                let value=sym_val.wrapping_add(addend).wrapping_sub(p) as u32;
+                unsafe {write_to_addr32(base, reloc_addr, value);}
+            }
+            loader::R_X86_64_GLOB_DAT=>{
+                let sym_val=self.get_symbol_loc(sti, elf, dynsym, base, true).ok_or(ENOENT)?;
+                unsafe {write_to_addr(base, reloc_addr, sym_val);}
+            }
+            loader::R_X86_64_JUMP_SLOT=>{
+                // `.rela.plt` entries go through `reloc_plt_lazy` instead -
+                // if one somehow also turned up in `.rela.dyn`/`.rel.dyn`,
+                // resolving it eagerly here is still the correct fallback.
+                let sym_val=self.get_symbol_loc(sti, elf, dynsym, base, true).ok_or(ENOENT)?;
+                unsafe {write_to_addr(base, reloc_addr, sym_val);}
             }
-            loader::REL_SYMBOLIC=>{
-                unsafe {write_to_addr(base, reloc_addr, sym_val+addend);}
+            loader::R_X86_64_RELATIVE=>{
+                unsafe {write_to_addr(base, reloc_addr, base.wrapping_add(addend));}
             }
-            loader::REL_GOT=>{
-                unsafe {write_to_addr(base, reloc_addr, sym_val+addend);}
+            loader::R_X86_64_TPOFF64=>{
+                let sym_val=self.get_symbol_loc(sti, elf, dynsym, base, true).ok_or(ENOENT)?;
+                unsafe {write_to_addr(base, reloc_addr, sym_val.wrapping_add(addend));}
             }
-            loader::REL_PLT=>{
-                unsafe {write_to_addr(base, reloc_addr, sym_val+addend);}
+            _=>{
+                error!("[LKM] unsupported relocation type: {}", itype);
+                return Err(ENOEXEC);
             }
-            loader::REL_RELATIVE=>{
-                unsafe {write_to_addr(base, reloc_addr, base+addend);}
+        }
+        Ok(0)
+    }
+    /// Builds one lazy-binding trampoline per `.rela.plt` entry in a small
+    /// scratch `VirtualSpace` of their own, and points each GOT slot at its
+    /// trampoline instead of resolving the symbol up front - so a module
+    /// loads successfully even when a PLT-called symbol is only provided by
+    /// a module that hasn't loaded yet, as long as it's loaded by the time
+    /// the call actually happens. Returns the stub pool (`None` if this
+    /// module has no `.rela.plt`) for the caller to store on
+    /// `LoadedModule::plt_stub_space`.
+    fn reloc_plt_lazy(&mut self, elf: &ElfFile, (start, total_size, single_size):(usize, usize, usize), base: usize, dynsym: &[DynEntry64])->Result<Option<VirtualSpace>, crate::syscall::SysError>{
+        if total_size==0 {return Ok(None);}
+        let count=total_size/single_size;
+        let pool_size=(count*PLT_STUB_SIZE+PAGE_SIZE-1)&neg(PAGE_SIZE);
+        let mut pool=VirtualSpace::new(&KERNELVM_MANAGER, pool_size).ok_or(ENOMEM)?;
+        let pool_base=pool.start();
+        let attr=MemoryAttr::default().writable().execute();
+        pool.add_area(pool_base, pool_base+pool_size, &attr);
+        for s in elf.section_iter(){
+            if (s.offset() as usize)==start{
+                match s.get_data(elf).map_err(|_|{error!("[LKM] corrupted .rela.plt!"); ENOEXEC})?{
+                    SectionData::Rela64(rela_items)=>{
+                        for (i, item) in rela_items.iter().enumerate(){
+                            let reloc_addr=item.get_offset() as usize;
+                            let sti=item.get_symbol_table_index() as usize;
+                            let name=dynsym[sti].get_name(elf).map_err(|_|{error!("[LKM] load PLT symbol name error!"); ENOEXEC})?;
+                            let name_ptr=leak_cstr(name);
+                            let got_slot=base+reloc_addr;
+                            let stub_addr=pool_base+i*PLT_STUB_SIZE;
+                            write_plt_stub(stub_addr, name_ptr, got_slot);
+                            unsafe {write_to_addr(base, reloc_addr, stub_addr);}
+                        }
+                    }
+                    _=>{
+                        error!("[LKM] bad .rela.plt section type!");
+                        return Err(ENOEXEC);
+                    }
+                }
+                return Ok(Some(pool));
             }
-            _=>{panic!("[LKM] unsupported relocation type: {}", itype);}
         }
+        error!("[LKM] .rela.plt section not found at the offset .dynamic pointed to!");
+        Err(ENOEXEC)
     }
-    fn reloc_symbols(&mut self, elf: &ElfFile, (start, total_size, single_size):(usize, usize, usize), base: usize, dynsym: &[DynEntry64]){
-        if total_size==0 {return;}
+    fn reloc_symbols(&mut self, elf: &ElfFile, (start, total_size, single_size):(usize, usize, usize), base: usize, dynsym: &[DynEntry64])->SysResult{
+        if total_size==0 {return Ok(0);}
         for s in elf.section_iter(){
             if (s.offset() as usize)==start{
                 {
@@ -315,7 +640,7 @@ impl<'a> ModuleManager<'a>{
                                 let mut reloc_addr=item.get_offset() as usize;
                                 let sti=item.get_symbol_table_index() as usize;
                                 let itype=item.get_type() as usize;
-                                self.relocate_single_symbol(base, reloc_addr, addend, sti, itype, elf, dynsym);
+                                self.relocate_single_symbol(base, reloc_addr, addend, sti, itype, elf, dynsym)?;
                             }
                         }
                         SectionData::Rel64(rel_items)=>{
@@ -324,7 +649,7 @@ impl<'a> ModuleManager<'a>{
                                 let mut reloc_addr=item.get_offset() as usize;
                                 let sti=item.get_symbol_table_index() as usize;
                                 let itype=item.get_type() as usize;
-                                self.relocate_single_symbol(base, reloc_addr, addend, sti, itype, elf, dynsym);
+                                self.relocate_single_symbol(base, reloc_addr, addend, sti, itype, elf, dynsym)?;
                             }
                         }
                         _=>{panic!("[LKM] bad relocation section type!");}
@@ -335,9 +660,60 @@ impl<'a> ModuleManager<'a>{
                 break;
             }
         }
+        Ok(0)
     }
-    pub fn delete_module(&mut self, name: &str, flags:u32){
-        unimplemented!("[LKM] You can't plug out what's INSIDE you, RIGHT?");
+    /// Unload the module named `name`, refusing if anything still depends on
+    /// or holds a reference to it. Walks `Ready` -> `PrepareUnload` ->
+    /// `Unloading` so a concurrent `resolve_dependency`/`ModuleRef::new`
+    /// that raced the check sees the module already committed to going away
+    /// rather than silently reusing it. `MODULE_UNLOAD_FORCE` in `flags`
+    /// skips the `used_counts`/`using_counts` busy checks, for prying out a
+    /// module that's wedged during development.
+    pub fn delete_module(&mut self, name: &str, flags:u32)->SysResult{
+        let force = flags & MODULE_UNLOAD_FORCE != 0;
+        let index = self.loaded_modules.iter().position(|km| km.info.name==name).ok_or(ENOENT)?;
+        let module = self.loaded_modules[index].clone();
+
+        {
+            let mut state = module.state.lock();
+            if *state != ModuleState::Ready {
+                return Err(EAGAIN);
+            }
+            if !force {
+                if module.used_counts.load(Ordering::SeqCst) > 0 {
+                    error!("[LKM] module {} is still depended on by other modules!", name);
+                    return Err(EBUSY);
+                }
+                if module.using_counts.load(Ordering::SeqCst) > 0 {
+                    error!("[LKM] module {} is still in use!", name);
+                    return Err(EBUSY);
+                }
+            }
+            *state = ModuleState::PrepareUnload;
+        }
+        *module.state.lock() = ModuleState::Unloading;
+
+        if module.cleanup_entry>0{
+            println!("[LKM] calling cleanup_module at {}", module.cleanup_entry);
+            if super::faultguard::guarded_call(name, module.cleanup_entry).is_err(){
+                // It's being removed either way - a module whose own
+                // `cleanup_module` can't even run cleanly doesn't get to
+                // block its own unload.
+                error!("[LKM] module {} faulted in cleanup_module, unloading anyway", name);
+            }
+        }
+
+        for provider in module.dep_edges.lock().iter(){
+            if let Some(loaded) = self.find_loaded(provider){
+                loaded.used_counts.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+        super::faultguard::unregister_module_range(name);
+        self.loaded_modules.remove(index);
+        // `module`'s own Arc, plus the one we just removed from
+        // `loaded_modules`, are the last two owners once this returns;
+        // dropping them tears down its `VirtualSpace` via its own `Drop`.
+        Ok(0)
     }
     pub fn with<T>(f: impl FnOnce(&mut ModuleManager)->T)->T{
         let global_lkmm: &Mutex<Option<ModuleManager>>=&LKM_MANAGER;
@@ -350,7 +726,9 @@ impl<'a> ModuleManager<'a>{
         println!("[LKM] Loadable Kernel Module Manager loading...");
         let mut kmm=ModuleManager{
             stub_symbols: ModuleManager::init_stub_symbols(),
-            loaded_modules:Vec::new()
+            loaded_modules:Vec::new(),
+            available_images: BTreeMap::new(),
+            current_load_providers: Vec::new(),
 
         };
 
@@ -364,8 +742,13 @@ impl<'a> ModuleManager<'a>{
 
 pub fn sys_init_module(module_image:*const u8, len: usize, param_values: *const u8)->SysResult{
     let modimg=unsafe {slice::from_raw_parts(module_image, len)};
+    let param_values=if param_values.is_null(){
+        String::new()
+    }else{
+        unsafe{super::api::cstr_to_str(param_values, 4096)}
+    };
 
     ModuleManager::with(|kmm| {
-       kmm.init_module(modimg, "")
+       kmm.init_module(modimg, &param_values)
     })
 }
\ No newline at end of file
@@ -0,0 +1,505 @@
+//! Userspace "scheme" device backend.
+//!
+//! `CDevManager`/`CharDev` normally dispatch device I/O to an in-kernel
+//! `FileOperations` impl. `SchemeFileOperations` is instead a thin stub: it
+//! packages every call into a `SchemeRequest`, pushes it onto the
+//! registering process's `SchemeServer` queue, and blocks the calling
+//! thread on a `Condvar` until that process answers with a matching
+//! `SchemeReply`. The server process drains requests and posts replies
+//! through `sys_scheme_read_request`/`sys_scheme_write_reply` (see
+//! `syscall::fs`), so a userspace driver backs a device major number the
+//! same way a FUSE daemon backs a mountpoint, without loading a kernel
+//! module.
+//!
+//! There's no per-open state to track: every call is addressed by the
+//! `FileHandle`'s own address, stable for the lifetime of the open, so
+//! `open()` mints no handle id and `close()` has none to free; neither is
+//! forwarded to the server process.
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::fs::{FileHandle, SeekFrom};
+use crate::rcore_fs::vfs::{FsError, Metadata, PollStatus, Result, Timespec};
+use crate::sync::Condvar;
+use crate::sync::SpinNoIrqLock as Mutex;
+use spin::RwLock;
+
+use super::cdev::{CDevManager, CharDev, FileOperations};
+
+/// One operation forwarded to the server process, addressed to the
+/// `FileHandle` it was called on.
+#[derive(Debug, Clone)]
+pub enum SchemeOp {
+    Read { len: usize },
+    ReadAt { offset: usize, len: usize },
+    Write { data: Vec<u8> },
+    WriteAt { offset: usize, data: Vec<u8> },
+    Seek(SeekFrom),
+    SetLen(u64),
+    SyncAll,
+    SyncData,
+    Metadata,
+    ReadEntry,
+    Poll,
+    IoControl { cmd: u32, arg: usize },
+    Close,
+}
+
+pub struct SchemeRequest {
+    pub id: u64,
+    pub handle: usize,
+    pub op: SchemeOp,
+}
+
+/// The server process's answer to a `SchemeRequest`: either the call's
+/// result, serialized per-opcode the same way the request's own payload
+/// is, or the error it failed with.
+pub enum SchemeReply {
+    Ok(Vec<u8>),
+    Err(FsError),
+}
+
+#[derive(Default)]
+struct SchemeState {
+    queue: VecDeque<SchemeRequest>,
+    replies: BTreeMap<u64, SchemeReply>,
+}
+
+/// Shared between every `SchemeFileOperations` call forwarded to this
+/// scheme and the server fd the registering process reads requests from /
+/// writes replies to.
+pub struct SchemeServer {
+    state: Mutex<SchemeState>,
+    request_posted: Condvar,
+    reply_posted: Condvar,
+    next_id: AtomicU64,
+}
+
+impl SchemeServer {
+    pub fn new() -> Arc<SchemeServer> {
+        Arc::new(SchemeServer {
+            state: Mutex::new(SchemeState::default()),
+            request_posted: Condvar::new(),
+            reply_posted: Condvar::new(),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Enqueue `op` addressed to `handle` and block until the server
+    /// process has answered it.
+    fn call(&self, handle: usize, op: SchemeOp) -> SchemeReply {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut state = self.state.lock();
+        state.queue.push_back(SchemeRequest { id, handle, op });
+        self.request_posted.notify_one();
+        loop {
+            if let Some(reply) = state.replies.remove(&id) {
+                return reply;
+            }
+            state = self.reply_posted.wait(state);
+        }
+    }
+
+    /// Block until a request is available, then hand it to the server
+    /// process (`sys_scheme_read_request`) to decode and act on.
+    pub fn next_request(&self) -> SchemeRequest {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(req) = state.queue.pop_front() {
+                return req;
+            }
+            state = self.request_posted.wait(state);
+        }
+    }
+
+    /// Post the server process's answer (`sys_scheme_write_reply`),
+    /// waking whichever thread is blocked on it in `call`.
+    pub fn reply(&self, id: u64, reply: SchemeReply) {
+        self.state.lock().replies.insert(id, reply);
+        self.reply_posted.notify_all();
+    }
+}
+
+fn decode_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    let n = bytes.len().min(8);
+    buf[..n].copy_from_slice(&bytes[..n]);
+    u64::from_le_bytes(buf)
+}
+
+/// Wire format for a `Metadata`: every field in declaration order,
+/// fixed-width little-endian, for `SchemeOp::Metadata`'s reply.
+pub fn encode_metadata(m: &Metadata) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(8 * 11 + 1 + 2);
+    buf.extend_from_slice(&(m.dev as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.inode as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.size as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.blk_size as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.blocks as u64).to_le_bytes());
+    buf.extend_from_slice(&m.atime.sec.to_le_bytes());
+    buf.extend_from_slice(&m.atime.nsec.to_le_bytes());
+    buf.extend_from_slice(&m.mtime.sec.to_le_bytes());
+    buf.extend_from_slice(&m.mtime.nsec.to_le_bytes());
+    buf.extend_from_slice(&m.ctime.sec.to_le_bytes());
+    buf.extend_from_slice(&m.ctime.nsec.to_le_bytes());
+    buf.push(file_type_byte(m.type_));
+    buf.extend_from_slice(&m.mode.to_le_bytes());
+    buf.extend_from_slice(&(m.nlinks as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.uid as u64).to_le_bytes());
+    buf.extend_from_slice(&(m.gid as u64).to_le_bytes());
+    buf.extend_from_slice(&m.rdev.to_le_bytes());
+    buf
+}
+
+fn file_type_byte(t: crate::rcore_fs::vfs::FileType) -> u8 {
+    use crate::rcore_fs::vfs::FileType::*;
+    match t {
+        File => 0,
+        Dir => 1,
+        SymLink => 2,
+        CharDevice => 3,
+        BlockDevice => 4,
+        NamedPipe => 5,
+        Socket => 6,
+    }
+}
+
+/// `FsError` has no `Copy`/wire form of its own, so replies encode it as
+/// this variant's position in the enum as declared in `rcore_fs::vfs`.
+pub fn error_byte(e: &FsError) -> u8 {
+    use crate::rcore_fs::vfs::FsError::*;
+    match e {
+        NotSupported => 0,
+        NotFile => 1,
+        IsDir => 2,
+        NotDir => 3,
+        EntryNotFound => 4,
+        EntryExist => 5,
+        NotSameFs => 6,
+        InvalidParam => 7,
+        NoDeviceSpace => 8,
+        DirRemoved => 9,
+        DirNotEmpty => 10,
+        WrongFs => 11,
+        DeviceError => 12,
+        Busy => 13,
+        Again => 14,
+        SymLoop => 15,
+        NoDevice => 16,
+    }
+}
+
+pub fn error_from_byte(b: u8) -> FsError {
+    use crate::rcore_fs::vfs::FsError::*;
+    match b {
+        0 => NotSupported,
+        1 => NotFile,
+        2 => IsDir,
+        3 => NotDir,
+        4 => EntryNotFound,
+        5 => EntryExist,
+        6 => NotSameFs,
+        7 => InvalidParam,
+        8 => NoDeviceSpace,
+        9 => DirRemoved,
+        10 => DirNotEmpty,
+        11 => WrongFs,
+        12 => DeviceError,
+        13 => Busy,
+        14 => Again,
+        15 => SymLoop,
+        _ => NoDevice,
+    }
+}
+
+/// A `FileOperations` impl whose every method forwards to a `SchemeServer`
+/// instead of touching real device state.
+pub struct SchemeFileOperations {
+    server: Arc<SchemeServer>,
+}
+
+impl SchemeFileOperations {
+    pub fn new(server: Arc<SchemeServer>) -> Arc<SchemeFileOperations> {
+        Arc::new(SchemeFileOperations { server })
+    }
+
+    fn handle_of(fh: &FileHandle) -> usize {
+        fh as *const FileHandle as usize
+    }
+
+    /// Forward a call whose reply carries no payload, just success/error.
+    fn ack(&self, handle: usize, op: SchemeOp) -> Result<()> {
+        match self.server.call(handle, op) {
+            SchemeReply::Ok(_) => Ok(()),
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+}
+
+impl FileOperations for SchemeFileOperations {
+    fn open(&self) -> usize {
+        // No per-open state to mint: every call addresses itself by the
+        // `FileHandle`'s own address.
+        0
+    }
+
+    fn read(&self, fh: &mut FileHandle, buf: &mut [u8]) -> Result<usize> {
+        match self.server.call(Self::handle_of(fh), SchemeOp::Read { len: buf.len() }) {
+            SchemeReply::Ok(data) => {
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                Ok(n)
+            }
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+
+    fn read_at(&self, fh: &mut FileHandle, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        match self.server.call(Self::handle_of(fh), SchemeOp::ReadAt { offset, len: buf.len() }) {
+            SchemeReply::Ok(data) => {
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                Ok(n)
+            }
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+
+    fn write(&self, fh: &mut FileHandle, buf: &[u8]) -> Result<usize> {
+        match self.server.call(Self::handle_of(fh), SchemeOp::Write { data: buf.to_vec() }) {
+            SchemeReply::Ok(data) => Ok(decode_u64(&data) as usize),
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+
+    fn write_at(&self, fh: &mut FileHandle, offset: usize, buf: &[u8]) -> Result<usize> {
+        let op = SchemeOp::WriteAt { offset, data: buf.to_vec() };
+        match self.server.call(Self::handle_of(fh), op) {
+            SchemeReply::Ok(data) => Ok(decode_u64(&data) as usize),
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+
+    fn seek(&self, fh: &mut FileHandle, pos: SeekFrom) -> Result<u64> {
+        match self.server.call(Self::handle_of(fh), SchemeOp::Seek(pos)) {
+            SchemeReply::Ok(data) => Ok(decode_u64(&data)),
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+
+    fn set_len(&self, fh: &mut FileHandle, len: u64) -> Result<()> {
+        self.ack(Self::handle_of(fh), SchemeOp::SetLen(len))
+    }
+
+    fn sync_all(&self, fh: &mut FileHandle) -> Result<()> {
+        self.ack(Self::handle_of(fh), SchemeOp::SyncAll)
+    }
+
+    fn sync_data(&self, fh: &mut FileHandle) -> Result<()> {
+        self.ack(Self::handle_of(fh), SchemeOp::SyncData)
+    }
+
+    fn metadata(&self, fh: &FileHandle) -> Result<Metadata> {
+        match self.server.call(Self::handle_of(fh), SchemeOp::Metadata) {
+            SchemeReply::Ok(data) => decode_metadata(&data).ok_or(FsError::InvalidParam),
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+
+    fn read_entry(&self, fh: &mut FileHandle) -> Result<String> {
+        match self.server.call(Self::handle_of(fh), SchemeOp::ReadEntry) {
+            SchemeReply::Ok(data) => String::from_utf8(data).map_err(|_| FsError::InvalidParam),
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+
+    fn poll(&self, fh: &FileHandle) -> Result<PollStatus> {
+        match self.server.call(Self::handle_of(fh), SchemeOp::Poll) {
+            SchemeReply::Ok(data) => Ok(PollStatus {
+                read: data.get(0).map_or(false, |&b| b & 1 != 0),
+                write: data.get(0).map_or(false, |&b| b & 2 != 0),
+                error: data.get(0).map_or(false, |&b| b & 4 != 0),
+            }),
+            SchemeReply::Err(e) => Err(e),
+        }
+    }
+
+    fn io_control(&self, fh: &FileHandle, cmd: u32, arg: usize) -> Result<()> {
+        self.ack(Self::handle_of(fh), SchemeOp::IoControl { cmd, arg })
+    }
+
+    fn close(&self, _data: usize) {
+        // No particular `FileHandle` to address any more by the time
+        // `close` runs (it only gets back whatever `open` returned, which
+        // carries no identity here) - nothing useful to forward.
+    }
+}
+
+fn decode_metadata(data: &[u8]) -> Option<Metadata> {
+    if data.len() < 8 * 11 + 1 + 2 {
+        return None;
+    }
+    let mut p = 0usize;
+    let mut take64 = |p: &mut usize| {
+        let v = decode_u64(&data[*p..*p + 8]);
+        *p += 8;
+        v
+    };
+    let dev = take64(&mut p) as usize;
+    let inode = take64(&mut p) as usize;
+    let size = take64(&mut p) as usize;
+    let blk_size = take64(&mut p) as usize;
+    let blocks = take64(&mut p) as usize;
+    let atime = Timespec {
+        sec: take64(&mut p) as i64,
+        nsec: take64(&mut p) as i32,
+    };
+    let mtime = Timespec {
+        sec: take64(&mut p) as i64,
+        nsec: take64(&mut p) as i32,
+    };
+    let ctime = Timespec {
+        sec: take64(&mut p) as i64,
+        nsec: take64(&mut p) as i32,
+    };
+    let type_ = file_type_from_byte(data[p]);
+    p += 1;
+    let mode = u16::from_le_bytes([data[p], data[p + 1]]);
+    p += 2;
+    let nlinks = take64(&mut p) as usize;
+    let uid = take64(&mut p) as usize;
+    let gid = take64(&mut p) as usize;
+    let rdev = take64(&mut p);
+    Some(Metadata {
+        dev,
+        inode,
+        size,
+        blk_size,
+        blocks,
+        atime,
+        mtime,
+        ctime,
+        type_,
+        mode,
+        nlinks,
+        uid,
+        gid,
+        rdev,
+    })
+}
+
+fn file_type_from_byte(b: u8) -> crate::rcore_fs::vfs::FileType {
+    use crate::rcore_fs::vfs::FileType::*;
+    match b {
+        1 => Dir,
+        2 => SymLink,
+        3 => CharDevice,
+        4 => BlockDevice,
+        5 => NamedPipe,
+        6 => Socket,
+        _ => File,
+    }
+}
+
+fn opcode_byte(op: &SchemeOp) -> u8 {
+    match op {
+        SchemeOp::Read { .. } => 0,
+        SchemeOp::ReadAt { .. } => 1,
+        SchemeOp::Write { .. } => 2,
+        SchemeOp::WriteAt { .. } => 3,
+        SchemeOp::Seek(_) => 4,
+        SchemeOp::SetLen(_) => 5,
+        SchemeOp::SyncAll => 6,
+        SchemeOp::SyncData => 7,
+        SchemeOp::Metadata => 8,
+        SchemeOp::ReadEntry => 9,
+        SchemeOp::Poll => 10,
+        SchemeOp::IoControl { .. } => 11,
+        SchemeOp::Close => 12,
+    }
+}
+
+/// Wire format for a `SchemeRequest`, read back out by
+/// `sys_scheme_read_request`: `[opcode:u8][handle:u64][id:u64][payload]`,
+/// with the payload shaped per-opcode the same way `SchemeOp` is.
+pub fn encode_request(req: &SchemeRequest) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.push(opcode_byte(&req.op));
+    buf.extend_from_slice(&(req.handle as u64).to_le_bytes());
+    buf.extend_from_slice(&req.id.to_le_bytes());
+    match &req.op {
+        SchemeOp::Read { len } => buf.extend_from_slice(&(*len as u64).to_le_bytes()),
+        SchemeOp::ReadAt { offset, len } => {
+            buf.extend_from_slice(&(*offset as u64).to_le_bytes());
+            buf.extend_from_slice(&(*len as u64).to_le_bytes());
+        }
+        SchemeOp::Write { data } => buf.extend_from_slice(data),
+        SchemeOp::WriteAt { offset, data } => {
+            buf.extend_from_slice(&(*offset as u64).to_le_bytes());
+            buf.extend_from_slice(data);
+        }
+        SchemeOp::Seek(pos) => {
+            let (tag, off): (u8, i64) = match pos {
+                SeekFrom::Start(off) => (0, *off as i64),
+                SeekFrom::End(off) => (1, *off),
+                SeekFrom::Current(off) => (2, *off),
+            };
+            buf.push(tag);
+            buf.extend_from_slice(&off.to_le_bytes());
+        }
+        SchemeOp::SetLen(len) => buf.extend_from_slice(&len.to_le_bytes()),
+        SchemeOp::IoControl { cmd, arg } => {
+            buf.extend_from_slice(&cmd.to_le_bytes());
+            buf.extend_from_slice(&(*arg as u64).to_le_bytes());
+        }
+        SchemeOp::SyncAll
+        | SchemeOp::SyncData
+        | SchemeOp::Metadata
+        | SchemeOp::ReadEntry
+        | SchemeOp::Poll
+        | SchemeOp::Close => {}
+    }
+    buf
+}
+
+/// Registry of live scheme servers, keyed by the server fd returned from
+/// `sys_scheme_create`. Kept separate from `CDevManager` (which only knows
+/// about the `SchemeFileOperations` side) so `sys_scheme_read_request`/
+/// `sys_scheme_write_reply` can look a server back up without going
+/// through a device major number.
+#[derive(Default)]
+pub struct SchemeManager {
+    servers: BTreeMap<usize, Arc<SchemeServer>>,
+    next_fd: usize,
+}
+
+pub static mut SCHEME_MANAGER: Option<RwLock<SchemeManager>> = None;
+
+impl SchemeManager {
+    pub fn init() {
+        unsafe {
+            SCHEME_MANAGER = Some(RwLock::new(SchemeManager::default()));
+        }
+    }
+
+    pub fn get() -> &'static RwLock<SchemeManager> {
+        unsafe { SCHEME_MANAGER.as_ref().unwrap() }
+    }
+
+    /// Register a newly-created server and hand back the fd it's known by.
+    pub fn insert(&mut self, server: Arc<SchemeServer>) -> usize {
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        self.servers.insert(fd, server);
+        fd
+    }
+
+    pub fn get_server(&self, fd: usize) -> Option<Arc<SchemeServer>> {
+        self.servers.get(&fd).cloned()
+    }
+}
@@ -1,7 +1,30 @@
 use alloc::vec::*;
 use alloc::string::*;
+use alloc::sync::Arc;
+use alloc::collections::btree_map::BTreeMap;
+use core::sync::atomic::{AtomicI32, Ordering};
 use super::kernelvm::*;
 use crate::sync::SpinLock as Mutex;
+
+/// Parse `param_values` (as passed to `sys_init_module`) into a map of
+/// Linux-module-parameter-style `key=value` pairs, separated by whitespace
+/// and/or commas (`"baud=115200,debug=1"` or `"baud=115200 debug=1"`).
+/// Pairs with no `=` are ignored. Values are stored with a trailing NUL so
+/// `lkm_api_get_param` can hand a module a plain C string.
+pub fn parse_params(param_values: &str) -> BTreeMap<String, String> {
+    let mut params = BTreeMap::new();
+    for pair in param_values.split(|c: char| c == ',' || c.is_whitespace()) {
+        if pair.is_empty() {
+            continue;
+        }
+        if let Some(eq) = pair.find('=') {
+            let mut value = String::from(&pair[eq + 1..]);
+            value.push('\0');
+            params.insert(String::from(&pair[..eq]), value);
+        }
+    }
+    params
+}
 pub struct ModuleSymbol{
     pub name: String,
     pub loc: usize
@@ -73,6 +96,7 @@ impl ModuleInfo{
 
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ModuleState{
     Ready,
     PrepareUnload,
@@ -82,28 +106,88 @@ pub enum ModuleState{
 pub struct LoadedModule{
     pub info: ModuleInfo,
     pub exported_symbols: Vec<ModuleSymbol>,
-    pub used_counts: i32,
-    pub using_counts: i32,
+    /// How many symbol references other loaded modules hold on this one -
+    /// bumped once per resolved symbol by `ModuleManager::find_symbol_in_deps`
+    /// (and by `lkm_plt_lazy_resolve` for a lazily-bound PLT slot), dropped
+    /// by one per `dep_edges` entry naming it when a dependent module unloads.
+    pub used_counts: AtomicI32,
+    /// How many live references (e.g. open device fds via
+    /// `CharDev::parent_module`, one per outstanding `ModuleRef`) are
+    /// currently grabbed on this module.
+    pub using_counts: AtomicI32,
     pub vspace: VirtualSpace,
-    pub lock: Mutex<()>,
-    pub state:ModuleState
+    pub state: Mutex<ModuleState>,
+    /// `key=value` pairs parsed from this load's `param_values` string by
+    /// `parse_params`, queried back by the module itself (while it's the
+    /// currently-loading module) through `lkm_api_get_param`.
+    pub params: BTreeMap<String, String>,
+    /// Name of every module this one actually pulled a relocation from, one
+    /// entry per reference - folded in from `current_load_providers` once
+    /// eager relocation finishes loading this module, and appended to
+    /// directly (hence the `Mutex`: this module is already sitting in an
+    /// `Arc` by then) by `lkm_plt_lazy_resolve` every time one of this
+    /// module's lazy PLT slots gets resolved, however long after load that
+    /// happens. `delete_module` decrements each named provider's
+    /// `used_counts` by one per entry here on unload.
+    pub dep_edges: Mutex<Vec<String>>,
+    /// Address of this module's exported `cleanup_module` symbol, or 0 if
+    /// it didn't export one. Called by `delete_module` before the module's
+    /// `VirtualSpace` is torn down.
+    pub cleanup_entry: usize,
+    /// Scratch `VirtualSpace` holding this module's lazy-PLT-binding
+    /// trampolines (see `manager::reloc_plt_lazy`), if it had any
+    /// `.rela.plt` entries. Kept alongside `vspace` rather than folded into
+    /// it since it's allocated separately (it isn't sized by the module's
+    /// own `PT_LOAD` segments); dropped the same way on unload.
+    pub plt_stub_space: Option<VirtualSpace>,
 }
 
-struct ModuleGuard<'a>(&'a mut LoadedModule);
+impl LoadedModule{
+    pub fn new(info: ModuleInfo, exported_symbols: Vec<ModuleSymbol>, vspace: VirtualSpace, params: BTreeMap<String, String>) -> LoadedModule {
+        LoadedModule{
+            info,
+            exported_symbols,
+            used_counts: AtomicI32::new(0),
+            using_counts: AtomicI32::new(0),
+            vspace,
+            state: Mutex::new(ModuleState::Ready),
+            params,
+            dep_edges: Mutex::new(Vec::new()),
+            cleanup_entry: 0,
+            plt_stub_space: None,
+        }
+    }
+}
 
-impl<'a> Drop for ModuleGuard<'a>{
-    fn drop(&mut self){
-        self.0.lock.lock();
-        self.0.using_counts-=1;
+/// A safe handle onto a loaded module, held for as long as something needs
+/// the module to stay resident - e.g. `CharDev::parent_module` holds one for
+/// the lifetime of every open file backed by that module's device. Taking
+/// one bumps `using_counts`; `ModuleManager::delete_module` refuses to
+/// unload a module while it's nonzero.
+pub struct ModuleRef(pub Arc<LoadedModule>);
+
+impl ModuleRef {
+    /// Takes a reference on `module`, or `None` if it's already on its way
+    /// out (`delete_module` has moved it past `Ready`). Checking `state` and
+    /// bumping `using_counts` happen under the same lock acquisition as
+    /// `delete_module`'s own `Ready -> PrepareUnload` transition, so whichever
+    /// of the two runs first wins outright: a racing unload either commits
+    /// before this call observes the module (and this returns `None`) or
+    /// after (and `delete_module` sees the bumped `using_counts` and backs
+    /// off with `EBUSY`). There's no window where both think they won.
+    pub fn new(module: Arc<LoadedModule>) -> Option<ModuleRef> {
+        let state = module.state.lock();
+        if *state != ModuleState::Ready {
+            return None;
+        }
+        module.using_counts.fetch_add(1, Ordering::SeqCst);
+        drop(state);
+        Some(ModuleRef(module))
     }
 }
-impl LoadedModule{
-    // Grabs a reference to the kernel module.
-    // For example, a file descriptor to a device file controlled by the module is a reference.
-    // This must be called without the lock!
-    fn grab(&mut self)->ModuleGuard{
-        self.lock.lock();
-        self.using_counts+=1;
-        ModuleGuard(self)
+
+impl Drop for ModuleRef {
+    fn drop(&mut self){
+        self.0.using_counts.fetch_sub(1, Ordering::SeqCst);
     }
 }
\ No newline at end of file
@@ -65,6 +65,78 @@ pub trait INode: Any + Sync + Send {
         unimplemented!()
     }
 
+    /// Read the value of extended attribute `name` into `buf`, returning the
+    /// number of bytes written. Namespaced keys (`user.*`, `security.*`,
+    /// `trusted.*`, ...) are accepted as opaque strings; this trait does not
+    /// interpret or enforce the namespace.
+    fn get_xattr(&self, name: &str, buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    /// Set extended attribute `name` to `value`. `flags` is reserved for
+    /// XATTR_CREATE/XATTR_REPLACE-style semantics; implementations that
+    /// don't support them may ignore it.
+    fn set_xattr(&self, name: &str, value: &[u8], flags: u32) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    /// List all extended attribute names as a NUL-separated string into
+    /// `buf`. If `buf` is empty, return the length that would be needed
+    /// without writing anything, mirroring `listxattr(2)`'s size-query mode.
+    fn list_xattr(&self, buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    /// Remove extended attribute `name`.
+    fn remove_xattr(&self, name: &str) -> Result<()> {
+        Err(FsError::NotSupported)
+    }
+
+    /// Fetch the directory entry at `cursor`, returning it together with
+    /// the cursor to pass on the next call, or `None` once the directory
+    /// is exhausted. Cursors are opaque and filesystem-defined - callers
+    /// must round-trip whatever is handed back rather than compute the
+    /// next one themselves, which keeps this safe to call on directory
+    /// formats that aren't a dense `0..size` array (e.g. a linked list)
+    /// and avoids the races a fixed index has when entries are added or
+    /// removed mid-scan.
+    ///
+    /// The default implementation layers over `get_entry` plus a
+    /// `find`+`metadata` round trip, same as the `(0..size)` loops this
+    /// replaces. Filesystems that can resolve inode number and type while
+    /// walking their own on-disk directory format should override this
+    /// directly to skip that round trip.
+    fn readdir(&self, cursor: usize) -> Result<Option<(DirEntryInfo, usize)>> {
+        match self.get_entry(cursor) {
+            Ok(name) => {
+                let meta = self.find(&name)?.metadata()?;
+                Ok(Some((
+                    DirEntryInfo {
+                        name,
+                        inode: meta.inode,
+                        type_: meta.type_,
+                    },
+                    cursor + 1,
+                )))
+            }
+            Err(FsError::EntryNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Copy `len` bytes from `src` at `src_off` into `self` at `dst_off`,
+    /// sharing or cloning underlying blocks instead of bouncing the data
+    /// through a buffer. Returns the number of bytes actually copied, which
+    /// may be less than `len` on a short source.
+    ///
+    /// The default always returns `NotSupported`; callers are expected to
+    /// fall back to a plain read/write loop when they see that error. Only
+    /// meaningful when `src` and `self` belong to the same `FileSystem` -
+    /// this is not a cross-filesystem copy hook.
+    fn copy_range(&self, src: &Arc<INode>, src_off: usize, dst_off: usize, len: usize) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
 }
 
 impl INode {
@@ -76,10 +148,26 @@ impl INode {
         if info.type_ != FileType::Dir {
             return Err(FsError::NotDir);
         }
-        (0..info.size).map(|i| self.get_entry(i)).collect()
+        let mut names = Vec::new();
+        let mut cursor = 0;
+        while let Some((entry, next)) = self.readdir(cursor)? {
+            names.push(entry.name);
+            cursor = next;
+        }
+        Ok(names)
     }
 
 }
+
+/// One entry produced by [`INode::readdir`]: a name plus enough of its
+/// metadata to build a `dirent`-like record without a second `find` +
+/// `metadata` round trip.
+#[derive(Debug)]
+pub struct DirEntryInfo {
+    pub name: String,
+    pub inode: usize,
+    pub type_: FileType,
+}
 #[derive(Debug, Default)]
 pub struct PollStatus {
     pub read: bool,
@@ -185,6 +273,8 @@ pub enum FsError {
     DirNotEmpty,   //E_NOTEMPTY
     WrongFs,       //E_INVAL, when we find the content on disk is wrong when opening the device
     DeviceError,
+    Busy,          //E_BUSY, e.g. mounting over an existing mountpoint
+    Again,         //E_AGAIN, the operation would block
     SymLoop,        //E_LOOP, too many symlink follows.
     NoDevice //E_NXIO
 }